@@ -1,56 +1,208 @@
-use crate::config;
-use crate::{config::Config, keyboard, rom, sound};
+use crate::{
+    audio::{self, Buzzer},
+    capture::{self, Recording},
+    cli::CaptureFormat,
+    config::{Color, Config, TimingConfig},
+    gamepad::Gamepads,
+    gdb::GdbServer,
+    hotkeys::{self, Hotkey},
+    keyboard::Keymap,
+    movie::{Playback, Recorder},
+    phosphor::{self, Phosphor},
+    rom,
+    watch::RomWatcher,
+};
 use libchip8::config as libconfig;
-use libchip8::{Chip8, timers::Timer};
-use pixels::{Pixels, SurfaceTexture};
-use rodio::Player;
-use std::path::Path;
-use std::{sync::Arc, time::Instant};
-use winit::keyboard::PhysicalKey;
+use libchip8::{Chip8, ChipError, MemoryError};
+use pixels::{Pixels, PixelsBuilder, SurfaceTexture, wgpu};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use winit::{
     application::ApplicationHandler,
-    dpi::LogicalSize,
+    dpi::{LogicalSize, PhysicalSize},
     event::{ElementState, KeyEvent, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
-    window::{Window, WindowId},
+    window::{Fullscreen, Window, WindowId},
 };
 
+const TITLE: &str = "CHIP-8 Emulator";
+
 pub struct App<'win> {
     pub config: Config,
     pub chip8: Chip8,
+    /// ROM loaded again on reset.
+    pub rom: Vec<u8>,
+    /// Seed of the machine, reset and reload reuse it so `Cxkk` stays reproducible.
+    pub seed: u64,
     pub window: Option<Arc<Window>>,
     pub pixels: Option<Pixels<'win>>,
-    pub sink: Player,
+    pub buzzer: Buzzer,
+    pub muted: bool,
     pub last_cpu_tick: Instant,
-    pub last_timer_tick: Instant,
+    /// Instructions executed so far.
+    pub cycle: u64,
+    pub recorder: Option<Recorder>,
+    pub playback: Option<Playback>,
+    pub gdb: Option<GdbServer>,
+    pub capture_format: CaptureFormat,
+    /// Display frames captured since recording started.
+    pub recording: Option<Recording>,
+    pub paused: bool,
+    /// Fast-forward key is held.
+    pub fast_forward: bool,
+    pub slow_motion: bool,
+    /// Restarts the machine when the ROM file changes.
+    pub watcher: Option<RomWatcher>,
+    /// Keep the keypad state on reload.
+    pub keep_keys: bool,
+    /// Why the last reload or the reloaded ROM failed, shown in the title.
+    pub load_error: Option<String>,
+    /// A watched ROM stopped on an error, execution resumes after the next reload.
+    pub halted: bool,
+    pub keymap: Keymap,
+    /// Keypad keys held on the keyboard.
+    pub keys_held: [bool; 16],
+    pub gamepads: Option<Gamepads>,
+    pub phosphor: Phosphor,
+}
+
+/// Emulation speed picked with the hotkeys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speed {
+    Normal,
+    Paused,
+    FastForward(u32),
+    SlowMotion(u32),
+}
+
+impl Speed {
+    /// Time between two instructions, `None` while paused.
+    pub fn tick_duration(self, timing: &TimingConfig) -> Option<Duration> {
+        let tick = timing.cpu_tick_duration();
+        match self {
+            Speed::Normal => Some(tick),
+            Speed::Paused => None,
+            Speed::FastForward(multiplier) => Some(tick / multiplier.max(1)),
+            Speed::SlowMotion(divisor) => Some(tick * divisor.max(1)),
+        }
+    }
+
+    /// Window title showing the speed.
+    pub fn title(self) -> String {
+        match self {
+            Speed::Normal => TITLE.to_string(),
+            Speed::Paused => format!("{} - Paused", TITLE),
+            Speed::FastForward(multiplier) => format!("{} - Fast forward x{}", TITLE, multiplier),
+            Speed::SlowMotion(divisor) => format!("{} - Slow motion 1/{}", TITLE, divisor),
+        }
+    }
+}
+
+/// Executes the instruction at `cycle`, timers tick `timer_hz` times every `cpu_hz` cycles.
+///
+/// Timers are driven by the cycle count so runs are deterministic.
+pub fn step(chip8: &mut Chip8, cycle: u64, timing: &TimingConfig) -> Result<(), ChipError> {
+    chip8.tick()?;
+    for _ in 0..timing.timer_ticks_after(cycle) {
+        chip8.timers.tick();
+    }
+
+    Ok(())
+}
+
+/// Writes the color of each Chip8 pixel into `frame` as a block of `pixel_size` pixels.
+fn fill_frame(frame: &mut [u8], pixel_size: (usize, usize), color: impl Fn(usize) -> [u8; 4]) {
+    let (width, height) = pixel_size;
+    let row = libconfig::DISPLAY_WIDTH * width;
+
+    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % row / width, i / row / height);
+        pixel.copy_from_slice(&color(y * libconfig::DISPLAY_WIDTH + x));
+    }
+}
+
+/// Clear color for an sRGB surface, which expects linear values.
+fn linear_color(color: Color) -> wgpu::Color {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    wgpu::Color {
+        r: linear(color.0),
+        g: linear(color.1),
+        b: linear(color.2),
+        a: color.3 as f64 / 255.0,
+    }
 }
 
 impl<'win> App<'win> {
+    /// App running `chip8`, created with `seed`, at normal speed with the optional parts disabled.
+    pub fn new(config: Config, chip8: Chip8, rom: Vec<u8>, seed: u64) -> Self {
+        let phosphor = Phosphor::new(config.display.persistence, config.display.decay);
+        let buzzer = Buzzer::new(audio::open_device(&config.audio));
+
+        Self {
+            config,
+            chip8,
+            rom,
+            seed,
+            window: None,
+            pixels: None,
+            buzzer,
+            muted: false,
+            last_cpu_tick: Instant::now(),
+            cycle: 0,
+            recorder: None,
+            playback: None,
+            gdb: None,
+            capture_format: CaptureFormat::Gif,
+            recording: None,
+            paused: false,
+            fast_forward: false,
+            slow_motion: false,
+            watcher: None,
+            keep_keys: false,
+            load_error: None,
+            halted: false,
+            keymap: Keymap::default(),
+            keys_held: [false; 16],
+            gamepads: None,
+            phosphor,
+        }
+    }
+
+    /// Current speed, pause wins over fast-forward and fast-forward over slow motion.
+    pub fn speed(&self) -> Speed {
+        if self.paused {
+            Speed::Paused
+        } else if self.fast_forward {
+            Speed::FastForward(self.config.timing.fast_forward)
+        } else if self.slow_motion {
+            Speed::SlowMotion(self.config.timing.slow_motion)
+        } else {
+            Speed::Normal
+        }
+    }
+
     /// Renders display.
     fn render(&mut self) {
         // Display pixels on screen
         if let (Some(window), Some(pixels)) = (&self.window, &mut self.pixels) {
             let display = self.chip8.display.dump();
-            let frame = pixels.frame_mut();
-
-            for (i, pixel_on) in display.iter().enumerate() {
-                let color = if *pixel_on {
-                    [
-                        self.config.display.on_color.0,
-                        self.config.display.on_color.1,
-                        self.config.display.on_color.2,
-                        self.config.display.on_color.3,
-                    ]
-                } else {
-                    [
-                        self.config.display.off_color.0,
-                        self.config.display.off_color.1,
-                        self.config.display.off_color.2,
-                        self.config.display.off_color.3,
-                    ]
-                };
-                frame[i * 4..(i + 1) * 4].copy_from_slice(&color);
-            }
+            let (on, off) = (self.config.display.on_color, self.config.display.off_color);
+
+            fill_frame(
+                pixels.frame_mut(),
+                self.config.display.aspect.pixel_size(),
+                |i| phosphor::blend(off, on, self.phosphor.brightness(i, display)),
+            );
             pixels.render().unwrap();
             window.request_redraw();
         }
@@ -60,49 +212,295 @@ impl<'win> App<'win> {
     fn advance(&mut self) {
         let now = Instant::now();
 
-        while now.duration_since(self.last_cpu_tick) >= self.config.timing.cpu_tick_duration() {
-            self.chip8.tick().unwrap();
-            self.last_cpu_tick += self.config.timing.cpu_tick_duration();
+        if let Some(gdb) = &mut self.gdb {
+            gdb.poll(&mut self.chip8);
         }
 
-        while now.duration_since(self.last_timer_tick) >= self.config.timing.timer_tick_duration() {
-            self.chip8.timers.tick();
-            self.last_timer_tick += self.config.timing.timer_tick_duration();
+        if let Some(tick) = self.speed().tick_duration(&self.config.timing) {
+            while now.duration_since(self.last_cpu_tick) >= tick {
+                if !self.execute() {
+                    self.last_cpu_tick = now;
+                    break;
+                }
+                self.last_cpu_tick += tick;
+            }
+        } else {
+            // don't catch up on the paused time after resuming
+            self.last_cpu_tick = now;
         }
 
-        // play sound if the sound timer is not 0
-        if self.chip8.timers.get(Timer::Sound) > 0 {
-            self.sink.play();
-        } else {
-            self.sink.pause();
+        self.update_buzzer();
+    }
+
+    /// Plays the buzzer of the emulated time since the last update, silent when muted, paused
+    /// or fast-forwarding.
+    fn update_buzzer(&mut self) {
+        let audible = !self.muted && matches!(self.speed(), Speed::Normal | Speed::SlowMotion(_));
+        self.buzzer.update(&mut self.chip8, audible);
+    }
+
+    /// Executes one instruction, returns false when playback, gdb or an error stops execution.
+    fn execute(&mut self) -> bool {
+        if self.halted {
+            return false;
+        }
+        if let Some(playback) = &mut self.playback {
+            if playback.is_finished(self.cycle) {
+                return false;
+            }
+            playback.apply(&mut self.chip8, self.cycle);
+        }
+
+        let (cycle, timing) = (self.cycle, &self.config.timing);
+        if let Some(gdb) = &mut self.gdb {
+            if !gdb.execute(&mut self.chip8, |chip8| step(chip8, cycle, timing)) {
+                return false;
+            }
+        } else if let Err(e) = step(&mut self.chip8, cycle, timing) {
+            // keep the window open, a watched ROM runs again once it is saved
+            eprintln!("Execution stopped: {}", e);
+            self.load_error = Some(format!("Execution stopped: {}", e));
+            self.halted = true;
+            self.update_title();
+            return false;
+        }
+        self.cycle += 1;
+
+        if self.frame_ended() {
+            self.phosphor.update(self.chip8.display.dump());
+            if let Some(recording) = &mut self.recording {
+                recording.push(self.chip8.display.dump());
+            }
+        }
+
+        true
+    }
+
+    /// Whether the last instruction ticked the timers, which ends a display frame.
+    fn frame_ended(&self) -> bool {
+        self.cycle > 0 && self.config.timing.timer_ticks_after(self.cycle - 1) > 0
+    }
+
+    /// Loads the ROM into a fresh machine.
+    fn reset(&mut self) {
+        if self.recorder.is_some() || self.playback.is_some() {
+            eprintln!("Reset is not available while recording or replaying a movie");
+            return;
+        }
+
+        if let Err(e) = self.restart(self.rom.clone(), false) {
+            eprintln!("Failed to reset: {}", e);
+        }
+    }
+
+    /// Replaces the machine with a fresh one running `rom`, keeping the old one on error.
+    fn restart(&mut self, rom: Vec<u8>, keep_keys: bool) -> Result<(), MemoryError> {
+        let mut chip8 = Chip8::with_seed(self.seed);
+        chip8.load_rom(&rom)?;
+        if keep_keys {
+            for (key, pressed) in self.chip8.keyboard.dump().iter().enumerate() {
+                chip8.keyboard.set_key(key as u8, *pressed);
+            }
+        }
+
+        self.chip8 = chip8;
+        self.rom = rom;
+        self.cycle = 0;
+        self.halted = false;
+        self.phosphor.clear();
+        self.last_cpu_tick = Instant::now();
+
+        Ok(())
+    }
+
+    /// Restarts with the ROM file if it changed, reporting load errors in the title.
+    fn reload_if_changed(&mut self) {
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        if !watcher.changed() {
+            return;
+        }
+        // a movie only covers one machine from power on
+        if self.recorder.is_some() || self.playback.is_some() {
+            eprintln!("Reload is not available while recording or replaying a movie");
+            return;
+        }
+
+        let path = watcher.path().to_path_buf();
+        let result =
+            rom::read_rom_bytes(&path).and_then(|rom| Ok(self.restart(rom, self.keep_keys)?));
+        match result {
+            Ok(()) => {
+                println!("Reloaded '{}'", path.display());
+                self.load_error = None;
+            }
+            Err(e) => {
+                eprintln!("Reload failed: {}", e);
+                self.load_error = Some(format!("Reload failed: {}", e));
+            }
+        }
+        self.update_title();
+    }
+
+    /// Presses or releases a keypad key, it stays pressed while the keyboard or a gamepad holds it.
+    fn update_key(&mut self, key: u8) {
+        let pressed = self.keys_held[key as usize]
+            || self
+                .gamepads
+                .as_ref()
+                .is_some_and(|gamepads| gamepads.is_held(key));
+        // key repeats and a second device holding the key are not changes
+        if self.chip8.keyboard.dump()[key as usize] == pressed {
+            return;
+        }
+
+        self.chip8.keyboard.set_key(key, pressed);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.cycle, key, pressed);
+        }
+    }
+
+    fn poll_gamepads(&mut self) {
+        let Some(gamepads) = &mut self.gamepads else {
+            return;
+        };
+        let changed = gamepads.poll();
+        // movie input only while replaying
+        if self.playback.is_some() {
+            return;
+        }
+
+        for key in changed {
+            self.update_key(key);
+        }
+    }
+
+    /// Title with the speed and the last reload error.
+    fn title(&self) -> String {
+        let mut title = self.speed().title();
+        if self.muted {
+            title.push_str(" - Muted");
+        }
+        if let Some(error) = &self.load_error {
+            title = format!("{} - {}", title, error);
+        }
+
+        title
+    }
+
+    fn update_title(&self) {
+        if let Some(window) = &self.window {
+            window.set_title(&self.title());
+        }
+    }
+
+    /// Runs an emulator action bound to a hotkey.
+    fn handle_hotkey(&mut self, hotkey: Hotkey, pressed: bool) {
+        match hotkey {
+            // the only hotkey that is held, the others act on press
+            Hotkey::FastForward => self.fast_forward = pressed,
+            _ if !pressed => return,
+            Hotkey::Pause => self.paused = !self.paused,
+            Hotkey::Reset => self.reset(),
+            Hotkey::FrameAdvance => {
+                if self.paused {
+                    while self.execute() && !self.frame_ended() {}
+                }
+            }
+            Hotkey::SlowMotion => self.slow_motion = !self.slow_motion,
+            Hotkey::Mute => self.muted = !self.muted,
+            Hotkey::Fullscreen => {
+                if let Some(window) = &self.window {
+                    let fullscreen = window
+                        .fullscreen()
+                        .is_none()
+                        .then_some(Fullscreen::Borderless(None));
+                    window.set_fullscreen(fullscreen);
+                }
+            }
+            Hotkey::Screenshot => {
+                let path = capture::timestamped_path("screenshot", "png");
+                match capture::save_screenshot(
+                    &path,
+                    self.chip8.display.dump(),
+                    &self.config.display,
+                ) {
+                    Ok(()) => println!("Screenshot saved to '{}'", path.display()),
+                    Err(e) => eprintln!("Failed to save screenshot: {}", e),
+                }
+            }
+            Hotkey::ToggleRecording => {
+                if self.recording.is_some() {
+                    self.stop_recording();
+                } else {
+                    self.recording = Some(Recording::new());
+                    println!("Recording started");
+                }
+            }
+        }
+        self.update_title();
+    }
+
+    /// Saves the recording in progress, if any.
+    fn stop_recording(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+        let extension = match self.capture_format {
+            CaptureFormat::Gif => "gif",
+            CaptureFormat::Apng => "png",
+        };
+        let path = capture::timestamped_path("recording", extension);
+
+        match recording.save(&path, self.capture_format, &self.config.display) {
+            Ok(()) => println!(
+                "Recording of {} frames saved to '{}'",
+                recording.len(),
+                path.display()
+            ),
+            Err(e) => eprintln!("Failed to save recording: {}", e),
         }
     }
 }
 
 impl<'win> ApplicationHandler for App<'win> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let display = &self.config.display;
+        let (pixel_width, pixel_height) = display.aspect.pixel_size();
+        let (buffer_width, buffer_height) = (
+            (libconfig::DISPLAY_WIDTH * pixel_width) as u32,
+            (libconfig::DISPLAY_HEIGHT * pixel_height) as u32,
+        );
+        // `scale` is the width of a Chip8 pixel, the height follows the aspect
+        let width = libconfig::DISPLAY_WIDTH as f64 * display.scale as f64;
+        let height = libconfig::DISPLAY_HEIGHT as f64 * display.scale as f64 * pixel_height as f64
+            / pixel_width as f64;
+
         let window = Arc::new(
             event_loop
                 .create_window(
                     Window::default_attributes()
-                        .with_title("CHIP-8 Emulator")
-                        .with_inner_size(LogicalSize::new(
-                            libconfig::DISPLAY_WIDTH as f64 * self.config.display.scale as f64,
-                            libconfig::DISPLAY_HEIGHT as f64 * self.config.display.scale as f64,
-                        )),
+                        .with_title(self.title())
+                        .with_inner_size(LogicalSize::new(width, height))
+                        // the image is never scaled below one screen pixel per buffer pixel
+                        .with_min_inner_size(PhysicalSize::new(buffer_width, buffer_height))
+                        .with_fullscreen(
+                            display.fullscreen.then_some(Fullscreen::Borderless(None)),
+                        ),
                 )
                 .unwrap(),
         );
 
-        let pixels = Pixels::new(
-            libconfig::DISPLAY_WIDTH as u32,
-            libconfig::DISPLAY_HEIGHT as u32,
-            SurfaceTexture::new(
-                (libconfig::DISPLAY_WIDTH * self.config.display.scale as usize) as u32,
-                (libconfig::DISPLAY_HEIGHT * self.config.display.scale as usize) as u32,
-                window.clone(),
-            ),
+        // pixels scales the buffer by whole numbers and fills the rest with the clear color
+        let size = window.inner_size();
+        let pixels = PixelsBuilder::new(
+            buffer_width,
+            buffer_height,
+            SurfaceTexture::new(size.width, size.height, window.clone()),
         )
+        .clear_color(linear_color(display.border_color))
+        .build()
         .unwrap();
 
         self.window = Some(window);
@@ -112,25 +510,59 @@ impl<'win> ApplicationHandler for App<'win> {
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
         match event {
             WindowEvent::CloseRequested => {
+                self.stop_recording();
                 event_loop.exit();
             }
+            WindowEvent::Resized(size) => {
+                // minimized windows have nothing to draw on
+                if let Some(pixels) = &mut self.pixels
+                    && size.width > 0
+                    && size.height > 0
+                    && let Err(e) = pixels.resize_surface(size.width, size.height)
+                {
+                    eprintln!("Failed to resize: {}", e);
+                }
+            }
             WindowEvent::RedrawRequested => {
+                self.reload_if_changed();
+                self.poll_gamepads();
                 self.advance();
                 self.render();
+
+                if self
+                    .playback
+                    .as_ref()
+                    .is_some_and(|playback| playback.is_finished(self.cycle))
+                    || self.gdb.as_ref().is_some_and(|gdb| gdb.is_killed())
+                {
+                    event_loop.exit();
+                }
             }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
                         physical_key,
                         state,
+                        repeat,
                         ..
                     },
                 ..
             } => {
-                if let Some(chip8_key) = keyboard::map_to_chip8(physical_key) {
-                    self.chip8
-                        .keyboard
-                        .set_key(chip8_key, state == ElementState::Pressed);
+                if let Some(hotkey) = hotkeys::map_to_hotkey(physical_key) {
+                    if !repeat {
+                        self.handle_hotkey(hotkey, state == ElementState::Pressed);
+                    }
+                    return;
+                }
+
+                // movie input only while replaying
+                if self.playback.is_some() {
+                    return;
+                }
+
+                if let Some(chip8_key) = self.keymap.map(physical_key) {
+                    self.keys_held[chip8_key as usize] = state == ElementState::Pressed;
+                    self.update_key(chip8_key);
                 }
             }
             _ => (),
@@ -145,3 +577,47 @@ pub fn set_up_event_loop() -> EventLoop<()> {
 
     event_loop
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Aspect;
+
+    #[test]
+    fn test_app_fill_frame() {
+        let (width, height) = Aspect::Vip.pixel_size();
+        let mut frame =
+            vec![0; libconfig::DISPLAY_WIDTH * width * libconfig::DISPLAY_HEIGHT * height * 4];
+        // second pixel of the second row is on
+        fill_frame(&mut frame, (width, height), |i| {
+            if i == libconfig::DISPLAY_WIDTH + 1 {
+                [255; 4]
+            } else {
+                [0; 4]
+            }
+        });
+
+        let row = libconfig::DISPLAY_WIDTH * width * 4;
+        let lit = |x: usize, y: usize| frame[y * row + x * 4] == 255;
+        assert!((2..4).all(|x| (3..6).all(|y| lit(x, y))));
+        assert!(!lit(1, 3) && !lit(4, 3) && !lit(2, 2) && !lit(2, 6));
+        assert_eq!(6 * 4, frame.iter().filter(|&&c| c == 255).count());
+    }
+    #[test]
+    fn test_app_speed() {
+        let timing = Config::default().timing;
+        let tick = timing.cpu_tick_duration();
+
+        assert_eq!(Some(tick), Speed::Normal.tick_duration(&timing));
+        assert_eq!(None, Speed::Paused.tick_duration(&timing));
+        assert_eq!(Some(tick / 4), Speed::FastForward(4).tick_duration(&timing));
+        assert_eq!(Some(tick * 2), Speed::SlowMotion(2).tick_duration(&timing));
+        assert_eq!(Some(tick), Speed::FastForward(0).tick_duration(&timing));
+
+        assert_eq!("CHIP-8 Emulator", Speed::Normal.title());
+        assert_eq!(
+            "CHIP-8 Emulator - Fast forward x4",
+            Speed::FastForward(4).title()
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Outputs for the buzzer: the audio device, nothing, or a WAV file.
+//!
+//! The machine decides when the buzzer is on, `Chip8::fill_buzzer` places every change on the
+//! sample where its instruction ran. The backends only turn those states into a tone.
+
+use crate::{config::AudioConfig, sound::Tone};
+use libchip8::Chip8;
+use rodio::{ChannelCount, MixerDeviceSink, SampleRate, Source};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Sample rate of the tone played on the audio device.
+const DEVICE_SAMPLE_RATE: u32 = 48_000;
+/// Sample rate of WAV files.
+pub const WAV_SAMPLE_RATE: u32 = 44_100;
+/// Samples queued before the device starts playing, two timer ticks absorb frame jitter.
+const DEVICE_PREBUFFER: usize = 2 * DEVICE_SAMPLE_RATE as usize / 60;
+/// Queued samples past this are dropped, e.g. after fast-forwarding.
+const DEVICE_MAX_QUEUED: usize = 4 * DEVICE_SAMPLE_RATE as usize / 60;
+
+/// Destination of the buzzer sound.
+pub trait AudioBackend {
+    /// Samples per second of the buzzer states passed to `push`, 0 leaves them in the machine.
+    fn sample_rate(&self) -> u32;
+
+    /// Plays the buzzer state of each sample.
+    fn push(&mut self, buzzer: &[bool]);
+
+    /// Ends the output.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Passes the machine's buzzer samples on to a backend.
+pub struct Buzzer {
+    backend: Box<dyn AudioBackend>,
+    buffer: Vec<bool>,
+}
+
+impl Buzzer {
+    pub fn new(backend: Box<dyn AudioBackend>) -> Self {
+        Self {
+            backend,
+            buffer: vec![false; 1024],
+        }
+    }
+
+    /// Hands the samples of the emulated time since the last update to the backend, silent
+    /// unless `audible`.
+    pub fn update(&mut self, chip8: &mut Chip8, audible: bool) {
+        let sample_rate = self.backend.sample_rate();
+        loop {
+            let written = chip8.fill_buzzer(&mut self.buffer, sample_rate);
+            if !audible {
+                self.buffer[..written].fill(false);
+            }
+            self.backend.push(&self.buffer[..written]);
+            if written < self.buffer.len() {
+                return;
+            }
+        }
+    }
+
+    /// Plays the remaining samples and ends the output.
+    pub fn finish(&mut self, chip8: &mut Chip8) -> anyhow::Result<()> {
+        self.update(chip8, true);
+        self.backend.finish()
+    }
+}
+
+/// Discards the sound.
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn sample_rate(&self) -> u32 {
+        0
+    }
+
+    fn push(&mut self, _buzzer: &[bool]) {}
+}
+
+/// Buzzer states waiting for the audio device.
+#[derive(Default)]
+struct DeviceQueue {
+    states: VecDeque<bool>,
+    /// False until `DEVICE_PREBUFFER` samples are queued, again after running dry.
+    playing: bool,
+}
+
+/// Tone read by the audio device thread.
+struct DeviceSource {
+    tone: Tone,
+    queue: Arc<Mutex<DeviceQueue>>,
+}
+
+impl Iterator for DeviceSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let on = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.playing |= queue.states.len() >= DEVICE_PREBUFFER;
+            let on = if queue.playing {
+                queue.states.pop_front()
+            } else {
+                Some(false)
+            };
+            on.unwrap_or_else(|| {
+                queue.playing = false;
+                false
+            })
+        };
+
+        Some(self.tone.sample(on))
+    }
+}
+
+impl Source for DeviceSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        ChannelCount::MIN
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        SampleRate::new(DEVICE_SAMPLE_RATE).expect("sample rate is not zero")
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Plays the tone live on the default audio device.
+pub struct RodioBackend {
+    queue: Arc<Mutex<DeviceQueue>>,
+    // sound stops when the device is closed
+    _device: MixerDeviceSink,
+}
+
+impl RodioBackend {
+    pub fn new(config: &AudioConfig) -> anyhow::Result<Self> {
+        let mut device = rodio::DeviceSinkBuilder::open_default_sink()?;
+        device.log_on_drop(false);
+
+        let queue = Arc::new(Mutex::new(DeviceQueue::default()));
+        device.mixer().add(DeviceSource {
+            tone: Tone::new(config, DEVICE_SAMPLE_RATE),
+            queue: queue.clone(),
+        });
+
+        Ok(Self {
+            queue,
+            _device: device,
+        })
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn sample_rate(&self) -> u32 {
+        DEVICE_SAMPLE_RATE
+    }
+
+    fn push(&mut self, buzzer: &[bool]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.states.extend(buzzer);
+        let excess = queue.states.len().saturating_sub(DEVICE_MAX_QUEUED);
+        queue.states.drain(..excess);
+    }
+}
+
+/// Backend for live runs, silent when audio is disabled or no audio device is available.
+pub fn open_device(config: &AudioConfig) -> Box<dyn AudioBackend> {
+    if !config.enabled {
+        return Box::new(NullBackend);
+    }
+
+    match RodioBackend::new(config) {
+        Ok(backend) => Box::new(backend),
+        Err(e) => {
+            eprintln!("No audio output, running silently: {}", e);
+            Box::new(NullBackend)
+        }
+    }
+}
+
+/// Renders the tone into a mono 16-bit WAV file.
+pub struct WavBackend<W: Write + Seek> {
+    writer: Option<hound::WavWriter<W>>,
+    tone: Tone,
+}
+
+impl WavBackend<BufWriter<File>> {
+    pub fn create(path: &Path, config: &AudioConfig) -> anyhow::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), config)
+    }
+}
+
+impl<W: Write + Seek> WavBackend<W> {
+    pub fn new(writer: W, config: &AudioConfig) -> anyhow::Result<Self> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: WAV_SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        Ok(Self {
+            writer: Some(hound::WavWriter::new(writer, spec)?),
+            tone: Tone::new(config, WAV_SAMPLE_RATE),
+        })
+    }
+}
+
+impl<W: Write + Seek> AudioBackend for WavBackend<W> {
+    fn sample_rate(&self) -> u32 {
+        WAV_SAMPLE_RATE
+    }
+
+    fn push(&mut self, buzzer: &[bool]) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+
+        for &on in buzzer {
+            let sample = self.tone.sample(on);
+            // write errors are reported by `finish`
+            if writer
+                .write_sample((sample * i16::MAX as f32) as i16)
+                .is_err()
+            {
+                self.writer = None;
+                return;
+            }
+        }
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let Some(writer) = self.writer.take() else {
+            anyhow::bail!("Failed to write the WAV file");
+        };
+        writer.finalize()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_audio_wav() {
+        let mut config = Config::default().audio;
+        config.attack_ms = 0.0;
+        config.release_ms = 0.0;
+        let path = std::env::temp_dir().join(format!("chip8-audio-{}.wav", std::process::id()));
+
+        // 0x200: LD V0, 2
+        // 0x202: LD ST, V0
+        // 0x204: JP 0x204
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom(&[0x60, 0x02, 0xf0, 0x18, 0x12, 0x04])
+            .unwrap();
+        let mut buzzer = Buzzer::new(Box::new(WavBackend::create(&path, &config).unwrap()));
+        for _ in 0..5 {
+            chip8.run_frame(10).unwrap();
+            buzzer.update(&mut chip8, true);
+        }
+        buzzer.finish(&mut chip8).unwrap();
+
+        let samples: Vec<i16> = hound::WavReader::open(&path)
+            .unwrap()
+            .into_samples()
+            .map(Result::unwrap)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        // 735 samples per tick, the beep starts early in the first tick and lasts two ticks
+        assert_eq!(5 * 735, samples.len());
+        assert!(samples[..70].iter().all(|&sample| sample == 0));
+        assert!(samples[80..1400].iter().all(|&sample| sample != 0));
+        assert!(samples[1500..].iter().all(|&sample| sample == 0));
+    }
+}
@@ -0,0 +1,299 @@
+//! Core performance measurement for `bench`.
+
+use libchip8::{Chip8, analysis, batch::Backend, recompiler::Recompiler};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+/// How long the benchmark runs.
+#[derive(Clone, Copy, Debug)]
+pub enum Limit {
+    Instructions(u64),
+    Time(Duration),
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpcodeTiming {
+    /// Opcode pattern, e.g. `8xy6`.
+    pub opcode: &'static str,
+    pub count: u64,
+    /// Mean time per instruction with the timing overhead removed.
+    pub mean_ns: f64,
+    /// Share of the total instruction time from 0 to 1.
+    pub share: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub rom: String,
+    pub version: &'static str,
+    /// `interpreter` or `recompiler`.
+    pub backend: &'static str,
+    pub cycles_per_frame: u32,
+    pub instructions: u64,
+    pub frames: u64,
+    pub seconds: f64,
+    pub instructions_per_second: f64,
+    pub frames_per_second: f64,
+    /// Instructions per second relative to the interpreter running as many instructions,
+    /// only measured for the recompiler.
+    pub speedup: Option<f64>,
+    /// Measured in a separate run, sorted by total time.
+    pub opcodes: Vec<OpcodeTiming>,
+}
+
+/// Runs whole frames until the limit is reached, returns the frames and instructions run.
+fn run(
+    chip8: &mut Chip8,
+    backend: Backend,
+    cycles_per_frame: u32,
+    limit: Limit,
+) -> anyhow::Result<(u64, u64)> {
+    let mut recompiler = (backend == Backend::Recompiler).then(Recompiler::new);
+    let start = Instant::now();
+    let (mut frames, mut instructions) = (0, 0);
+
+    loop {
+        let done = match limit {
+            Limit::Instructions(count) => instructions >= count,
+            Limit::Time(duration) => start.elapsed() >= duration,
+        };
+        if done {
+            return Ok((frames, instructions));
+        }
+
+        match &mut recompiler {
+            Some(recompiler) => recompiler.run_frame(chip8, cycles_per_frame),
+            None => chip8.run_frame(cycles_per_frame),
+        }
+        .map_err(|e| anyhow::anyhow!("Execution stopped in frame {}: {}", frames, e))?;
+        frames += 1;
+        instructions += cycles_per_frame as u64;
+    }
+}
+
+/// Cost of taking two timestamps, removed from each instruction's time.
+fn timing_overhead() -> Duration {
+    const SAMPLES: u32 = 10_000;
+
+    let start = Instant::now();
+    for _ in 0..SAMPLES {
+        std::hint::black_box(Instant::now().elapsed());
+    }
+
+    start.elapsed() / SAMPLES
+}
+
+/// Times every instruction of `instructions` run in frames, grouped by opcode.
+fn time_opcodes(
+    chip8: &mut Chip8,
+    cycles_per_frame: u32,
+    instructions: u64,
+) -> anyhow::Result<Vec<OpcodeTiming>> {
+    let overhead = timing_overhead();
+    let mut timings: BTreeMap<&'static str, (u64, Duration)> = BTreeMap::new();
+
+    for cycle in 0..instructions {
+        let pc = chip8.pc() as usize;
+        let word = chip8
+            .read_memory(pc, 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))?;
+
+        let start = Instant::now();
+        chip8.tick()?;
+        let elapsed = start.elapsed().saturating_sub(overhead);
+
+        let opcode = analysis::opcode(word).map_or("unknown", |(pattern, _)| pattern);
+        let timing = timings.entry(opcode).or_default();
+        timing.0 += 1;
+        timing.1 += elapsed;
+
+        if (cycle + 1) % cycles_per_frame as u64 == 0 {
+            chip8.timers.tick();
+        }
+    }
+
+    let total: Duration = timings.values().map(|(_, time)| *time).sum();
+    let mut opcodes: Vec<OpcodeTiming> = timings
+        .into_iter()
+        .map(|(opcode, (count, time))| OpcodeTiming {
+            opcode,
+            count,
+            mean_ns: time.as_nanos() as f64 / count as f64,
+            share: if total.is_zero() {
+                0.0
+            } else {
+                time.as_secs_f64() / total.as_secs_f64()
+            },
+        })
+        .collect();
+    opcodes.sort_by(|a, b| b.share.total_cmp(&a.share).then(a.opcode.cmp(b.opcode)));
+
+    Ok(opcodes)
+}
+
+/// Benchmarks `rom` without pacing, `seed` keeps `Cxkk` results the same between runs.
+pub fn bench(
+    name: &str,
+    rom: &[u8],
+    seed: u64,
+    backend: Backend,
+    cycles_per_frame: u32,
+    limit: Limit,
+) -> anyhow::Result<BenchReport> {
+    let mut chip8 = Chip8::with_seed(seed);
+    chip8.load_rom(rom)?;
+    // timing every instruction slows it down, so it gets its own run
+    let mut timed = chip8.clone();
+    let mut baseline = chip8.clone();
+
+    let start = Instant::now();
+    let (frames, instructions) = run(&mut chip8, backend, cycles_per_frame, limit)?;
+    let seconds = start.elapsed().as_secs_f64();
+
+    let speedup = if backend == Backend::Recompiler {
+        let start = Instant::now();
+        let limit = Limit::Instructions(instructions);
+        run(&mut baseline, Backend::Interpreter, cycles_per_frame, limit)?;
+        Some(start.elapsed().as_secs_f64() / seconds)
+    } else {
+        None
+    };
+
+    let opcodes = time_opcodes(&mut timed, cycles_per_frame, instructions)?;
+
+    Ok(BenchReport {
+        rom: name.to_string(),
+        version: env!("CARGO_PKG_VERSION"),
+        backend: match backend {
+            Backend::Interpreter => "interpreter",
+            Backend::Recompiler => "recompiler",
+        },
+        cycles_per_frame,
+        instructions,
+        frames,
+        seconds,
+        instructions_per_second: instructions as f64 / seconds,
+        frames_per_second: frames as f64 / seconds,
+        speedup,
+        opcodes,
+    })
+}
+
+impl BenchReport {
+    /// Human readable summary with the opcode table.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+
+        writeln!(out, "ROM:                 {}", self.rom).unwrap();
+        writeln!(out, "Backend:             {}", self.backend).unwrap();
+        writeln!(
+            out,
+            "Instructions:        {} in {:.3} s",
+            self.instructions, self.seconds
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "Instructions/second: {:.0}",
+            self.instructions_per_second
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "Frames/second:       {:.0} at {} cycles per frame",
+            self.frames_per_second, self.cycles_per_frame
+        )
+        .unwrap();
+        if let Some(speedup) = self.speedup {
+            writeln!(
+                out,
+                "Speedup:             {:.2}x over the interpreter",
+                speedup
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "\n  {:<10} {:>12} {:>10} {:>7}",
+            "Opcode", "Count", "Mean ns", "Time"
+        )
+        .unwrap();
+        for timing in &self.opcodes {
+            writeln!(
+                out,
+                "  {:<10} {:>12} {:>10.1} {:>6.1}%",
+                timing.opcode,
+                timing.count,
+                timing.mean_ns,
+                timing.share * 100.0
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_report() {
+        // 0x200: LD V0, 1
+        // 0x202: ADD V1, V0
+        // 0x204: JP 0x202
+        let rom = [0x60, 0x01, 0x71, 0x00, 0x12, 0x02];
+        let report = bench(
+            "loop.ch8",
+            &rom,
+            0,
+            Backend::Interpreter,
+            10,
+            Limit::Instructions(95),
+        )
+        .unwrap();
+
+        // whole frames only
+        assert_eq!(100, report.instructions);
+        assert_eq!(10, report.frames);
+        let counts: BTreeMap<&str, u64> = report
+            .opcodes
+            .iter()
+            .map(|timing| (timing.opcode, timing.count))
+            .collect();
+        assert_eq!(
+            BTreeMap::from([("1nnn", 49), ("6xkk", 1), ("7xkk", 50)]),
+            counts
+        );
+
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(100, json["instructions"]);
+        assert_eq!(3, json["opcodes"].as_array().unwrap().len());
+        assert!(report.text().contains("at 10 cycles per frame"));
+        assert!(report.speedup.is_none());
+
+        let limit = Limit::Instructions(10);
+        assert!(bench("bad.ch8", &[0xff, 0xff], 0, Backend::Interpreter, 10, limit).is_err());
+    }
+    #[test]
+    fn test_bench_recompiler_speedup() {
+        let rom = [0x60, 0x01, 0x71, 0x00, 0x12, 0x02];
+        let limit = Limit::Instructions(100);
+        let report = bench("loop.ch8", &rom, 0, Backend::Recompiler, 10, limit).unwrap();
+
+        assert_eq!("recompiler", report.backend);
+        assert_eq!(100, report.instructions);
+        assert!(report.speedup.is_some_and(|speedup| speedup > 0.0));
+        assert!(report.text().contains("over the interpreter"));
+        assert_eq!(
+            "recompiler",
+            serde_json::to_value(&report).unwrap()["backend"]
+        );
+    }
+}
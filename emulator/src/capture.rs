@@ -0,0 +1,270 @@
+//! PNG screenshots and animated GIF/APNG recordings of the display.
+
+use crate::{cli::CaptureFormat, config::DisplayConfig};
+use libchip8::config as libconfig;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Emulated frames per second, one per timer tick.
+const FRAME_RATE: u32 = 60;
+
+fn dimensions(scale: u32) -> (u32, u32) {
+    (
+        libconfig::DISPLAY_WIDTH as u32 * scale,
+        libconfig::DISPLAY_HEIGHT as u32 * scale,
+    )
+}
+
+/// Palette index per pixel (0 off, 1 on), scaled up.
+fn indexed(display: &[bool], scale: u32) -> Vec<u8> {
+    let (width, height) = dimensions(scale);
+    let scale = scale as usize;
+
+    (0..height as usize)
+        .flat_map(|y| {
+            (0..width as usize)
+                .map(move |x| display[(y / scale) * libconfig::DISPLAY_WIDTH + x / scale] as u8)
+        })
+        .collect()
+}
+
+fn palette(config: &DisplayConfig) -> [u8; 6] {
+    let (off, on) = (config.off_color, config.on_color);
+    [off.0, off.1, off.2, on.0, on.1, on.2]
+}
+
+/// Path like `screenshot-1700000000123.png` in the current directory.
+pub fn timestamped_path(prefix: &str, extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+    PathBuf::from(format!("{}-{}.{}", prefix, millis, extension))
+}
+
+/// Picks the recording format from the file extension.
+pub fn format_for_path(path: &Path) -> anyhow::Result<CaptureFormat> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(e) if e.eq_ignore_ascii_case("gif") => Ok(CaptureFormat::Gif),
+        Some(e) if e.eq_ignore_ascii_case("png") || e.eq_ignore_ascii_case("apng") => {
+            Ok(CaptureFormat::Apng)
+        }
+        _ => anyhow::bail!(
+            "Cannot tell recording format of '{}', use .gif, .png or .apng",
+            path.display()
+        ),
+    }
+}
+
+fn png_encoder<W: Write>(writer: W, config: &DisplayConfig) -> png::Encoder<'static, W> {
+    let (width, height) = dimensions(config.scale);
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(palette(config).to_vec());
+    encoder
+}
+
+/// Writes the display as a PNG image.
+pub fn write_screenshot<W: Write>(
+    writer: W,
+    display: &[bool],
+    config: &DisplayConfig,
+) -> anyhow::Result<()> {
+    let mut writer = png_encoder(writer, config).write_header()?;
+    writer.write_image_data(&indexed(display, config.scale))?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Saves the display as a PNG image.
+pub fn save_screenshot(
+    path: &Path,
+    display: &[bool],
+    config: &DisplayConfig,
+) -> anyhow::Result<()> {
+    write_screenshot(BufWriter::new(File::create(path)?), display, config)
+}
+
+/// Display contents captured once per emulated frame.
+#[derive(Default)]
+pub struct Recording {
+    /// Distinct consecutive displays and how many frames each was shown.
+    frames: Vec<(Vec<bool>, u32)>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, display: &[bool]) {
+        match self.frames.last_mut() {
+            Some((last, count)) if last.as_slice() == display => *count += 1,
+            _ => self.frames.push((display.to_vec(), 1)),
+        }
+    }
+
+    /// Number of emulated frames recorded.
+    pub fn len(&self) -> u32 {
+        self.frames.iter().map(|(_, count)| count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn save(
+        &self,
+        path: &Path,
+        format: CaptureFormat,
+        config: &DisplayConfig,
+    ) -> anyhow::Result<()> {
+        self.write(BufWriter::new(File::create(path)?), format, config)
+    }
+
+    pub fn write<W: Write>(
+        &self,
+        writer: W,
+        format: CaptureFormat,
+        config: &DisplayConfig,
+    ) -> anyhow::Result<()> {
+        if self.frames.is_empty() {
+            anyhow::bail!("Recording has no frames");
+        }
+
+        match format {
+            CaptureFormat::Gif => self.write_gif(writer, config),
+            CaptureFormat::Apng => self.write_apng(writer, config),
+        }
+    }
+
+    fn write_gif<W: Write>(&self, writer: W, config: &DisplayConfig) -> anyhow::Result<()> {
+        let (width, height) = dimensions(config.scale);
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &palette(config))?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        // GIF delays are in 1/100 s, so round the running time to keep the 60 Hz pace
+        let mut elapsed_frames = 0;
+        let mut elapsed_cs = 0;
+        for (display, count) in &self.frames {
+            elapsed_frames += count;
+            let end_cs = (elapsed_frames * 100 + FRAME_RATE / 2) / FRAME_RATE;
+            let mut frame = gif::Frame::from_indexed_pixels(
+                width as u16,
+                height as u16,
+                indexed(display, config.scale),
+                None,
+            );
+            frame.delay = (end_cs - elapsed_cs).max(1) as u16;
+            elapsed_cs += frame.delay as u32;
+            encoder.write_frame(&frame)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_apng<W: Write>(&self, writer: W, config: &DisplayConfig) -> anyhow::Result<()> {
+        let mut encoder = png_encoder(writer, config);
+        encoder.set_animated(self.frames.len() as u32, 0)?;
+        let mut writer = encoder.write_header()?;
+
+        for (display, count) in &self.frames {
+            writer.set_frame_delay((*count).min(u16::MAX as u32) as u16, FRAME_RATE as u16)?;
+            writer.write_image_data(&indexed(display, config.scale))?;
+        }
+        writer.finish()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn display(lit: usize) -> Vec<bool> {
+        let mut display = vec![false; libconfig::DISPLAY_WIDTH * libconfig::DISPLAY_HEIGHT];
+        display[lit] = true;
+        display
+    }
+
+    fn recording() -> Recording {
+        let mut recording = Recording::new();
+        for frame in 0..90 {
+            recording.push(&display(frame / 30));
+        }
+        recording
+    }
+
+    fn config() -> DisplayConfig {
+        let mut config = Config::default().display;
+        config.scale = 2;
+        config
+    }
+
+    #[test]
+    fn test_capture_screenshot() {
+        let mut bytes = Vec::new();
+        write_screenshot(&mut bytes, &display(1), &config()).unwrap();
+
+        let mut reader = png::Decoder::new(std::io::Cursor::new(bytes))
+            .read_info()
+            .unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((128, 64), (reader.info().width, reader.info().height));
+        // second pixel of the display is lit, scaled to x 2..4
+        assert_eq!(&[0, 0, 1, 1, 0], &pixels[..5]);
+    }
+    #[test]
+    fn test_capture_gif() {
+        let recording = recording();
+        assert_eq!(90, recording.len());
+        let mut bytes = Vec::new();
+        recording
+            .write(&mut bytes, CaptureFormat::Gif, &config())
+            .unwrap();
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(bytes.as_slice())
+            .unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(vec![50, 50, 50], delays);
+    }
+    #[test]
+    fn test_capture_apng() {
+        let mut bytes = Vec::new();
+        recording()
+            .write(&mut bytes, CaptureFormat::Apng, &config())
+            .unwrap();
+
+        let reader = png::Decoder::new(std::io::Cursor::new(bytes))
+            .read_info()
+            .unwrap();
+        let animation = reader.info().animation_control.unwrap();
+        assert_eq!(3, animation.num_frames);
+        let frame = reader.info().frame_control.unwrap();
+        assert_eq!((30, 60), (frame.delay_num, frame.delay_den));
+    }
+    #[test]
+    fn test_capture_format_for_path() {
+        assert!(matches!(
+            format_for_path(Path::new("a.GIF")),
+            Ok(CaptureFormat::Gif)
+        ));
+        assert!(matches!(
+            format_for_path(Path::new("a.apng")),
+            Ok(CaptureFormat::Apng)
+        ));
+        assert!(format_for_path(Path::new("a.mp4")).is_err());
+    }
+}
@@ -1,7 +1,7 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::{path::PathBuf, time::Duration};
 
-use crate::commands;
+use crate::{bench, commands, config::Color};
 
 #[derive(Parser)]
 #[command(version, about = "Chip-8 emulator", long_about = None)]
@@ -13,15 +13,166 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Execute ROM
-    Run {
+    Run(RunArgs),
+
+    /// Run ROM without a window for a number of frames
+    Headless {
+        /// Path to the ROM file
+        path: PathBuf,
+
+        /// Number of 60 Hz frames to emulate
+        #[arg(long, default_value_t = 600)]
+        frames: u64,
+
+        /// Record every frame into an animated .gif or .png (APNG)
+        #[arg(long)]
+        record: Option<PathBuf>,
+
+        /// Save the final display as a PNG image
+        #[arg(long)]
+        screenshot: Option<PathBuf>,
+
+        /// Render the buzzer into a WAV file
+        #[arg(long)]
+        audio: Option<PathBuf>,
+
+        /// Seed for the random number generator, random when omitted
+        #[arg(long)]
+        seed: Option<u64>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+
+    /// Play back a movie recorded with `run --record`
+    Replay {
+        /// Path to the movie file
+        movie: PathBuf,
+
+        /// Path to the ROM the movie was recorded with
+        rom: PathBuf,
+
+        /// Replay without a window
+        #[arg(long)]
+        headless: bool,
+
+        /// Fail unless the final state matches the recorded one
+        #[arg(long)]
+        verify: bool,
+    },
+
+    /// Execute ROM in the terminal
+    Tui {
+        /// Path to the ROM file
+        path: PathBuf,
+
+        /// Seed for the random number generator, random when omitted
+        #[arg(long)]
+        seed: Option<u64>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+
+        /// How pixels are drawn, detected from the terminal when omitted
+        #[arg(short, long, value_enum)]
+        graphics: Option<Graphics>,
+
+        /// Milliseconds a key stays pressed after the terminal last reported it
+        #[arg(long, default_value_t = 150)]
+        key_timeout: u64,
+    },
+
+    /// Debug ROM in a full-screen terminal UI
+    Debug {
+        /// Path to the ROM file
+        path: PathBuf,
+
+        /// Assembler listing shown next to the disassembly
+        #[arg(short, long)]
+        listing: Option<PathBuf>,
+
+        /// Seed for the random number generator, random when omitted
+        #[arg(long)]
+        seed: Option<u64>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+
+    /// Serve the Debug Adapter Protocol over stdio
+    ///
+    /// The ROM, listing and options come from the client's launch request.
+    Dap,
+
+    /// Run ROM without a window and report code coverage
+    Coverage {
         /// Path to the ROM file
         path: PathBuf,
 
-        /// Configuration file path
+        /// Number of instructions to execute
+        #[arg(long, default_value_t = 1_000_000)]
+        cycles: u64,
+
+        /// Assembler listing used to map addresses to source lines
         #[arg(short, long)]
-        config: Option<PathBuf>,
+        listing: Option<PathBuf>,
+
+        /// Report format
+        #[arg(short, long, value_enum, default_value_t = CoverageFormat::Lcov)]
+        format: CoverageFormat,
+
+        /// Report output path, prints to stdout when omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Seed for the random number generator, random when omitted
+        #[arg(long)]
+        seed: Option<u64>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+
+    /// Run ROM as fast as possible and report its speed
+    Bench {
+        /// Path to the ROM file
+        path: PathBuf,
+
+        /// Number of instructions to execute, rounded up to whole frames
+        #[arg(long, default_value_t = 10_000_000, conflicts_with = "seconds")]
+        instructions: u64,
+
+        /// Run for this many seconds instead of a number of instructions
+        #[arg(long)]
+        seconds: Option<f64>,
+
+        /// Instructions per 60 Hz frame, 11 matches the default 700 Hz
+        #[arg(long, default_value_t = 11, value_parser = clap::value_parser!(u32).range(1..))]
+        cycles_per_frame: u32,
+
+        /// Seed for the random number generator
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// Execution backend, the recompiler also reports its speedup over the interpreter
+        #[arg(long, value_enum, default_value_t = BenchBackend::Interpreter)]
+        backend: BenchBackend,
+
+        /// Report format
+        #[arg(short, long, value_enum, default_value_t = BenchFormat::Text)]
+        format: BenchFormat,
+    },
+
+    /// Report a ROM's size, hash, opcodes and quirk-sensitive instructions
+    Info {
+        /// Path to the ROM file
+        path: PathBuf,
     },
 
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
     /// Generate default configuration file
     GenerateConfig {
         /// Path to store the configuration in.
@@ -29,12 +180,210 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the effective configuration and the layers it came from
+    Dump {
+        /// ROM whose per-ROM overrides are applied
+        rom: Option<PathBuf>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+
+    /// Validate the configuration, listing every invalid field
+    Check {
+        /// ROM whose per-ROM overrides are applied
+        rom: Option<PathBuf>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+}
+
+/// Settings layered over the defaults, the user configuration and per-ROM overrides.
+#[derive(Args, Clone, Default)]
+pub struct ConfigArgs {
+    /// Configuration file path
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Ignore the user configuration and per-ROM overrides
+    #[arg(long)]
+    pub no_user_config: bool,
+
+    /// Instructions executed per second
+    #[arg(long)]
+    pub cpu_hz: Option<u64>,
+
+    /// Initial window width in screen pixels per Chip8 pixel
+    #[arg(long)]
+    pub scale: Option<u32>,
+
+    /// Color of lit pixels as #RRGGBB, #RRGGBBAA or R,G,B[,A]
+    #[arg(long)]
+    pub on_color: Option<Color>,
+
+    /// Color of unlit pixels as #RRGGBB, #RRGGBBAA or R,G,B[,A]
+    #[arg(long)]
+    pub off_color: Option<Color>,
+
+    /// Start in fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Turn sound off
+    #[arg(long)]
+    pub no_audio: bool,
+
+    /// Set any configuration field, e.g. `--set display.decay=0.8`
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BenchBackend {
+    /// Decode every instruction as it runs
+    Interpreter,
+    /// Run cached blocks of decoded instructions
+    Recompiler,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BenchFormat {
+    /// Human readable summary
+    Text,
+    /// JSON for comparing runs
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CoverageFormat {
+    /// lcov tracefile
+    Lcov,
+    /// Disassembly annotated with hit counts
+    Annotated,
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Path to the ROM file
+    pub path: PathBuf,
+
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Record key presses into a movie file
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+
+    /// Seed for the random number generator, random when omitted
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Wait for a GDB remote protocol connection on this localhost port
+    #[arg(long, value_name = "PORT")]
+    pub gdb: Option<u16>,
+
+    /// Format of recordings started with F10
+    #[arg(long, value_enum, default_value_t = CaptureFormat::Gif)]
+    pub capture_format: CaptureFormat,
+
+    /// Restart the machine whenever the ROM file changes
+    #[arg(long, conflicts_with = "record")]
+    pub watch: bool,
+
+    /// Keep held keypad keys pressed across reloads
+    #[arg(long, requires = "watch")]
+    pub keep_keys: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CaptureFormat {
+    /// Animated GIF
+    Gif,
+    /// Animated PNG
+    Apng,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Graphics {
+    /// Two pixels per character cell
+    HalfBlock,
+    /// Eight pixels per character cell
+    Braille,
+    /// Sixel images
+    Sixel,
+    /// Kitty graphics protocol
+    Kitty,
+}
+
 /// Cli entrypoint.
 pub fn run() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Run { path, config } => commands::run_rom(&path, config),
+        Commands::Run(args) => commands::run_rom(args),
+        Commands::Headless {
+            path,
+            frames,
+            record,
+            screenshot,
+            audio,
+            seed,
+            config,
+        } => commands::run_headless(&path, frames, record, screenshot, audio, seed, config),
+        Commands::Replay {
+            movie,
+            rom,
+            headless,
+            verify,
+        } => commands::replay_movie(&movie, &rom, headless, verify),
+        Commands::Tui {
+            path,
+            seed,
+            config,
+            graphics,
+            key_timeout,
+        } => commands::run_rom_in_terminal(&path, seed, config, graphics, key_timeout),
+        Commands::Debug {
+            path,
+            listing,
+            seed,
+            config,
+        } => commands::debug_rom(&path, listing, seed, config),
+        Commands::Dap => commands::serve_dap(),
+        Commands::Coverage {
+            path,
+            cycles,
+            listing,
+            format,
+            output,
+            seed,
+            config,
+        } => commands::coverage_rom(&path, cycles, listing, format, output, seed, config),
+        Commands::Bench {
+            path,
+            instructions,
+            seconds,
+            cycles_per_frame,
+            seed,
+            backend,
+            format,
+        } => {
+            let limit = match seconds {
+                Some(seconds) => bench::Limit::Time(Duration::try_from_secs_f64(seconds)?),
+                None => bench::Limit::Instructions(instructions),
+            };
+            commands::bench_rom(&path, limit, cycles_per_frame, seed, backend, format)
+        }
+        Commands::Info { path } => commands::rom_info(&path),
+        Commands::Config(ConfigCommand::Dump { rom, config }) => {
+            commands::dump_config(rom.as_deref(), &config)
+        }
+        Commands::Config(ConfigCommand::Check { rom, config }) => {
+            commands::check_config(rom.as_deref(), &config)
+        }
         Commands::GenerateConfig { path } => commands::generate_default_config(&path),
     }
 }
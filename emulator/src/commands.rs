@@ -1,42 +1,424 @@
 use crate::{
     app,
-    config::{self, Config},
-    rom, sound,
+    audio::{Buzzer, NullBackend, WavBackend},
+    bench,
+    capture::{self, Recording},
+    cli::{BenchBackend, BenchFormat, ConfigArgs, CoverageFormat, Graphics, RunArgs},
+    config::{self, Config, ConfigBuilder},
+    dap, debug,
+    gamepad::Gamepads,
+    gdb::GdbServer,
+    info,
+    keyboard::Keymap,
+    movie::{self, Movie, Playback, Recorder},
+    rom, tui,
+    watch::RomWatcher,
 };
-use libchip8::Chip8;
+use libchip8::{Chip8, batch::Backend, coverage::Coverage, listing::Listing};
 use std::{
-    fs::File,
-    io::Write,
+    fs::{self, File},
+    io::{self, BufReader, Write},
     path::{Path, PathBuf},
-    time::Instant,
+    time::Duration,
 };
 
+/// Layers the defaults, the user config, `--config`, per-ROM overrides and command line flags.
+fn config_builder(args: &ConfigArgs, rom: Option<(&Path, &[u8])>) -> anyhow::Result<ConfigBuilder> {
+    let mut builder = ConfigBuilder::new();
+
+    let user_dir = if args.no_user_config {
+        None
+    } else {
+        config::user_config_dir()
+    };
+    if let Some(dir) = &user_dir {
+        builder.optional_file(&dir.join("config.toml"))?;
+    }
+    if let Some(path) = &args.config {
+        builder.file(path)?;
+    }
+    if let (Some(dir), Some((rom_path, rom_bytes))) = (&user_dir, rom) {
+        for path in config::rom_config_paths(dir, rom_path, rom_bytes) {
+            builder.optional_file(&path)?;
+        }
+    }
+
+    let mut flags = Vec::new();
+    if let Some(cpu_hz) = args.cpu_hz {
+        flags.push(format!("timing.cpu_hz = {}", cpu_hz));
+    }
+    if let Some(scale) = args.scale {
+        flags.push(format!("display.scale = {}", scale));
+    }
+    if let Some(color) = args.on_color {
+        flags.push(format!(
+            "display.on_color = {}",
+            toml::Value::try_from(color)?
+        ));
+    }
+    if let Some(color) = args.off_color {
+        flags.push(format!(
+            "display.off_color = {}",
+            toml::Value::try_from(color)?
+        ));
+    }
+    if args.fullscreen {
+        flags.push("display.fullscreen = true".to_string());
+    }
+    if args.no_audio {
+        flags.push("audio.enabled = false".to_string());
+    }
+    if !flags.is_empty() {
+        builder.layer(toml::from_str(&flags.join("\n"))?, "command line flags")?;
+    }
+    for assignment in &args.overrides {
+        builder.layer(
+            config::parse_assignment(assignment)?,
+            &format!("--set {}", assignment),
+        )?;
+    }
+
+    Ok(builder)
+}
+
+/// Effective configuration for the ROM at `rom_path`.
+fn load_config(args: &ConfigArgs, rom_path: &Path, rom_bytes: &[u8]) -> anyhow::Result<Config> {
+    config_builder(args, Some((rom_path, rom_bytes)))?.build()
+}
+
 /// Runs the ROM.
-pub fn run_rom(rom_path: &Path, config_path: Option<PathBuf>) -> anyhow::Result<()> {
-    let config = if let Some(config_path) = config_path {
-        config::load_config(config_path)?
+pub fn run_rom(args: RunArgs) -> anyhow::Result<()> {
+    let rom_bytes = rom::read_rom_bytes(&args.path)?;
+    let config = load_config(&args.config, &args.path, &rom_bytes)?;
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut chip8 = Chip8::with_seed(seed);
+    chip8.load_rom(&rom_bytes)?;
+
+    let recorder = args
+        .record
+        .is_some()
+        .then(|| Recorder::new(seed, &rom_bytes, config.clone()));
+
+    let watcher = args
+        .watch
+        .then(|| RomWatcher::new(&args.path))
+        .transpose()?;
+
+    let keymap = Keymap::new(config.keymap_for(&args.path))?;
+
+    // playing with the keyboard still works without gamepad support
+    let gamepads = if config.gamepad.enabled {
+        Gamepads::new(&config.gamepad)
+            .inspect_err(|e| eprintln!("{}", e))
+            .ok()
+    } else {
+        None
+    };
+
+    let gdb = args.gdb.map(GdbServer::accept).transpose()?;
+
+    let mut app = run_app(config, chip8, rom_bytes, seed, |app| {
+        app.recorder = recorder;
+        app.gdb = gdb;
+        app.keymap = keymap;
+        app.gamepads = gamepads;
+        app.watcher = watcher;
+        app.keep_keys = args.keep_keys;
+        app.capture_format = args.capture_format;
+    });
+
+    if let (Some(path), Some(recorder)) = (args.record, app.recorder.take()) {
+        recorder.finish(&app.chip8, app.cycle).save(&path)?;
+        println!("Movie saved to '{}'", path.display());
+    }
+
+    Ok(())
+}
+
+/// Replays a movie, optionally checking the final state.
+pub fn replay_movie(
+    movie_path: &Path,
+    rom_path: &Path,
+    headless: bool,
+    verify: bool,
+) -> anyhow::Result<()> {
+    let movie = Movie::load(movie_path)?;
+    let rom_bytes = rom::read_rom_bytes(rom_path)?;
+
+    let (chip8, cycle) = if headless {
+        (movie::replay(&movie, &rom_bytes)?, movie.cycles)
     } else {
-        config::Config::default()
+        let chip8 = movie.start(&rom_bytes)?;
+        let app = run_app(movie.config.clone(), chip8, rom_bytes, movie.seed, |app| {
+            app.playback = Some(Playback::new(movie.clone()));
+        });
+        (app.chip8, app.cycle)
     };
 
+    if verify {
+        Playback::new(movie).verify(&chip8, cycle)?;
+        println!("Replay matches the recording");
+    } else {
+        println!(
+            "Replayed {} cycles, state hash {:016x}",
+            cycle,
+            movie::state_hash(&chip8)
+        );
+    }
+
+    Ok(())
+}
+
+/// Opens the window and runs the machine until it is closed.
+///
+/// `setup` enables optional parts of the app before the window opens.
+fn run_app<'win>(
+    config: Config,
+    chip8: Chip8,
+    rom: Vec<u8>,
+    seed: u64,
+    setup: impl FnOnce(&mut app::App<'win>),
+) -> app::App<'win> {
+    let mut app = app::App::new(config, chip8, rom, seed);
+    setup(&mut app);
+    app::set_up_event_loop().run_app(&mut app).unwrap();
+
+    app
+}
+
+/// Runs the ROM without a window, optionally capturing the display.
+pub fn run_headless(
+    rom_path: &Path,
+    frames: u64,
+    record_path: Option<PathBuf>,
+    screenshot_path: Option<PathBuf>,
+    audio_path: Option<PathBuf>,
+    seed: Option<u64>,
+    config_args: ConfigArgs,
+) -> anyhow::Result<()> {
     let rom_bytes = rom::read_rom_bytes(rom_path)?;
+    let config = load_config(&config_args, rom_path, &rom_bytes)?;
+    // fail before emulating if the recording cannot be written
+    let record_format = record_path
+        .as_deref()
+        .map(capture::format_for_path)
+        .transpose()?;
 
-    let mut chip8 = Chip8::new();
+    let mut chip8 = Chip8::with_seed(seed.unwrap_or_else(rand::random));
     chip8.load_rom(&rom_bytes)?;
 
-    // set up audio sink
-    let (sink, _out) = sound::create_beep_sink();
+    let mut buzzer = Buzzer::new(match &audio_path {
+        Some(path) => Box::new(WavBackend::create(path, &config.audio)?),
+        None => Box::new(NullBackend),
+    });
+
+    let mut recording = Recording::new();
+    let mut cycle = 0;
+    for frame in 0..frames {
+        // a frame ends with a timer tick
+        loop {
+            if let Err(e) = app::step(&mut chip8, cycle, &config.timing) {
+                // the audio up to the error is still a valid WAV file
+                buzzer.finish(&mut chip8)?;
+                anyhow::bail!("Execution stopped in frame {}: {}", frame, e);
+            }
+            cycle += 1;
+            if config.timing.timer_ticks_after(cycle - 1) > 0 {
+                break;
+            }
+        }
+        buzzer.update(&mut chip8, true);
+        if record_format.is_some() {
+            recording.push(chip8.display.dump());
+        }
+    }
+
+    if let (Some(path), Some(format)) = (record_path, record_format) {
+        recording.save(&path, format, &config.display)?;
+        println!("Recording saved to '{}'", path.display());
+    }
+    buzzer.finish(&mut chip8)?;
+    if let Some(path) = audio_path {
+        println!("Audio saved to '{}'", path.display());
+    }
+    if let Some(path) = screenshot_path {
+        capture::save_screenshot(&path, chip8.display.dump(), &config.display)?;
+        println!("Screenshot saved to '{}'", path.display());
+    }
+    println!("Ran {} frames ({} instructions)", frames, cycle);
+
+    Ok(())
+}
+
+/// Runs the ROM in the terminal.
+pub fn run_rom_in_terminal(
+    rom_path: &Path,
+    seed: Option<u64>,
+    config_args: ConfigArgs,
+    graphics: Option<Graphics>,
+    key_timeout_ms: u64,
+) -> anyhow::Result<()> {
+    let rom_bytes = rom::read_rom_bytes(rom_path)?;
+    let config = load_config(&config_args, rom_path, &rom_bytes)?;
+
+    let mut chip8 = Chip8::with_seed(seed.unwrap_or_else(rand::random));
+    chip8.load_rom(&rom_bytes)?;
 
-    let mut app = app::App {
-        config,
+    tui::run(
         chip8,
-        pixels: None,
-        window: None,
-        last_cpu_tick: Instant::now(),
-        last_timer_tick: Instant::now(),
-        sink,
+        &config,
+        &Keymap::new(config.keymap_for(rom_path))?,
+        graphics.unwrap_or_else(tui::detect_graphics),
+        Duration::from_millis(key_timeout_ms),
+    )
+}
+
+/// Opens the terminal debugger.
+pub fn debug_rom(
+    rom_path: &Path,
+    listing_path: Option<PathBuf>,
+    seed: Option<u64>,
+    config_args: ConfigArgs,
+) -> anyhow::Result<()> {
+    let rom_bytes = rom::read_rom_bytes(rom_path)?;
+    let config = load_config(&config_args, rom_path, &rom_bytes)?;
+    let listing = match &listing_path {
+        Some(path) => Some(Listing::parse(&fs::read_to_string(path)?)),
+        None => None,
     };
-    app::set_up_event_loop().run_app(&mut app).unwrap();
+
+    let mut chip8 = Chip8::with_seed(seed.unwrap_or_else(rand::random));
+    chip8.load_rom(&rom_bytes)?;
+
+    debug::run(chip8, config, listing)
+}
+
+/// Runs a debug adapter session on stdin and stdout.
+pub fn serve_dap() -> anyhow::Result<()> {
+    dap::serve(BufReader::new(io::stdin()), io::stdout())
+}
+
+/// Runs the ROM headlessly and writes a coverage report.
+pub fn coverage_rom(
+    rom_path: &Path,
+    cycles: u64,
+    listing_path: Option<PathBuf>,
+    format: CoverageFormat,
+    output: Option<PathBuf>,
+    seed: Option<u64>,
+    config_args: ConfigArgs,
+) -> anyhow::Result<()> {
+    let rom_bytes = rom::read_rom_bytes(rom_path)?;
+    let config = load_config(&config_args, rom_path, &rom_bytes)?;
+    let listing = match &listing_path {
+        Some(path) => Some(Listing::parse(&fs::read_to_string(path)?)),
+        None => None,
+    };
+
+    let mut chip8 = Chip8::with_seed(seed.unwrap_or_else(rand::random));
+    chip8.load_rom(&rom_bytes)?;
+
+    // timers run at their usual rate relative to the CPU
+    let mut coverage = Coverage::new();
+    for cycle in 0..cycles {
+        if let Err(e) = coverage.tick(&mut chip8) {
+            eprintln!("Execution stopped after {} instructions: {}", cycle, e);
+            break;
+        }
+        for _ in 0..config.timing.timer_ticks_after(cycle) {
+            chip8.timers.tick();
+        }
+    }
+
+    let report = match format {
+        CoverageFormat::Lcov => {
+            let source = listing_path.as_deref().unwrap_or(rom_path);
+            coverage.lcov(&rom_bytes, &source.display().to_string(), listing.as_ref())
+        }
+        CoverageFormat::Annotated => coverage.annotated_disassembly(&rom_bytes, listing.as_ref()),
+    };
+
+    match output {
+        Some(path) => fs::write(path, report)?,
+        None => print!("{}", report),
+    }
+
+    Ok(())
+}
+
+/// Runs the ROM unpaced and prints its speed.
+pub fn bench_rom(
+    rom_path: &Path,
+    limit: bench::Limit,
+    cycles_per_frame: u32,
+    seed: u64,
+    backend: BenchBackend,
+    format: BenchFormat,
+) -> anyhow::Result<()> {
+    let rom_bytes = rom::read_rom_bytes(rom_path)?;
+    let backend = match backend {
+        BenchBackend::Interpreter => Backend::Interpreter,
+        BenchBackend::Recompiler => Backend::Recompiler,
+    };
+    let report = bench::bench(
+        &rom_path.display().to_string(),
+        &rom_bytes,
+        seed,
+        backend,
+        cycles_per_frame,
+        limit,
+    )?;
+
+    match format {
+        BenchFormat::Text => print!("{}", report.text()),
+        BenchFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+
+    Ok(())
+}
+
+/// Prints what the ROM contains and which platform it was likely written for.
+pub fn rom_info(rom_path: &Path) -> anyhow::Result<()> {
+    let rom_bytes = rom::read_rom_bytes(rom_path)?;
+    print!(
+        "{}",
+        info::report(&rom_path.display().to_string(), &rom_bytes)
+    );
+
+    Ok(())
+}
+
+/// Layers the configuration for `config` subcommands, with the ROM's overrides if given.
+fn inspect_config(rom_path: Option<&Path>, args: &ConfigArgs) -> anyhow::Result<ConfigBuilder> {
+    let rom_bytes = rom_path.map(rom::read_rom_bytes).transpose()?;
+
+    config_builder(args, rom_path.zip(rom_bytes.as_deref()))
+}
+
+/// Prints the effective configuration, preceded by the layers it was built from.
+pub fn dump_config(rom_path: Option<&Path>, args: &ConfigArgs) -> anyhow::Result<()> {
+    let builder = inspect_config(rom_path, args)?;
+    let config = builder.build()?;
+
+    println!("# Layers, later ones win:");
+    for source in builder.sources() {
+        println!("# - {}", source);
+    }
+    print!("{}", toml::to_string_pretty(&config)?);
+
+    Ok(())
+}
+
+/// Validates the configuration, failing with every invalid field.
+pub fn check_config(rom_path: Option<&Path>, args: &ConfigArgs) -> anyhow::Result<()> {
+    let builder = inspect_config(rom_path, args)?;
+    builder.build()?;
+
+    println!("Configuration is valid, layers checked:");
+    for source in builder.sources() {
+        println!("  {}", source);
+    }
 
     Ok(())
 }
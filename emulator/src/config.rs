@@ -1,25 +1,213 @@
-use serde::{Deserialize, Serialize};
-use std::{fs, path::Path, time::Duration};
+use crate::{gamepad, keyboard::Keymap, rom};
+use serde::{Deserialize, Deserializer, Serialize, de};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
 pub struct Color(pub u8, pub u8, pub u8, pub u8);
 
-#[derive(Serialize, Deserialize)]
+impl Color {
+    pub fn rgba(self) -> [u8; 4] {
+        [self.0, self.1, self.2, self.3]
+    }
+
+    fn from_channels(channels: &[u8]) -> Option<Self> {
+        match *channels {
+            [r, g, b] => Some(Color(r, g, b, 255)),
+            [r, g, b, a] => Some(Color(r, g, b, a)),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `#RRGGBB`, `#RRGGBBAA` or `R,G,B[,A]`.
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let channels = match s.strip_prefix('#') {
+            Some(hex) if hex.is_ascii() && hex.len() % 2 == 0 => (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                .collect(),
+            Some(_) => None,
+            None => s
+                .split(',')
+                .map(|channel| channel.trim().parse().ok())
+                .collect(),
+        };
+
+        channels
+            .and_then(|channels: Vec<u8>| Color::from_channels(&channels))
+            .ok_or_else(|| {
+                format!(
+                    "invalid color '{}', expected #RRGGBB, #RRGGBBAA or R,G,B[,A]",
+                    s
+                )
+            })
+    }
+}
+
+/// Accepts the strings `FromStr` does as well as `[R, G, B]` and `[R, G, B, A]` arrays.
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ColorVisitor;
+
+        impl<'de> de::Visitor<'de> for ColorVisitor {
+            type Value = Color;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a \"#RRGGBB\" string or an [R, G, B, A] array")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Color, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Color, A::Error> {
+                let mut channels = Vec::new();
+                while let Some(channel) = seq.next_element::<u8>()? {
+                    channels.push(channel);
+                }
+                Color::from_channels(&channels)
+                    .ok_or_else(|| de::Error::invalid_length(channels.len(), &self))
+            }
+        }
+
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+/// Shape of a Chip8 pixel on screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aspect {
+    #[default]
+    Square,
+    /// Pixels 1.5 times as tall as wide, filling the 4:3 picture of the COSMAC VIP.
+    Vip,
+}
+
+impl Aspect {
+    /// Frame buffer pixels per Chip8 pixel, horizontally and vertically.
+    pub fn pixel_size(self) -> (usize, usize) {
+        match self {
+            Aspect::Square => (1, 1),
+            Aspect::Vip => (2, 3),
+        }
+    }
+}
+
+/// How pixels that turned off keep glowing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Persistence {
+    #[default]
+    Off,
+    /// Pixels fade out over a few frames, see `decay`.
+    Decay,
+    /// Pixels on at the end of either of the last two frames are shown.
+    Or,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct DisplayConfig {
     pub on_color: Color,
     pub off_color: Color,
+    /// Initial window width in screen pixels per Chip8 pixel.
     pub scale: u32,
+    /// Color around the image when the window does not fit it exactly.
+    #[serde(default = "default_border_color")]
+    pub border_color: Color,
+    #[serde(default)]
+    pub aspect: Aspect,
+    /// Start in fullscreen.
+    #[serde(default)]
+    pub fullscreen: bool,
+    #[serde(default)]
+    pub persistence: Persistence,
+    /// Brightness from 0 to 1 a pixel keeps per frame after turning off.
+    #[serde(default = "default_decay")]
+    pub decay: f32,
 }
 
-#[derive(Serialize, Deserialize)]
+fn default_decay() -> f32 {
+    0.5
+}
+
+fn default_border_color() -> Color {
+    Color(0, 0, 0, 255)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sine,
+    Noise,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AudioConfig {
     pub enabled: bool,
+    /// Buzzer pitch in Hz.
+    #[serde(default = "default_frequency")]
+    pub frequency: f32,
+    #[serde(default)]
+    pub waveform: Waveform,
+    /// Volume from 0 to 1.
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+    /// Fade in time in milliseconds, avoids clicks.
+    #[serde(default = "default_ramp_ms")]
+    pub attack_ms: f32,
+    /// Fade out time in milliseconds.
+    #[serde(default = "default_ramp_ms")]
+    pub release_ms: f32,
+}
+
+fn default_frequency() -> f32 {
+    1000.0
+}
+
+fn default_volume() -> f32 {
+    0.25
 }
 
-#[derive(Serialize, Deserialize)]
+fn default_ramp_ms() -> f32 {
+    5.0
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct TimingConfig {
     pub cpu_hz: u64,
     pub timer_hz: u64,
+    /// Speed multiplier while fast-forward is held.
+    #[serde(default = "default_fast_forward")]
+    pub fast_forward: u32,
+    /// Speed divisor in slow motion.
+    #[serde(default = "default_slow_motion")]
+    pub slow_motion: u32,
+}
+
+fn default_fast_forward() -> u32 {
+    4
+}
+
+fn default_slow_motion() -> u32 {
+    4
 }
 
 impl TimingConfig {
@@ -30,13 +218,108 @@ impl TimingConfig {
     pub const fn timer_tick_duration(&self) -> Duration {
         Duration::from_nanos(1_000_000_000 / self.timer_hz)
     }
+
+    /// Timer ticks due after the instruction at `cycle`, `timer_hz` every `cpu_hz` cycles.
+    ///
+    /// Counted from the first cycle, so the rate is exact even when `cpu_hz` is not a multiple
+    /// of `timer_hz`.
+    pub fn timer_ticks_after(&self, cycle: u64) -> u64 {
+        let ticks = |cycles: u64| cycles as u128 * self.timer_hz as u128 / self.cpu_hz as u128;
+        (ticks(cycle + 1) - ticks(cycle)) as u64
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GamepadConfig {
+    pub enabled: bool,
+    /// Stick deflection from 0 to 1 below which sticks count as released.
+    pub deadzone: f32,
+    /// Keypad value for each gilrs button name or stick direction, e.g. `LeftStickUp = 0x2`.
+    pub mapping: BTreeMap<String, u8>,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        let mapping = [
+            ("DPadUp", 0x2),
+            ("DPadDown", 0x8),
+            ("DPadLeft", 0x4),
+            ("DPadRight", 0x6),
+            ("LeftStickUp", 0x2),
+            ("LeftStickDown", 0x8),
+            ("LeftStickLeft", 0x4),
+            ("LeftStickRight", 0x6),
+            ("South", 0x5),
+            ("East", 0xf),
+            ("West", 0x7),
+            ("North", 0x9),
+            ("Start", 0x1),
+            ("Select", 0x0),
+        ]
+        .into_iter()
+        .map(|(name, key)| (name.to_string(), key))
+        .collect();
+
+        Self {
+            enabled: true,
+            deadzone: 0.5,
+            mapping,
+        }
+    }
+}
+
+/// Keypad value for each winit key name, e.g. `KeyQ = 0x4`.
+pub type KeymapConfig = BTreeMap<String, u8>;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub display: DisplayConfig,
     pub timing: TimingConfig,
     pub audio: AudioConfig,
+    #[serde(default = "default_keymap")]
+    pub keymap: KeymapConfig,
+    /// Keymaps replacing `keymap` for ROMs with this file name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rom_keymaps: BTreeMap<String, KeymapConfig>,
+    #[serde(default)]
+    pub gamepad: GamepadConfig,
+}
+
+impl Config {
+    /// Keymap for the ROM at `rom_path`.
+    pub fn keymap_for(&self, rom_path: &Path) -> &KeymapConfig {
+        rom_path
+            .file_name()
+            .and_then(|name| self.rom_keymaps.get(name.to_str()?))
+            .unwrap_or(&self.keymap)
+    }
+}
+
+/// QWERTY `1234/QWER/ASDF/ZXCV` layout of the COSMAC VIP keypad.
+pub fn default_keymap() -> KeymapConfig {
+    [
+        ("Digit1", 0x1),
+        ("Digit2", 0x2),
+        ("Digit3", 0x3),
+        ("Digit4", 0xc),
+        ("KeyQ", 0x4),
+        ("KeyW", 0x5),
+        ("KeyE", 0x6),
+        ("KeyR", 0xd),
+        ("KeyA", 0x7),
+        ("KeyS", 0x8),
+        ("KeyD", 0x9),
+        ("KeyF", 0xe),
+        ("KeyZ", 0xa),
+        ("KeyX", 0x0),
+        ("KeyC", 0xb),
+        ("KeyV", 0xf),
+    ]
+    .into_iter()
+    .map(|(name, key)| (name.to_string(), key))
+    .collect()
 }
 
 impl Default for Config {
@@ -46,20 +329,387 @@ impl Default for Config {
                 on_color: Color(255, 255, 255, 255),
                 off_color: Color(0, 0, 0, 255),
                 scale: 25,
+                border_color: default_border_color(),
+                aspect: Aspect::Square,
+                fullscreen: false,
+                persistence: Persistence::Off,
+                decay: default_decay(),
             },
             timing: TimingConfig {
                 cpu_hz: 700,
                 timer_hz: 60,
+                fast_forward: default_fast_forward(),
+                slow_motion: default_slow_motion(),
             },
-            audio: AudioConfig { enabled: true },
+            audio: AudioConfig {
+                enabled: true,
+                frequency: default_frequency(),
+                waveform: Waveform::Square,
+                volume: default_volume(),
+                attack_ms: default_ramp_ms(),
+                release_ms: default_ramp_ms(),
+            },
+            keymap: default_keymap(),
+            rom_keymaps: BTreeMap::new(),
+            gamepad: GamepadConfig::default(),
+        }
+    }
+}
+
+/// Directory of the user configuration, e.g. `~/.config/chip8` on Linux.
+pub fn user_config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chip8"))
+}
+
+/// Per-ROM override files in `dir`, by file name and then by content hash.
+pub fn rom_config_paths(dir: &Path, rom_path: &Path, rom: &[u8]) -> Vec<PathBuf> {
+    let roms = dir.join("roms");
+    let mut paths = Vec::new();
+    if let Some(name) = rom_path.file_name().and_then(|name| name.to_str()) {
+        paths.push(roms.join(format!("{}.toml", name)));
+    }
+    paths.push(roms.join(format!("{:016x}.toml", rom::hash(rom))));
+
+    paths
+}
+
+/// Tables later layers replace as a whole, merging keymaps would keep stale bindings.
+fn replaces_table(path: &str) -> bool {
+    path == "keymap" || path == "gamepad.mapping" || path.starts_with("rom_keymaps.")
+}
+
+/// Merges `layer` into `base`, `path` is the dotted key of `base`.
+fn merge(base: &mut toml::Table, layer: toml::Table, path: &str) {
+    for (key, value) in layer {
+        let key_path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(layer))
+                if !replaces_table(&key_path) =>
+            {
+                merge(base, layer, &key_path)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
         }
     }
 }
 
-/// Loads config from toml file.
+/// Parses a `key=value` override like `display.decay=0.8` into a layer.
+///
+/// Values that are not valid TOML are taken as strings.
+pub fn parse_assignment(assignment: &str) -> anyhow::Result<toml::Table> {
+    let (key, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Expected KEY=VALUE, got '{}'", assignment))?;
+    let (key, value) = (key.trim(), value.trim());
+
+    toml::from_str(&format!("{} = {}", key, value))
+        .or_else(|_| toml::from_str(&format!("{} = {}", key, toml::Value::from(value))))
+        .map_err(|e| anyhow::anyhow!("Invalid override '{}': {}", assignment, e))
+}
+
+fn deserialize(table: &toml::Table) -> Result<Config, toml::de::Error> {
+    Config::deserialize(toml::Value::Table(table.clone()))
+}
+
+/// Builds a config from partial TOML layers over the defaults, later layers win.
+pub struct ConfigBuilder {
+    table: toml::Table,
+    /// Each layer applied so far, defaults first.
+    sources: Vec<String>,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        let table = toml::Table::try_from(Config::default()).expect("default config serializes");
+
+        Self {
+            table,
+            sources: vec!["defaults".to_string()],
+        }
+    }
+
+    /// Merges a layer, `source` describes it in errors and `config dump`.
+    pub fn layer(&mut self, layer: toml::Table, source: &str) -> anyhow::Result<()> {
+        let mut table = self.table.clone();
+        merge(&mut table, layer, "");
+        // type errors are reported against the layer that caused them
+        deserialize(&table).map_err(|e| anyhow::anyhow!("Invalid config in {}: {}", source, e))?;
+
+        self.table = table;
+        self.sources.push(source.to_string());
+
+        Ok(())
+    }
+
+    /// Merges the TOML file at `path`, which may set any subset of the fields.
+    pub fn file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config '{}': {}", path.display(), e))?;
+        let layer = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid config '{}': {}", path.display(), e))?;
+        self.layer(layer, &path.display().to_string())
+    }
+
+    /// Merges the file at `path` when it exists.
+    pub fn optional_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        if path.is_file() {
+            self.file(path)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    /// Resolves the layers into a validated config.
+    pub fn build(&self) -> anyhow::Result<Config> {
+        let config = deserialize(&self.table)?;
+        validate(&config)?;
+
+        Ok(config)
+    }
+}
+
+/// Loads config from toml file, fields it leaves out keep their defaults.
 pub fn load_config<P: AsRef<Path>>(path: P) -> anyhow::Result<Config> {
-    let contents = fs::read_to_string(path)?;
-    let config = toml::from_str(&contents)?;
+    let mut builder = ConfigBuilder::new();
+    builder.file(path.as_ref())?;
+
+    builder.build()
+}
+
+/// Rejects values the emulator cannot use, reporting every invalid field at once.
+pub fn validate(config: &Config) -> anyhow::Result<()> {
+    let mut problems = Problems::default();
+
+    let display = &config.display;
+    problems.range("display.scale", display.scale, 1..=100);
+    problems.check(
+        "display.decay",
+        display.decay,
+        (0.0..1.0).contains(&display.decay),
+        "at least 0 and below 1",
+    );
+
+    let timing = &config.timing;
+    problems.range("timing.cpu_hz", timing.cpu_hz, 1..=10_000_000);
+    problems.range("timing.timer_hz", timing.timer_hz, 1..=1000);
+    problems.range("timing.fast_forward", timing.fast_forward, 1..=64);
+    problems.range("timing.slow_motion", timing.slow_motion, 1..=64);
+
+    let audio = &config.audio;
+    problems.check(
+        "audio.frequency",
+        audio.frequency,
+        audio.frequency > 0.0 && audio.frequency <= 20_000.0,
+        "above 0 and at most 20000",
+    );
+    problems.range("audio.volume", audio.volume, 0.0..=1.0);
+    problems.range("audio.attack_ms", audio.attack_ms, 0.0..=1000.0);
+    problems.range("audio.release_ms", audio.release_ms, 0.0..=1000.0);
 
-    Ok(config)
+    if let Err(e) = Keymap::new(&config.keymap) {
+        problems.table("keymap", e);
+    }
+    for (rom, keymap) in &config.rom_keymaps {
+        if let Err(e) = Keymap::new(keymap) {
+            problems.table(&format!("rom_keymaps.\"{}\"", rom), e);
+        }
+    }
+
+    let gamepad = &config.gamepad;
+    if let Err(e) = gamepad::parse_mapping(&gamepad.mapping) {
+        problems.table("gamepad.mapping", e);
+    }
+    problems.check(
+        "gamepad.deadzone",
+        gamepad.deadzone,
+        (0.0..1.0).contains(&gamepad.deadzone),
+        "at least 0 and below 1",
+    );
+
+    problems.into_result()
+}
+
+/// Invalid fields found by `validate`, each with its TOML path.
+#[derive(Default)]
+struct Problems(Vec<String>);
+
+impl Problems {
+    fn check(&mut self, path: &str, value: impl Display, valid: bool, expected: &str) {
+        if !valid {
+            self.0.push(format!(
+                "{} = {} is invalid, expected {}",
+                path, value, expected
+            ));
+        }
+    }
+
+    fn range<T: PartialOrd + Display>(&mut self, path: &str, value: T, range: RangeInclusive<T>) {
+        let valid = range.contains(&value);
+        let expected = format!("{} to {}", range.start(), range.end());
+        self.check(path, value, valid, &expected);
+    }
+
+    fn table(&mut self, path: &str, error: impl Display) {
+        self.0.push(format!("[{}] is invalid: {}", path, error));
+    }
+
+    fn into_result(self) -> anyhow::Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+
+        let lines: Vec<String> = self
+            .0
+            .iter()
+            .map(|problem| format!("  {}", problem))
+            .collect();
+        anyhow::bail!("Invalid config:\n{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(toml: &str) -> toml::Table {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_config_partial_layers() {
+        let mut builder = ConfigBuilder::new();
+        builder
+            .layer(layer("[timing]\ncpu_hz = 1000"), "user")
+            .unwrap();
+        builder
+            .layer(layer("[display]\nscale = 10"), "rom")
+            .unwrap();
+        builder
+            .layer(parse_assignment("timing.cpu_hz=500").unwrap(), "flags")
+            .unwrap();
+
+        let config = builder.build().unwrap();
+        assert_eq!(500, config.timing.cpu_hz);
+        assert_eq!(60, config.timing.timer_hz);
+        assert_eq!(10, config.display.scale);
+        assert_eq!(25, Config::default().display.scale);
+        assert_eq!(["defaults", "user", "rom", "flags"], builder.sources());
+
+        builder
+            .layer(layer("[audio]\nvolume = 2.0"), "invalid")
+            .unwrap();
+        assert!(builder.build().is_err());
+        assert!(
+            builder
+                .layer(layer("[display]\nscael = 2"), "typo")
+                .is_err()
+        );
+    }
+    #[test]
+    fn test_config_reports_every_problem() {
+        let mut builder = ConfigBuilder::new();
+        builder
+            .layer(
+                layer("[timing]\ncpu_hz = 0\n[display]\nscale = 0\ndecay = 1.5\n[keymap]\nF1 = 1"),
+                "user",
+            )
+            .unwrap();
+
+        let error = builder.build().err().unwrap().to_string();
+        assert!(error.contains("timing.cpu_hz = 0 is invalid, expected 1 to 10000000"));
+        assert!(error.contains("display.scale = 0 is invalid, expected 1 to 100"));
+        assert!(error.contains("display.decay = 1.5"));
+        assert!(error.contains("[keymap] is invalid"));
+        assert_eq!(5, error.lines().count());
+    }
+    #[test]
+    fn test_config_colors() {
+        assert_eq!(Ok(Color(255, 0, 128, 255)), "#ff0080".parse());
+        assert_eq!(Ok(Color(1, 2, 3, 4)), "#01020304".parse());
+        assert_eq!(Ok(Color(1, 2, 3, 255)), "1, 2, 3".parse());
+        assert!("#ff00".parse::<Color>().is_err());
+        assert!("#gg0000".parse::<Color>().is_err());
+        assert!("1,2".parse::<Color>().is_err());
+
+        let mut builder = ConfigBuilder::new();
+        builder
+            .layer(
+                layer("[display]\non_color = \"#336699\"\noff_color = [1, 2, 3]"),
+                "user",
+            )
+            .unwrap();
+        let config = builder.build().unwrap();
+        assert_eq!(Color(0x33, 0x66, 0x99, 255), config.display.on_color);
+        assert_eq!(Color(1, 2, 3, 255), config.display.off_color);
+        assert!(
+            builder
+                .layer(layer("[display]\non_color = \"red\""), "user")
+                .is_err()
+        );
+    }
+    #[test]
+    fn test_config_keymap_replaced() {
+        let mut builder = ConfigBuilder::new();
+        builder
+            .layer(layer("[keymap]\nKeyQ = 0x1"), "user")
+            .unwrap();
+
+        let config = builder.build().unwrap();
+        assert_eq!(1, config.keymap.len());
+        assert_eq!(Some(&1), config.keymap.get("KeyQ"));
+    }
+    #[test]
+    fn test_config_parse_assignment() {
+        assert_eq!(
+            layer("[display]\npersistence = \"decay\""),
+            parse_assignment("display.persistence=decay").unwrap()
+        );
+        assert_eq!(
+            layer("[audio]\nenabled = false"),
+            parse_assignment("audio.enabled = false").unwrap()
+        );
+        assert!(parse_assignment("display.scale").is_err());
+    }
+    #[test]
+    fn test_config_timer_ticks() {
+        let timing = Config::default().timing;
+        let ticks: Vec<u64> = (0..700)
+            .map(|cycle| timing.timer_ticks_after(cycle))
+            .collect();
+        // 700 Hz is not a multiple of 60 Hz, ticks are 11 or 12 cycles apart
+        assert_eq!(60, ticks.iter().sum::<u64>());
+        assert_eq!(1, ticks[11]);
+        assert_eq!(0, ticks[12]);
+
+        let fast_timers = TimingConfig {
+            cpu_hz: 30,
+            ..timing
+        };
+        assert_eq!(2, fast_timers.timer_ticks_after(0));
+    }
+    #[test]
+    fn test_config_rom_paths() {
+        let paths = rom_config_paths(Path::new("/c"), Path::new("roms/pong.ch8"), &[0x00, 0xe0]);
+        assert_eq!(Path::new("/c/roms/pong.ch8.toml"), paths[0]);
+        assert_eq!(
+            format!("/c/roms/{:016x}.toml", rom::hash(&[0x00, 0xe0])),
+            paths[1].display().to_string()
+        );
+    }
 }
@@ -0,0 +1,888 @@
+//! Debug Adapter Protocol server over stdio.
+//!
+//! Breakpoints can be set by listing line (`setBreakpoints`, needs the
+//! `listing` launch argument), by address (`setInstructionBreakpoints`, or
+//! function breakpoints named like `0x200`). The display is printed to the
+//! debug console whenever execution stops.
+
+use crate::{
+    app,
+    config::{self, Config},
+    rom,
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use libchip8::{
+    Chip8, config as libconfig,
+    debugger::{Debugger, Stop},
+    instruction::Instruction,
+    listing::Listing,
+    timers::Timer,
+};
+use serde_json::{Value, json};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REF: u64 = 1;
+const TIMERS_REF: u64 = 2;
+const STACK_REF: u64 = 3;
+
+/// Reads one `Content-Length` framed message, `None` at end of input.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Writes one `Content-Length` framed message.
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// What a running machine waits for before stopping on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Until {
+    Paused,
+    /// `continue`.
+    Breakpoint,
+    /// `stepIn`, a single instruction.
+    Instruction,
+    /// `next`, back at or above the given call depth.
+    Depth(usize),
+    /// `stepOut`, above the given call depth.
+    Return(usize),
+}
+
+/// Launched program.
+struct Target {
+    chip8: Chip8,
+    config: Config,
+    listing: Option<Listing>,
+    listing_path: Option<String>,
+    stop_on_entry: bool,
+    cycle: u64,
+}
+
+pub struct Adapter<W: Write> {
+    out: W,
+    seq: u64,
+    target: Option<Target>,
+    debugger: Debugger,
+    line_breakpoints: Vec<u16>,
+    address_breakpoints: Vec<u16>,
+    function_breakpoints: Vec<u16>,
+    until: Until,
+    last_cpu_tick: Instant,
+    finished: bool,
+}
+
+impl<W: Write> Adapter<W> {
+    pub fn new(out: W) -> Self {
+        Adapter {
+            out,
+            seq: 1,
+            target: None,
+            debugger: Debugger::new(),
+            line_breakpoints: Vec::new(),
+            address_breakpoints: Vec::new(),
+            function_breakpoints: Vec::new(),
+            until: Until::Paused,
+            last_cpu_tick: Instant::now(),
+            finished: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.until != Until::Paused
+    }
+
+    /// Set once the client disconnected or terminated the session.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Handles a request and sends its response.
+    pub fn handle(&mut self, request: &Value) -> io::Result<()> {
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let args = &request["arguments"];
+
+        let result = match command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsDisassembleRequest": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "configurationDone" => Ok(Value::Null),
+            "setBreakpoints" => self.set_line_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_address_breakpoints(args),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => self.target().map(stack_trace),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REF, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS_REF, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_REF, "expensive": false },
+            ]})),
+            "variables" => self.target().map(|target| variables(target, args)),
+            "evaluate" => self.evaluate(args),
+            "readMemory" => self.target().and_then(|target| read_memory(target, args)),
+            "writeMemory" => self
+                .target_mut()
+                .and_then(|target| write_memory(target, args)),
+            "disassemble" => self.target().and_then(|target| disassemble(target, args)),
+            "continue" => self.resume(Until::Breakpoint),
+            "next" => {
+                let depth = self.target().map(|target| target.chip8.call_stack().len());
+                depth.and_then(|depth| self.resume(Until::Depth(depth)))
+            }
+            "stepIn" => self.resume(Until::Instruction),
+            "stepOut" => {
+                let depth = self.target().map(|target| target.chip8.call_stack().len());
+                depth.and_then(|depth| self.resume(Until::Return(depth)))
+            }
+            "pause" => {
+                self.until = Until::Paused;
+                Ok(Value::Null)
+            }
+            "disconnect" | "terminate" => {
+                self.until = Until::Paused;
+                self.finished = true;
+                Ok(Value::Null)
+            }
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+
+        let response = match &result {
+            Ok(body) => json!({
+                "type": "response",
+                "request_seq": request["seq"],
+                "success": true,
+                "command": command,
+                "body": body,
+            }),
+            Err(message) => json!({
+                "type": "response",
+                "request_seq": request["seq"],
+                "success": false,
+                "command": command,
+                "message": message,
+            }),
+        };
+        self.send(response)?;
+
+        match (command.as_str(), result.is_ok()) {
+            ("initialize", true) => self.event("initialized", Value::Null)?,
+            ("configurationDone", _) => {
+                if self
+                    .target
+                    .as_ref()
+                    .is_some_and(|target| target.stop_on_entry)
+                {
+                    self.stopped("entry", None)?;
+                } else if self.target.is_some() {
+                    self.until = Until::Breakpoint;
+                    self.last_cpu_tick = Instant::now();
+                }
+            }
+            ("pause", true) => self.stopped("pause", None)?,
+            ("terminate", true) => self.event("terminated", Value::Null)?,
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Executes the instructions due since the last call at the configured CPU rate.
+    pub fn advance(&mut self, now: Instant) -> io::Result<()> {
+        let Some(cpu_tick) = self
+            .target
+            .as_ref()
+            .map(|target| target.config.timing.cpu_tick_duration())
+        else {
+            return Ok(());
+        };
+
+        while self.is_running() && now.duration_since(self.last_cpu_tick) >= cpu_tick {
+            self.last_cpu_tick += cpu_tick;
+            self.execute()?;
+        }
+        if !self.is_running() {
+            self.last_cpu_tick = now;
+        }
+
+        Ok(())
+    }
+
+    /// Executes one instruction and reports a stop if there is one.
+    fn execute(&mut self) -> io::Result<()> {
+        let Some(target) = &mut self.target else {
+            return Ok(());
+        };
+
+        let (cycle, timing) = (target.cycle, &target.config.timing);
+        let result = self
+            .debugger
+            .step_with(&mut target.chip8, |chip8| app::step(chip8, cycle, timing));
+        let depth = target.chip8.call_stack().len();
+
+        match result {
+            Ok(Some(Stop::Breakpoint(_))) => self.stopped("breakpoint", None),
+            Ok(Some(Stop::Watchpoint { .. })) => self.stopped("data breakpoint", None),
+            Ok(None) => {
+                target.cycle += 1;
+                match self.until {
+                    Until::Instruction => self.stopped("step", None),
+                    Until::Depth(start) if depth <= start => self.stopped("step", None),
+                    Until::Return(start) if depth < start => self.stopped("step", None),
+                    _ => Ok(()),
+                }
+            }
+            Err(e) => self.stopped("exception", Some(e.to_string())),
+        }
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"]
+            .as_str()
+            .ok_or("launch needs a 'program' ROM path")?;
+        let rom_bytes = rom::read_rom_bytes(program).map_err(|e| e.to_string())?;
+
+        let config = match args["config"].as_str() {
+            Some(path) => config::load_config(path).map_err(|e| e.to_string())?,
+            None => Config::default(),
+        };
+        let listing_path = args["listing"].as_str().map(str::to_string);
+        let listing = match &listing_path {
+            Some(path) => Some(Listing::parse(
+                &fs::read_to_string(path).map_err(|e| e.to_string())?,
+            )),
+            None => None,
+        };
+
+        let mut chip8 = match args["seed"].as_u64() {
+            Some(seed) => Chip8::with_seed(seed),
+            None => Chip8::new(),
+        };
+        chip8.load_rom(&rom_bytes).map_err(|e| e.to_string())?;
+
+        self.target = Some(Target {
+            chip8,
+            config,
+            listing,
+            listing_path,
+            stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false),
+            cycle: 0,
+        });
+
+        Ok(Value::Null)
+    }
+
+    fn set_line_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let listing = self
+            .target
+            .as_ref()
+            .and_then(|target| target.listing.as_ref());
+        let mut addresses = Vec::new();
+        let breakpoints: Vec<Value> = requested(args, "breakpoints")
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+                match listing.and_then(|listing| listing.address_for(line)) {
+                    Some(address) => {
+                        addresses.push(address);
+                        json!({ "verified": true, "line": line, "instructionReference": reference(address) })
+                    }
+                    None => json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "no instruction at this line of the listing",
+                    }),
+                }
+            })
+            .collect();
+
+        self.line_breakpoints = addresses;
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_address_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let (addresses, breakpoints) = address_breakpoints(args, "instructionReference");
+        self.address_breakpoints = addresses;
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_function_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let (addresses, breakpoints) = address_breakpoints(args, "name");
+        self.function_breakpoints = addresses;
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn sync_breakpoints(&mut self) {
+        self.debugger.clear();
+        for &address in self
+            .line_breakpoints
+            .iter()
+            .chain(&self.address_breakpoints)
+            .chain(&self.function_breakpoints)
+        {
+            self.debugger.add_breakpoint(address);
+        }
+    }
+
+    fn evaluate(&mut self, args: &Value) -> Result<Value, String> {
+        let target = self.target()?;
+        let expression = args["expression"].as_str().unwrap_or_default().trim();
+
+        let result = match expression.to_lowercase().as_str() {
+            "display" | "framebuffer" => framebuffer(&target.chip8),
+            "i" => format!("{:#05X}", target.chip8.i()),
+            "pc" => format!("{:#05X}", target.chip8.pc()),
+            "dt" => target.chip8.timers.get(Timer::Delay).to_string(),
+            "st" => target.chip8.timers.get(Timer::Sound).to_string(),
+            register => match register
+                .strip_prefix('v')
+                .and_then(|idx| u8::from_str_radix(idx, 16).ok())
+            {
+                Some(idx) if (idx as usize) < libconfig::REGISTER_COUNT => {
+                    byte(target.chip8.register(idx))
+                }
+                _ => return Err(format!("unknown expression '{}'", expression)),
+            },
+        };
+
+        Ok(json!({ "result": result, "variablesReference": 0 }))
+    }
+
+    fn resume(&mut self, until: Until) -> Result<Value, String> {
+        self.target()?;
+        self.debugger.resume();
+        self.until = until;
+        self.last_cpu_tick = Instant::now();
+
+        Ok(json!({ "allThreadsContinued": true }))
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<String>) -> io::Result<()> {
+        self.until = Until::Paused;
+
+        if let Some(target) = &self.target {
+            let output = format!("{}\n", framebuffer(&target.chip8));
+            self.event("output", json!({ "category": "console", "output": output }))?;
+        }
+
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(description) = description {
+            body["description"] = json!(description);
+            body["text"] = json!(description);
+        }
+        self.event("stopped", body)
+    }
+
+    fn target(&self) -> Result<&Target, String> {
+        self.target
+            .as_ref()
+            .ok_or_else(|| "no program launched".to_string())
+    }
+
+    fn target_mut(&mut self) -> Result<&mut Target, String> {
+        self.target
+            .as_mut()
+            .ok_or_else(|| "no program launched".to_string())
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        write_message(&mut self.out, &message)
+    }
+}
+
+/// Serves one debug session until the client disconnects.
+pub fn serve<R, W>(input: R, output: W) -> anyhow::Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write,
+{
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = input;
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut adapter = Adapter::new(output);
+    while !adapter.is_finished() {
+        let request = if adapter.is_running() {
+            match requests.recv_timeout(Duration::from_millis(1)) {
+                Ok(request) => Some(request),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match requests.recv() {
+                Ok(request) => Some(request),
+                Err(_) => break,
+            }
+        };
+
+        if let Some(request) = request {
+            adapter.handle(&request)?;
+        }
+        adapter.advance(Instant::now())?;
+    }
+
+    Ok(())
+}
+
+fn requested<'a>(args: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    args[key].as_array().into_iter().flatten()
+}
+
+/// Breakpoints whose `key` holds an address such as `0x200`.
+fn address_breakpoints(args: &Value, key: &str) -> (Vec<u16>, Vec<Value>) {
+    let mut addresses = Vec::new();
+    let breakpoints = requested(args, "breakpoints")
+        .map(|breakpoint| {
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+            let address = breakpoint[key]
+                .as_str()
+                .and_then(parse_reference)
+                .map(|address| address as i64 + offset)
+                .filter(|address| (0..libconfig::MEMORY_SIZE as i64).contains(address));
+
+            match address {
+                Some(address) => {
+                    addresses.push(address as u16);
+                    json!({ "verified": true, "instructionReference": reference(address as u16) })
+                }
+                None => json!({ "verified": false, "message": "expected an address like 0x200" }),
+            }
+        })
+        .collect();
+
+    (addresses, breakpoints)
+}
+
+fn parse_reference(text: &str) -> Option<usize> {
+    let text = text.trim();
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    usize::from_str_radix(digits, 16).ok()
+}
+
+fn reference(address: u16) -> String {
+    format!("{:#05X}", address)
+}
+
+fn byte(value: u8) -> String {
+    format!("{:#04X} ({})", value, value)
+}
+
+fn instruction_at(chip8: &Chip8, address: u16) -> String {
+    chip8
+        .read_memory(address as usize, 2)
+        .ok()
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .and_then(Instruction::decode)
+        .map_or_else(|| "??".to_string(), |instruction| instruction.to_string())
+}
+
+fn stack_trace(target: &Target) -> Value {
+    let chip8 = &target.chip8;
+    // current instruction, then the CALL sites, innermost first
+    let addresses = std::iter::once(chip8.pc())
+        .chain(chip8.call_stack().iter().rev().map(|r| r.wrapping_sub(2)));
+
+    let frames: Vec<Value> = addresses
+        .enumerate()
+        .map(|(id, address)| {
+            let mut frame = json!({
+                "id": id,
+                "name": format!("{}: {}", reference(address), instruction_at(chip8, address)),
+                "line": 0,
+                "column": 0,
+                "instructionPointerReference": reference(address),
+            });
+            let line = target
+                .listing
+                .as_ref()
+                .and_then(|listing| listing.line_for(address));
+            if let (Some(path), Some(line)) = (&target.listing_path, line) {
+                frame["source"] = json!({ "path": path });
+                frame["line"] = json!(line);
+                frame["column"] = json!(1);
+            }
+            frame
+        })
+        .collect();
+
+    json!({ "stackFrames": frames, "totalFrames": frames.len() })
+}
+
+fn variables(target: &Target, args: &Value) -> Value {
+    let chip8 = &target.chip8;
+    let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+    let pointer = |name: &str, address: u16| {
+        json!({
+            "name": name,
+            "value": reference(address),
+            "variablesReference": 0,
+            "memoryReference": reference(address),
+        })
+    };
+
+    let variables: Vec<Value> = match args["variablesReference"].as_u64() {
+        Some(REGISTERS_REF) => (0..libconfig::REGISTER_COUNT as u8)
+            .map(|idx| variable(format!("V{:X}", idx), byte(chip8.register(idx))))
+            .chain([pointer("I", chip8.i()), pointer("PC", chip8.pc())])
+            .collect(),
+        Some(TIMERS_REF) => vec![
+            variable("DT".to_string(), chip8.timers.get(Timer::Delay).to_string()),
+            variable("ST".to_string(), chip8.timers.get(Timer::Sound).to_string()),
+        ],
+        Some(STACK_REF) => std::iter::once(variable(
+            "SP".to_string(),
+            chip8.call_stack().len().to_string(),
+        ))
+        .chain(
+            chip8
+                .call_stack()
+                .iter()
+                .enumerate()
+                .map(|(idx, &address)| pointer(&format!("[{}]", idx), address)),
+        )
+        .collect(),
+        _ => Vec::new(),
+    };
+
+    json!({ "variables": variables })
+}
+
+fn read_memory(target: &Target, args: &Value) -> Result<Value, String> {
+    let start = memory_address(args)?;
+    let count = args["count"].as_u64().unwrap_or(0) as usize;
+    let end = (start + count).min(libconfig::MEMORY_SIZE).max(start);
+    let bytes = target
+        .chip8
+        .read_memory(start, end - start)
+        .map_err(|e| e.to_string())?;
+
+    Ok(json!({
+        "address": reference(start as u16),
+        "data": BASE64.encode(bytes),
+        "unreadableBytes": count - bytes.len(),
+    }))
+}
+
+fn write_memory(target: &mut Target, args: &Value) -> Result<Value, String> {
+    let start = memory_address(args)?;
+    let data = BASE64
+        .decode(args["data"].as_str().unwrap_or_default())
+        .map_err(|e| e.to_string())?;
+
+    target
+        .chip8
+        .write_memory(start, &data)
+        .map_err(|e| e.to_string())?;
+
+    Ok(json!({ "bytesWritten": data.len() }))
+}
+
+fn memory_address(args: &Value) -> Result<usize, String> {
+    let base = args["memoryReference"]
+        .as_str()
+        .and_then(parse_reference)
+        .ok_or("invalid memory reference")?;
+    let address = base as i64 + args["offset"].as_i64().unwrap_or(0);
+    if !(0..libconfig::MEMORY_SIZE as i64).contains(&address) {
+        return Err(format!("address {:#X} out of range", address));
+    }
+
+    Ok(address as usize)
+}
+
+fn disassemble(target: &Target, args: &Value) -> Result<Value, String> {
+    let base = args["memoryReference"]
+        .as_str()
+        .and_then(parse_reference)
+        .ok_or("invalid memory reference")? as i64
+        + args["offset"].as_i64().unwrap_or(0)
+        + args["instructionOffset"].as_i64().unwrap_or(0) * 2;
+    let count = args["instructionCount"].as_u64().unwrap_or(0) as i64;
+
+    // the client expects exactly `count` entries, even outside memory
+    let instructions: Vec<Value> = (0..count)
+        .map(|idx| {
+            let address = base + idx * 2;
+            let bytes = usize::try_from(address)
+                .ok()
+                .and_then(|address| target.chip8.read_memory(address, 2).ok());
+            let Some(bytes) = bytes else {
+                return json!({ "address": format!("{:#X}", address), "instruction": "??", "presentationHint": "invalid" });
+            };
+
+            let address = address as u16;
+            let mut instruction = json!({
+                "address": reference(address),
+                "instructionBytes": format!("{:02X}{:02X}", bytes[0], bytes[1]),
+                "instruction": instruction_at(&target.chip8, address),
+            });
+            let line = target
+                .listing
+                .as_ref()
+                .and_then(|listing| listing.line_for(address));
+            if let (Some(path), Some(line)) = (&target.listing_path, line) {
+                instruction["location"] = json!({ "path": path });
+                instruction["line"] = json!(line);
+            }
+            instruction
+        })
+        .collect();
+
+    Ok(json!({ "instructions": instructions }))
+}
+
+/// Display as text, `#` for lit pixels.
+fn framebuffer(chip8: &Chip8) -> String {
+    chip8
+        .display
+        .dump()
+        .chunks(libconfig::DISPLAY_WIDTH)
+        .map(|row| row.iter().map(|&on| if on { '#' } else { '.' }).collect())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, io::Cursor};
+
+    // 0x200: CALL 0x206
+    // 0x202: LD V1, 7
+    // 0x204: JP 0x204
+    // 0x206: LD V0, 5
+    // 0x208: RET
+    const ROM: [u8; 10] = [0x22, 0x06, 0x61, 0x07, 0x12, 0x04, 0x60, 0x05, 0x00, 0xee];
+    const LISTING: &str = "0200: 2206  CALL 0x206\n\
+                           0202: 6107  LD V1, 7\n\
+                           0204: 1204  JP 0x204\n\
+                           0206: 6005  LD V0, 5\n\
+                           0208: 00EE  RET\n";
+
+    struct Client {
+        adapter: Adapter<Vec<u8>>,
+        seq: u64,
+    }
+
+    impl Client {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("chip8-dap-{}-{}", std::process::id(), name));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("test.ch8"), ROM).unwrap();
+            fs::write(dir.join("test.lst"), LISTING).unwrap();
+
+            let mut client = Client {
+                adapter: Adapter::new(Vec::new()),
+                seq: 0,
+            };
+            client.request("initialize", json!({}));
+            client.request(
+                "launch",
+                json!({
+                    "program": dir.join("test.ch8"),
+                    "listing": dir.join("test.lst"),
+                    "stopOnEntry": true,
+                }),
+            );
+            client
+        }
+
+        /// Sends a request and returns every message written in response.
+        fn request(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+            self.seq += 1;
+            self.adapter
+                .handle(&json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }))
+                .unwrap();
+            self.drain()
+        }
+
+        fn drain(&mut self) -> Vec<Value> {
+            let mut reader = Cursor::new(std::mem::take(&mut self.adapter.out));
+            std::iter::from_fn(|| read_message(&mut reader).unwrap()).collect()
+        }
+
+        /// Runs the machine until it stops and returns the stop reason.
+        fn run(&mut self) -> String {
+            for _ in 0..100 {
+                self.adapter.execute().unwrap();
+                if !self.adapter.is_running() {
+                    let messages = self.drain();
+                    let stopped = messages.iter().find(|m| m["event"] == "stopped").unwrap();
+                    return stopped["body"]["reason"].as_str().unwrap().to_string();
+                }
+            }
+            panic!("machine did not stop");
+        }
+
+        fn pc(&self) -> u16 {
+            self.adapter.target.as_ref().unwrap().chip8.pc()
+        }
+    }
+
+    #[test]
+    fn test_dap_message_framing() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({ "seq": 1 })).unwrap();
+        assert_eq!(b"Content-Length: 9\r\n\r\n{\"seq\":1}", out.as_slice());
+
+        let message = read_message(&mut Cursor::new(out)).unwrap();
+        assert_eq!(Some(json!({ "seq": 1 })), message);
+    }
+    #[test]
+    fn test_dap_line_breakpoints_and_stepping() {
+        let mut client = Client::new("breakpoints");
+        let messages = client.request("configurationDone", json!({}));
+        assert!(
+            messages
+                .iter()
+                .any(|m| m["event"] == "stopped" && m["body"]["reason"] == "entry")
+        );
+
+        let messages = client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": "test.lst" }, "breakpoints": [{ "line": 2 }, { "line": 9 }] }),
+        );
+        let breakpoints = &messages[0]["body"]["breakpoints"];
+        assert_eq!(json!(true), breakpoints[0]["verified"]);
+        assert_eq!(json!(false), breakpoints[1]["verified"]);
+
+        client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!("breakpoint", client.run());
+        assert_eq!(0x202, client.pc());
+
+        let messages = client.request("stackTrace", json!({ "threadId": 1 }));
+        let frame = &messages[0]["body"]["stackFrames"][0];
+        assert_eq!(json!(2), frame["line"]);
+        assert_eq!(json!("0x202: LD V1, 0x07"), frame["name"]);
+
+        client.request("stepIn", json!({ "threadId": 1 }));
+        assert_eq!("step", client.run());
+        assert_eq!(0x204, client.pc());
+    }
+    #[test]
+    fn test_dap_step_over_and_out() {
+        let mut client = Client::new("step");
+        client.request("configurationDone", json!({}));
+
+        client.request("next", json!({ "threadId": 1 }));
+        assert_eq!("step", client.run());
+        assert_eq!(0x202, client.pc());
+
+        client.request(
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0x206" }] }),
+        );
+        client.adapter.target.as_mut().unwrap().chip8.set_pc(0x200);
+        client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!("breakpoint", client.run());
+
+        let messages = client.request("variables", json!({ "variablesReference": STACK_REF }));
+        assert_eq!(json!("0x202"), messages[0]["body"]["variables"][1]["value"]);
+
+        client.request("stepOut", json!({ "threadId": 1 }));
+        assert_eq!("step", client.run());
+        assert_eq!(0x202, client.pc());
+    }
+    #[test]
+    fn test_dap_memory_and_display() {
+        let mut client = Client::new("memory");
+
+        let messages = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x200", "count": 4 }),
+        );
+        assert_eq!(json!(BASE64.encode(&ROM[..4])), messages[0]["body"]["data"]);
+
+        client.request(
+            "writeMemory",
+            json!({ "memoryReference": "0x300", "data": BASE64.encode([1, 2]) }),
+        );
+        let messages = client.request("evaluate", json!({ "expression": "display" }));
+        let display = messages[0]["body"]["result"].as_str().unwrap();
+        assert_eq!(libconfig::DISPLAY_HEIGHT, display.lines().count());
+
+        let messages = client.request(
+            "disassemble",
+            json!({ "memoryReference": "0x200", "instructionOffset": -1, "instructionCount": 3 }),
+        );
+        let instructions = &messages[0]["body"]["instructions"];
+        assert_eq!(json!("CALL 0x206"), instructions[1]["instruction"]);
+        assert_eq!(json!(1), instructions[1]["line"]);
+
+        // the last word of memory, then one past the end
+        let messages = client.request(
+            "disassemble",
+            json!({ "memoryReference": "0xFFE", "instructionCount": 2 }),
+        );
+        let instructions = &messages[0]["body"]["instructions"];
+        assert_eq!(json!("0000"), instructions[0]["instructionBytes"]);
+        assert_eq!(json!("invalid"), instructions[1]["presentationHint"]);
+        let messages = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0xFFC", "count": 8 }),
+        );
+        assert_eq!(json!(BASE64.encode([0; 4])), messages[0]["body"]["data"]);
+        assert_eq!(json!(4), messages[0]["body"]["unreadableBytes"]);
+        assert_eq!(
+            2,
+            client.adapter.target.as_ref().unwrap().chip8.memory()[0x301]
+        );
+    }
+}
@@ -0,0 +1,524 @@
+//! Full-screen terminal debugger.
+//!
+//! Drives `Chip8` one instruction at a time through `libchip8::debugger`,
+//! commands are typed into the bar at the bottom (`help` lists them).
+
+use crate::{app, config::Config, tui};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use libchip8::{
+    Chip8, config as libconfig,
+    debugger::{Debugger, Stop, WatchKind, Watchpoint},
+    instruction::Instruction,
+    listing::Listing,
+    timers::Timer,
+};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+};
+use std::time::{Duration, Instant};
+
+const HELP: &str = "s [n] step | n next | c continue | b/d <addr> break/delete | \
+                    watch <addr> [len] | set <reg> <value> | w <addr> <bytes..> | \
+                    m <addr|i> memory view | press/release <key> | q quit";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Step(u32),
+    /// Steps over subroutine calls.
+    Next,
+    Continue,
+    Break(u16),
+    Delete(u16),
+    Watch(u16, u16),
+    /// Register name (`v0`..`vf`, `i`, `pc`, `dt`, `st`) and value.
+    Set(String, u16),
+    Write(u16, Vec<u8>),
+    /// `None` follows I.
+    Memory(Option<u16>),
+    Key(u8, bool),
+    Help,
+    Quit,
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("not a number: '{}'", text))
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+    u8::try_from(parse_number(text)?).map_err(|_| format!("not a byte: '{}'", text))
+}
+
+/// Parses a command bar line, numbers are decimal or `0x` hex.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let args: Vec<&str> = words.collect();
+    let arg = |idx: usize| {
+        args.get(idx)
+            .copied()
+            .ok_or_else(|| format!("'{}' needs more arguments", name))
+    };
+
+    let command = match name {
+        "s" | "step" => Command::Step(match args.first() {
+            Some(count) => parse_number(count)? as u32,
+            None => 1,
+        }),
+        "n" | "next" => Command::Next,
+        "c" | "continue" => Command::Continue,
+        "b" | "break" => Command::Break(parse_number(arg(0)?)?),
+        "d" | "delete" => Command::Delete(parse_number(arg(0)?)?),
+        "watch" => Command::Watch(
+            parse_number(arg(0)?)?,
+            args.get(1).map_or(Ok(1), |len| parse_number(len))?,
+        ),
+        "set" => Command::Set(arg(0)?.to_lowercase(), parse_number(arg(1)?)?),
+        "w" | "write" => Command::Write(
+            parse_number(arg(0)?)?,
+            args[1..]
+                .iter()
+                .map(|byte| parse_byte(byte))
+                .collect::<Result<_, _>>()?,
+        ),
+        "m" | "memory" => match arg(0)? {
+            "i" | "I" => Command::Memory(None),
+            address => Command::Memory(Some(parse_number(address)?)),
+        },
+        "press" | "release" => {
+            let key = parse_byte(arg(0)?)?;
+            if key >= libconfig::KEY_COUNT {
+                return Err(format!("key out of range: {}", key));
+            }
+            Command::Key(key, name == "press")
+        }
+        "h" | "help" => Command::Help,
+        "q" | "quit" => Command::Quit,
+        _ => return Err(format!("unknown command '{}', try 'help'", name)),
+    };
+
+    Ok(command)
+}
+
+/// Debugger state, independent of the terminal.
+pub struct Session {
+    pub chip8: Chip8,
+    pub debugger: Debugger,
+    config: Config,
+    listing: Option<Listing>,
+    cycle: u64,
+    /// Set while `continue` or `next` runs, `next` stops at this call depth.
+    running: Option<Option<usize>>,
+    memory_view: Option<u16>,
+    pub message: String,
+    quit: bool,
+}
+
+impl Session {
+    pub fn new(chip8: Chip8, config: Config, listing: Option<Listing>) -> Self {
+        Session {
+            chip8,
+            debugger: Debugger::new(),
+            config,
+            listing,
+            cycle: 0,
+            running: None,
+            memory_view: None,
+            message: HELP.to_string(),
+            quit: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    pub fn execute(&mut self, command: Command) {
+        self.message.clear();
+
+        match command {
+            Command::Step(count) => {
+                self.debugger.resume();
+                for _ in 0..count {
+                    if self.step() {
+                        break;
+                    }
+                }
+            }
+            Command::Next => {
+                self.debugger.resume();
+                self.running = Some(Some(self.chip8.call_stack().len()));
+            }
+            Command::Continue => {
+                self.debugger.resume();
+                self.running = Some(None);
+            }
+            Command::Break(address) => {
+                self.debugger.add_breakpoint(address);
+                self.message = format!("breakpoint at {:#05X}", address);
+            }
+            Command::Delete(address) => {
+                self.message = if self.debugger.remove_breakpoint(address) {
+                    format!("deleted breakpoint at {:#05X}", address)
+                } else {
+                    format!("no breakpoint at {:#05X}", address)
+                };
+            }
+            Command::Watch(address, len) => {
+                self.debugger.add_watchpoint(Watchpoint {
+                    address,
+                    len,
+                    kind: WatchKind::Access,
+                });
+                self.message = format!("watching {} bytes at {:#05X}", len, address);
+            }
+            Command::Set(register, value) => self.set_register(&register, value),
+            Command::Write(address, bytes) => {
+                if let Err(e) = self.chip8.write_memory(address as usize, &bytes) {
+                    self.message = e.to_string();
+                }
+            }
+            Command::Memory(address) => self.memory_view = address,
+            Command::Key(key, pressed) => self.chip8.keyboard.set_key(key, pressed),
+            Command::Help => self.message = HELP.to_string(),
+            Command::Quit => self.quit = true,
+        }
+    }
+
+    /// Executes the instructions due since the last call while running.
+    pub fn advance(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            let Some(depth) = self.running else {
+                break;
+            };
+            if self.step() || depth.is_some_and(|depth| self.chip8.call_stack().len() <= depth) {
+                self.running = None;
+            }
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if self.running.take().is_some() {
+            self.message = format!("paused at {:#05X}", self.chip8.pc());
+        }
+    }
+
+    /// Executes one instruction, returns whether execution stopped.
+    fn step(&mut self) -> bool {
+        let (cycle, timing) = (self.cycle, &self.config.timing);
+        let result = self
+            .debugger
+            .step_with(&mut self.chip8, |chip8| app::step(chip8, cycle, timing));
+
+        match result {
+            Ok(None) => {
+                self.cycle += 1;
+                false
+            }
+            Ok(Some(Stop::Breakpoint(address))) => {
+                self.message = format!("breakpoint at {:#05X}", address);
+                self.running = None;
+                true
+            }
+            Ok(Some(Stop::Watchpoint { address, .. })) => {
+                self.cycle += 1;
+                self.message = format!("watchpoint hit at {:#05X}", address);
+                self.running = None;
+                true
+            }
+            Err(e) => {
+                self.message = e.to_string();
+                self.running = None;
+                true
+            }
+        }
+    }
+
+    fn set_register(&mut self, register: &str, value: u16) {
+        match register {
+            "i" => self.chip8.set_i(value),
+            "pc" => self.chip8.set_pc(value),
+            "dt" => self.chip8.timers.set(Timer::Delay, value as u8),
+            "st" => self.chip8.timers.set(Timer::Sound, value as u8),
+            _ => match register
+                .strip_prefix('v')
+                .and_then(|idx| u8::from_str_radix(idx, 16).ok())
+            {
+                Some(idx) if (idx as usize) < libconfig::REGISTER_COUNT => {
+                    self.chip8.set_register(idx, value as u8)
+                }
+                _ => self.message = format!("unknown register '{}'", register),
+            },
+        }
+    }
+
+    fn instruction_at(&self, address: u16) -> Option<(u16, String)> {
+        let bytes = self.chip8.read_memory(address as usize, 2).ok()?;
+        let raw = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let text = Instruction::decode(raw).map_or_else(|| "??".to_string(), |i| i.to_string());
+        Some((raw, text))
+    }
+}
+
+fn panel(title: &str) -> Block<'_> {
+    Block::bordered().title(title)
+}
+
+fn draw(frame: &mut Frame, session: &Session, command_line: &str) {
+    let [main, bar] =
+        Layout::vertical([Constraint::Min(20), Constraint::Length(4)]).areas(frame.area());
+    let [code, state, right] = Layout::horizontal([
+        Constraint::Length(36),
+        Constraint::Length(22),
+        Constraint::Min(66),
+    ])
+    .areas(main);
+    let [registers, stack] =
+        Layout::vertical([Constraint::Length(14), Constraint::Min(3)]).areas(state);
+    let [display, memory] =
+        Layout::vertical([Constraint::Length(18), Constraint::Min(4)]).areas(right);
+
+    // disassembly around PC
+    let pc = session.chip8.pc();
+    let before = (code.height / 3).min(pc / 2);
+    let breakpoints: Vec<u16> = session.debugger.breakpoints().collect();
+    let lines: Vec<Line> = (0..code.height.saturating_sub(2))
+        .map(|row| pc.wrapping_sub(before * 2).wrapping_add(row * 2))
+        .filter_map(|address| {
+            let (raw, text) = session.instruction_at(address)?;
+            let marker = match (address == pc, breakpoints.contains(&address)) {
+                (true, _) => ">",
+                (false, true) => "*",
+                _ => " ",
+            };
+            let line = session
+                .listing
+                .as_ref()
+                .and_then(|listing| listing.line_for(address))
+                .map_or(String::new(), |line| format!(" ;{}", line));
+            let style = if address == pc {
+                Style::new().add_modifier(Modifier::REVERSED)
+            } else if breakpoints.contains(&address) {
+                Style::new().fg(Color::Red)
+            } else {
+                Style::new()
+            };
+            Some(Line::styled(
+                format!("{}{:03X} {:04X} {}{}", marker, address, raw, text, line),
+                style,
+            ))
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines).block(panel("Code")), code);
+
+    // registers and timers
+    let chip8 = &session.chip8;
+    let mut lines: Vec<Line> = (0..8u8)
+        .map(|idx| {
+            Line::raw(format!(
+                "V{:X} {:02X}    V{:X} {:02X}",
+                idx,
+                chip8.register(idx),
+                idx + 8,
+                chip8.register(idx + 8)
+            ))
+        })
+        .collect();
+    lines.extend([
+        Line::raw(""),
+        Line::raw(format!("I  {:03X}  PC {:03X}", chip8.i(), pc)),
+        Line::raw(format!(
+            "DT {:02X}   ST {:02X}",
+            chip8.timers.get(Timer::Delay),
+            chip8.timers.get(Timer::Sound)
+        )),
+        Line::raw(format!("cycle {}", session.cycle)),
+    ]);
+    frame.render_widget(Paragraph::new(lines).block(panel("Registers")), registers);
+
+    let lines: Vec<Line> = chip8
+        .call_stack()
+        .iter()
+        .rev()
+        .map(|address| Line::raw(format!("{:03X}", address)))
+        .collect();
+    let title = format!("Stack ({})", chip8.call_stack().len());
+    frame.render_widget(Paragraph::new(lines).block(panel(&title)), stack);
+
+    let lines: Vec<Line> = tui::half_blocks(chip8.display.dump())
+        .into_iter()
+        .map(Line::raw)
+        .collect();
+    frame.render_widget(Paragraph::new(lines).block(panel("Display")), display);
+
+    // memory at I, or at the chosen address
+    let start = session.memory_view.unwrap_or(chip8.i()) as usize & !0x7;
+    let lines: Vec<Line> = (0..memory.height.saturating_sub(2) as usize)
+        .map(|row| start + row * 8)
+        .take_while(|&address| address < libconfig::MEMORY_SIZE)
+        .map(|address| {
+            let bytes = chip8.read_memory(address, 8).unwrap_or_default();
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                .collect();
+            Line::raw(format!("{:03X}  {}  {}", address, hex.join(" "), ascii))
+        })
+        .collect();
+    let title = match session.memory_view {
+        Some(_) => "Memory".to_string(),
+        None => "Memory at I".to_string(),
+    };
+    frame.render_widget(Paragraph::new(lines).block(panel(&title)), memory);
+
+    let status = if session.is_running() {
+        Span::styled("running, Esc pauses", Style::new().fg(Color::Green))
+    } else {
+        Span::raw(session.message.as_str())
+    };
+    let lines = vec![Line::from(status), Line::raw(format!("> {}", command_line))];
+    frame.render_widget(Paragraph::new(lines).block(panel("Command")), bar);
+}
+
+/// Runs the debugger until `quit`.
+pub fn run(chip8: Chip8, config: Config, listing: Option<Listing>) -> anyhow::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, Session::new(chip8, config, listing));
+    ratatui::restore();
+
+    result
+}
+
+fn run_loop(terminal: &mut ratatui::DefaultTerminal, mut session: Session) -> anyhow::Result<()> {
+    let cpu_tick = session.config.timing.cpu_tick_duration();
+    let frame_duration = session.config.timing.timer_tick_duration();
+    let mut command_line = String::new();
+    let mut last_command = String::new();
+    let mut last_cpu_tick = Instant::now();
+
+    while !session.quit {
+        terminal.draw(|frame| draw(frame, &session, &command_line))?;
+
+        let timeout = if session.is_running() {
+            frame_duration
+        } else {
+            Duration::from_secs(1)
+        };
+        if event::poll(timeout)?
+            && let Event::Key(key) = event::read()?
+            && key.kind != KeyEventKind::Release
+        {
+            match key.code {
+                KeyCode::Esc => session.pause(),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    session.pause()
+                }
+                KeyCode::Char(c) => command_line.push(c),
+                KeyCode::Backspace => {
+                    command_line.pop();
+                }
+                KeyCode::Enter => {
+                    // empty line repeats the last command
+                    let line = if command_line.trim().is_empty() {
+                        last_command.clone()
+                    } else {
+                        std::mem::take(&mut command_line)
+                    };
+                    match parse_command(&line) {
+                        Ok(command) => {
+                            session.execute(command);
+                            last_command = line;
+                            last_cpu_tick = Instant::now();
+                        }
+                        Err(e) => session.message = e,
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let now = Instant::now();
+        let due = (now.duration_since(last_cpu_tick).as_nanos() / cpu_tick.as_nanos()) as u64;
+        if session.is_running() {
+            session.advance(due);
+            last_cpu_tick += cpu_tick * due as u32;
+        } else {
+            last_cpu_tick = now;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: CALL 0x206
+    // 0x202: LD V1, 7
+    // 0x204: JP 0x204
+    // 0x206: LD V0, 5
+    // 0x208: RET
+    const ROM: [u8; 10] = [0x22, 0x06, 0x61, 0x07, 0x12, 0x04, 0x60, 0x05, 0x00, 0xee];
+
+    fn session() -> Session {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&ROM).unwrap();
+        Session::new(chip8, Config::default(), None)
+    }
+
+    #[test]
+    fn test_debug_parse_command() {
+        assert_eq!(Ok(Command::Step(1)), parse_command("s"));
+        assert_eq!(Ok(Command::Step(10)), parse_command("step 10"));
+        assert_eq!(Ok(Command::Break(0x202)), parse_command("b 0x202"));
+        assert_eq!(
+            Ok(Command::Write(0x300, vec![1, 0xff])),
+            parse_command("w 0x300 1 0xff")
+        );
+        assert_eq!(
+            Ok(Command::Set("v3".to_string(), 16)),
+            parse_command("set V3 16")
+        );
+        assert_eq!(Ok(Command::Memory(None)), parse_command("m i"));
+        assert!(parse_command("b").is_err());
+        assert!(parse_command("w 0x300 256").is_err());
+        assert!(parse_command("press 16").is_err());
+        assert!(parse_command("jump").is_err());
+    }
+    #[test]
+    fn test_debug_session_step_and_break() {
+        let mut session = session();
+        session.execute(Command::Step(2));
+        assert_eq!(0x208, session.chip8.pc());
+        assert_eq!(1, session.chip8.call_stack().len());
+
+        session.execute(Command::Break(0x204));
+        session.execute(Command::Continue);
+        session.advance(100);
+        assert!(!session.is_running());
+        assert_eq!(0x204, session.chip8.pc());
+        assert_eq!(7, session.chip8.register(1));
+    }
+    #[test]
+    fn test_debug_session_next_and_edit() {
+        let mut session = session();
+        session.execute(Command::Next);
+        session.advance(100);
+        assert_eq!(0x202, session.chip8.pc());
+        assert_eq!(5, session.chip8.register(0));
+
+        session.execute(Command::Set("pc".to_string(), 0x200));
+        session.execute(Command::Write(0x206, vec![0x60, 0x09]));
+        session.execute(Command::Step(2));
+        assert_eq!(9, session.chip8.register(0));
+    }
+}
@@ -0,0 +1,166 @@
+//! Gamepad input through gilrs, hotplugged gamepads included.
+
+use crate::config::GamepadConfig;
+use gilrs::{Axis, Button, Gilrs};
+use std::collections::BTreeMap;
+
+/// Gamepad input bound to a keypad key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Button(Button),
+    /// Stick pushed past the deadzone, towards the sign of `direction`.
+    Stick {
+        axis: Axis,
+        direction: f32,
+    },
+}
+
+const BUTTONS: [(&str, Button); 19] = [
+    ("South", Button::South),
+    ("East", Button::East),
+    ("North", Button::North),
+    ("West", Button::West),
+    ("C", Button::C),
+    ("Z", Button::Z),
+    ("LeftTrigger", Button::LeftTrigger),
+    ("LeftTrigger2", Button::LeftTrigger2),
+    ("RightTrigger", Button::RightTrigger),
+    ("RightTrigger2", Button::RightTrigger2),
+    ("Select", Button::Select),
+    ("Start", Button::Start),
+    ("Mode", Button::Mode),
+    ("LeftThumb", Button::LeftThumb),
+    ("RightThumb", Button::RightThumb),
+    ("DPadUp", Button::DPadUp),
+    ("DPadDown", Button::DPadDown),
+    ("DPadLeft", Button::DPadLeft),
+    ("DPadRight", Button::DPadRight),
+];
+
+impl Input {
+    /// Parses gilrs button names and stick directions like `LeftStickUp`.
+    pub fn parse(name: &str) -> Option<Self> {
+        if let Some((_, button)) = BUTTONS.iter().find(|(button, _)| *button == name) {
+            return Some(Input::Button(*button));
+        }
+
+        let (x, y, direction) = if let Some(direction) = name.strip_prefix("LeftStick") {
+            (Axis::LeftStickX, Axis::LeftStickY, direction)
+        } else {
+            let direction = name.strip_prefix("RightStick")?;
+            (Axis::RightStickX, Axis::RightStickY, direction)
+        };
+        // gilrs reports up as positive Y
+        let (axis, direction) = match direction {
+            "Up" => (y, 1.0),
+            "Down" => (y, -1.0),
+            "Left" => (x, -1.0),
+            "Right" => (x, 1.0),
+            _ => return None,
+        };
+
+        Some(Input::Stick { axis, direction })
+    }
+}
+
+/// Whether a stick at `value` counts as pushed towards `direction`.
+fn stick_pushed(value: f32, direction: f32, deadzone: f32) -> bool {
+    value * direction > deadzone
+}
+
+/// Resolves the mapping, rejecting unknown names and values above 0xF.
+pub fn parse_mapping(mapping: &BTreeMap<String, u8>) -> anyhow::Result<Vec<(Input, u8)>> {
+    mapping
+        .iter()
+        .map(|(name, &key)| {
+            let input = Input::parse(name)
+                .ok_or_else(|| anyhow::anyhow!("unknown gamepad input '{}'", name))?;
+            if key > 0xf {
+                anyhow::bail!("'{}' is bound to {:#x}, keypad keys are 0x0-0xf", name, key);
+            }
+            Ok((input, key))
+        })
+        .collect()
+}
+
+pub struct Gamepads {
+    gilrs: Gilrs,
+    mapping: Vec<(Input, u8)>,
+    deadzone: f32,
+    /// Keypad keys held on any connected gamepad.
+    held: [bool; 16],
+}
+
+impl Gamepads {
+    pub fn new(config: &GamepadConfig) -> anyhow::Result<Self> {
+        let gilrs = Gilrs::new().map_err(|e| anyhow::anyhow!("Failed to open gamepads: {}", e))?;
+
+        Ok(Self {
+            gilrs,
+            mapping: parse_mapping(&config.mapping)?,
+            deadzone: config.deadzone,
+            held: [false; 16],
+        })
+    }
+
+    pub fn is_held(&self, key: u8) -> bool {
+        self.held[key as usize]
+    }
+
+    /// Processes pending gamepad events, returns keypad keys whose state changed.
+    pub fn poll(&mut self) -> Vec<u8> {
+        // events update the gamepad state and report connections
+        while self.gilrs.next_event().is_some() {}
+
+        let mut held = [false; 16];
+        for (_, gamepad) in self.gilrs.gamepads() {
+            for (input, key) in &self.mapping {
+                held[*key as usize] |= match *input {
+                    Input::Button(button) => gamepad.is_pressed(button),
+                    Input::Stick { axis, direction } => {
+                        stick_pushed(gamepad.value(axis), direction, self.deadzone)
+                    }
+                };
+            }
+        }
+
+        let changed = (0..16u8)
+            .filter(|&key| held[key as usize] != self.held[key as usize])
+            .collect();
+        self.held = held;
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_gamepad_parse() {
+        assert_eq!(Some(Input::Button(Button::South)), Input::parse("South"));
+        assert_eq!(
+            Some(Input::Stick {
+                axis: Axis::RightStickX,
+                direction: -1.0
+            }),
+            Input::parse("RightStickLeft")
+        );
+        assert_eq!(None, Input::parse("LeftStickSideways"));
+        assert_eq!(None, Input::parse("Unknown"));
+
+        assert!(parse_mapping(&Config::default().gamepad.mapping).is_ok());
+        let mut mapping = BTreeMap::new();
+        mapping.insert("DPadUp".to_string(), 0x10);
+        assert!(parse_mapping(&mapping).is_err());
+    }
+    #[test]
+    fn test_gamepad_deadzone() {
+        assert!(stick_pushed(0.8, 1.0, 0.5));
+        assert!(!stick_pushed(0.3, 1.0, 0.5));
+        assert!(!stick_pushed(0.8, -1.0, 0.5));
+        assert!(stick_pushed(-0.8, -1.0, 0.5));
+    }
+}
@@ -0,0 +1,663 @@
+//! GDB remote serial protocol stub.
+//!
+//! Registers are V0 to VF, I, PC and SP (call stack depth), target memory is
+//! the whole 4 KiB address space. Both gdb and lldb can attach with
+//! `target remote localhost:<port>` / `gdb-remote <port>`.
+
+use libchip8::{
+    Chip8, ChipError, config as libconfig,
+    debugger::{Debugger, Stop, WatchKind, Watchpoint},
+};
+use std::{
+    fmt::Write as _,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+/// Register numbers as exposed in the target description.
+const REG_I: usize = libconfig::REGISTER_COUNT;
+const REG_PC: usize = REG_I + 1;
+const REG_SP: usize = REG_PC + 1;
+
+/// Input from the debugger connection.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Input {
+    Packet(String),
+    /// Ctrl-C sent while the target runs.
+    Interrupt,
+    /// Packet with a bad checksum, asks for retransmission.
+    Corrupt,
+}
+
+/// Splits the incoming byte stream into packets.
+#[derive(Default)]
+pub struct PacketParser {
+    buffer: Vec<u8>,
+}
+
+impl PacketParser {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Input> {
+        self.buffer.extend_from_slice(bytes);
+        let mut inputs = Vec::new();
+
+        loop {
+            match self.buffer.first() {
+                None => break,
+                Some(0x03) => {
+                    self.buffer.remove(0);
+                    inputs.push(Input::Interrupt);
+                }
+                Some(b'$') => {
+                    let Some(end) = self.buffer.iter().position(|&b| b == b'#') else {
+                        break;
+                    };
+                    if self.buffer.len() < end + 3 {
+                        break;
+                    }
+
+                    let data = &self.buffer[1..end];
+                    let checksum = std::str::from_utf8(&self.buffer[end + 1..end + 3])
+                        .ok()
+                        .and_then(|text| u8::from_str_radix(text, 16).ok());
+                    inputs.push(match checksum {
+                        Some(sum) if sum == checksum_of(data) => {
+                            Input::Packet(String::from_utf8_lossy(data).into_owned())
+                        }
+                        _ => Input::Corrupt,
+                    });
+                    self.buffer.drain(..end + 3);
+                }
+                // acknowledgements and noise
+                Some(_) => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+
+        inputs
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+/// Frames a reply as `$data#checksum`.
+pub fn encode(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum_of(data.as_bytes()))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Halted,
+    Running,
+    Stepping,
+    /// Debugger went away, the machine runs freely.
+    Detached,
+}
+
+/// Protocol state independent of the transport.
+pub struct Session {
+    debugger: Debugger,
+    state: State,
+    stopped_at_breakpoint: bool,
+    killed: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    /// Creates session with the machine halted.
+    pub fn new() -> Self {
+        Session {
+            debugger: Debugger::new(),
+            state: State::Halted,
+            stopped_at_breakpoint: false,
+            killed: false,
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.state == State::Halted
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed
+    }
+
+    /// Handles a packet, returns the reply if one is due right away.
+    pub fn handle(&mut self, packet: &str, chip8: &mut Chip8) -> Option<String> {
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => "S05".to_string(),
+            Some(b'g') => read_registers(chip8),
+            Some(b'G') => ok_or_error(write_registers(chip8, &packet[1..])),
+            Some(b'p') => usize::from_str_radix(&packet[1..], 16)
+                .ok()
+                .and_then(|reg| read_register(chip8, reg))
+                .unwrap_or_else(|| "E01".to_string()),
+            Some(b'P') => ok_or_error(write_register(chip8, &packet[1..])),
+            Some(b'm') => read_memory(chip8, &packet[1..]).unwrap_or_else(|| "E01".to_string()),
+            Some(b'M') => ok_or_error(write_memory(chip8, &packet[1..])),
+            Some(b'c') | Some(b's') => {
+                if let Some(address) = parse_hex(&packet[1..]) {
+                    chip8.set_pc(address as u16);
+                }
+                if self.stopped_at_breakpoint {
+                    self.debugger.resume();
+                }
+                self.state = if packet.starts_with('c') {
+                    State::Running
+                } else {
+                    State::Stepping
+                };
+                return None;
+            }
+            Some(b'Z') | Some(b'z') => ok_or_error(self.set_point(packet)),
+            Some(b'H') => "OK".to_string(),
+            Some(b'k') => {
+                self.killed = true;
+                self.detach();
+                return None;
+            }
+            Some(b'D') => {
+                self.detach();
+                "OK".to_string()
+            }
+            _ => query(packet),
+        };
+
+        Some(reply)
+    }
+
+    /// Executes one instruction with `exec` unless halted.
+    ///
+    /// Returns whether the instruction ran and the stop reply, if execution stopped.
+    pub fn execute<F>(&mut self, chip8: &mut Chip8, exec: F) -> (bool, Option<String>)
+    where
+        F: FnOnce(&mut Chip8) -> Result<(), ChipError>,
+    {
+        match self.state {
+            State::Halted => return (false, None),
+            State::Detached => {
+                // no client to report to, stop the same way an attached one would see
+                return match exec(chip8) {
+                    Ok(()) => (true, None),
+                    Err(e) => {
+                        eprintln!("Execution stopped: {}", e);
+                        self.halt("S04");
+                        (false, None)
+                    }
+                };
+            }
+            State::Running | State::Stepping => (),
+        }
+
+        self.stopped_at_breakpoint = false;
+        match self.debugger.step_with(chip8, exec) {
+            Ok(None) if self.state == State::Running => (true, None),
+            Ok(None) => (true, Some(self.halt("S05"))),
+            Ok(Some(Stop::Breakpoint(_))) => {
+                self.stopped_at_breakpoint = true;
+                (false, Some(self.halt("T05swbreak:;")))
+            }
+            Ok(Some(Stop::Watchpoint { kind, address })) => {
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                (
+                    true,
+                    Some(self.halt(&format!("T05{}:{:x};", name, address))),
+                )
+            }
+            // SIGILL
+            Err(_) => (false, Some(self.halt("S04"))),
+        }
+    }
+
+    /// Stops a running machine on Ctrl-C.
+    pub fn interrupt(&mut self) -> Option<String> {
+        matches!(self.state, State::Running | State::Stepping).then(|| self.halt("S02"))
+    }
+
+    pub fn detach(&mut self) {
+        self.debugger.clear();
+        self.state = State::Detached;
+    }
+
+    fn halt(&mut self, reply: &str) -> String {
+        self.state = State::Halted;
+        reply.to_string()
+    }
+
+    fn set_point(&mut self, packet: &str) -> Option<()> {
+        let insert = packet.starts_with('Z');
+        let mut fields = packet[1..].split(',');
+        let kind = fields.next()?;
+        let address = parse_hex(fields.next()?)? as u16;
+        let len = parse_hex(fields.next()?.split(';').next()?)? as u16;
+
+        let kind = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(address);
+                } else {
+                    self.debugger.remove_breakpoint(address);
+                }
+                return Some(());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return None,
+        };
+        let watchpoint = Watchpoint { address, len, kind };
+        if insert {
+            self.debugger.add_watchpoint(watchpoint);
+        } else {
+            self.debugger.remove_watchpoint(watchpoint);
+        }
+
+        Some(())
+    }
+}
+
+/// General queries, unsupported ones get an empty reply.
+fn query(packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+        return "PacketSize=1000;qXfer:features:read+;swbreak+".to_string();
+    }
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        return read_target_xml(range).unwrap_or_else(|| "E01".to_string());
+    }
+
+    match packet {
+        "qAttached" => "1",
+        "qC" => "QC1",
+        "qfThreadInfo" => "m1",
+        "qsThreadInfo" => "l",
+        _ => "",
+    }
+    .to_string()
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    match result {
+        Some(()) => "OK".to_string(),
+        None => "E01".to_string(),
+    }
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(text.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+/// Target description, registers are numbered in this order.
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.chip8.core\">\n",
+    );
+    for idx in 0..libconfig::REGISTER_COUNT {
+        writeln!(
+            xml,
+            "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\" regnum=\"{}\"/>",
+            idx, idx
+        )
+        .unwrap();
+    }
+    writeln!(
+        xml,
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\" regnum=\"{}\"/>",
+        REG_I
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\" regnum=\"{}\" generic=\"pc\"/>",
+        REG_PC
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "<reg name=\"sp\" bitsize=\"8\" type=\"uint8\" regnum=\"{}\"/>",
+        REG_SP
+    )
+    .unwrap();
+    xml.push_str("</feature>\n</target>\n");
+
+    xml
+}
+
+fn read_target_xml(range: &str) -> Option<String> {
+    let (offset, len) = range.split_once(',')?;
+    let (offset, len) = (parse_hex(offset)?, parse_hex(len)?);
+    let xml = target_xml();
+
+    let start = offset.min(xml.len());
+    let end = (start + len).min(xml.len());
+    let prefix = if end == xml.len() { 'l' } else { 'm' };
+
+    Some(format!("{}{}", prefix, &xml[start..end]))
+}
+
+fn read_register(chip8: &Chip8, reg: usize) -> Option<String> {
+    match reg {
+        _ if reg < libconfig::REGISTER_COUNT => Some(format!("{:02x}", chip8.register(reg as u8))),
+        REG_I => Some(hex_u16(chip8.i())),
+        REG_PC => Some(hex_u16(chip8.pc())),
+        REG_SP => Some(format!("{:02x}", chip8.call_stack().len())),
+        _ => None,
+    }
+}
+
+fn read_registers(chip8: &Chip8) -> String {
+    (0..=REG_SP)
+        .filter_map(|reg| read_register(chip8, reg))
+        .collect()
+}
+
+/// 16-bit registers are sent little-endian.
+fn hex_u16(value: u16) -> String {
+    value
+        .to_le_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn set_register(chip8: &mut Chip8, reg: usize, bytes: &[u8]) -> Option<()> {
+    match (reg, bytes) {
+        (_, &[value]) if reg < libconfig::REGISTER_COUNT => chip8.set_register(reg as u8, value),
+        (REG_I, &[lo, hi]) => chip8.set_i(u16::from_le_bytes([lo, hi])),
+        (REG_PC, &[lo, hi]) => chip8.set_pc(u16::from_le_bytes([lo, hi])),
+        // the call stack can only change by executing CALL and RET
+        (REG_SP, &[sp]) if sp as usize == chip8.call_stack().len() => (),
+        _ => return None,
+    }
+
+    Some(())
+}
+
+fn write_register(chip8: &mut Chip8, args: &str) -> Option<()> {
+    let (reg, value) = args.split_once('=')?;
+    set_register(chip8, parse_hex(reg)?, &decode_hex(value)?)
+}
+
+fn write_registers(chip8: &mut Chip8, data: &str) -> Option<()> {
+    let bytes = decode_hex(data)?;
+    if bytes.len() < libconfig::REGISTER_COUNT + 4 {
+        return None;
+    }
+
+    let (v, rest) = bytes.split_at(libconfig::REGISTER_COUNT);
+    for (idx, &value) in v.iter().enumerate() {
+        chip8.set_register(idx as u8, value);
+    }
+    set_register(chip8, REG_I, &rest[0..2])?;
+    set_register(chip8, REG_PC, &rest[2..4])
+}
+
+fn read_memory(chip8: &Chip8, args: &str) -> Option<String> {
+    let (address, len) = args.split_once(',')?;
+    let (address, len) = (parse_hex(address)?, parse_hex(len)?);
+    let bytes = chip8.read_memory(address, len).ok()?;
+
+    Some(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn write_memory(chip8: &mut Chip8, args: &str) -> Option<()> {
+    let (range, data) = args.split_once(':')?;
+    let (address, len) = range.split_once(',')?;
+    let (address, bytes) = (parse_hex(address)?, decode_hex(data)?);
+    if bytes.len() != parse_hex(len)? {
+        return None;
+    }
+
+    chip8.write_memory(address, &bytes).ok()
+}
+
+/// Connection to a debugger, polled from the event loop.
+pub struct GdbServer {
+    stream: TcpStream,
+    input: Receiver<Vec<u8>>,
+    parser: PacketParser,
+    session: Session,
+}
+
+impl GdbServer {
+    /// Listens on localhost and blocks until a debugger attaches.
+    pub fn accept(port: u16) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Waiting for debugger on 127.0.0.1:{}", port);
+        let (stream, address) = listener.accept()?;
+        println!("Debugger attached from {}", address);
+
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while let Ok(len @ 1..) = reader.read(&mut buffer) {
+                if sender.send(buffer[..len].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(GdbServer {
+            stream,
+            input,
+            parser: PacketParser::default(),
+            session: Session::new(),
+        })
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.session.is_halted()
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.session.is_killed()
+    }
+
+    /// Handles everything the debugger sent since the last poll.
+    pub fn poll(&mut self, chip8: &mut Chip8) {
+        loop {
+            let bytes = match self.input.try_recv() {
+                Ok(bytes) => bytes,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.session.detach();
+                    break;
+                }
+            };
+
+            for input in self.parser.push(&bytes) {
+                match input {
+                    Input::Packet(packet) => {
+                        self.send_raw("+");
+                        if let Some(reply) = self.session.handle(&packet, chip8) {
+                            self.send(&reply);
+                        }
+                    }
+                    Input::Interrupt => {
+                        if let Some(reply) = self.session.interrupt() {
+                            self.send(&reply);
+                        }
+                    }
+                    Input::Corrupt => self.send_raw("-"),
+                }
+            }
+        }
+    }
+
+    /// Executes one instruction with `exec`, see `Session::execute`.
+    pub fn execute<F>(&mut self, chip8: &mut Chip8, exec: F) -> bool
+    where
+        F: FnOnce(&mut Chip8) -> Result<(), ChipError>,
+    {
+        let (executed, reply) = self.session.execute(chip8, exec);
+        if let Some(reply) = reply {
+            self.send(&reply);
+        }
+
+        executed
+    }
+
+    fn send(&mut self, reply: &str) {
+        self.send_raw(&encode(reply));
+    }
+
+    fn send_raw(&mut self, data: &str) {
+        if self.stream.write_all(data.as_bytes()).is_err() {
+            self.session.detach();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: LD V0, 1
+    // 0x202: LD I, 0x300
+    // 0x204: LD [I], V0
+    // 0x206: JP 0x200
+    const ROM: [u8; 8] = [0x60, 0x01, 0xa3, 0x00, 0xf0, 0x55, 0x12, 0x00];
+
+    fn chip8() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&ROM).unwrap();
+        chip8
+    }
+
+    fn run(session: &mut Session, chip8: &mut Chip8) -> Option<String> {
+        for _ in 0..100 {
+            if let (_, Some(reply)) = session.execute(chip8, Chip8::tick) {
+                return Some(reply);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_gdb_packet_parser() {
+        let mut parser = PacketParser::default();
+        assert_eq!(vec![Input::Interrupt], parser.push(b"+\x03$g#6"));
+        assert_eq!(vec![Input::Packet("g".to_string())], parser.push(b"7"));
+        assert_eq!(vec![Input::Corrupt], parser.push(b"$g#00"));
+        assert_eq!("$OK#9a", encode("OK"));
+    }
+    #[test]
+    fn test_gdb_registers_and_memory() {
+        let mut chip8 = chip8();
+        let mut session = Session::new();
+
+        let registers = session.handle("g", &mut chip8).unwrap();
+        assert_eq!((libconfig::REGISTER_COUNT + 5) * 2, registers.len());
+        assert!(registers.ends_with("0000000200"));
+
+        assert_eq!(
+            Some("OK".to_string()),
+            session.handle("P11=0602", &mut chip8)
+        );
+        assert_eq!(0x206, chip8.pc());
+        assert_eq!(Some("0602".to_string()), session.handle("p11", &mut chip8));
+
+        assert_eq!(
+            Some("6001a300".to_string()),
+            session.handle("m200,4", &mut chip8)
+        );
+        assert_eq!(
+            Some("OK".to_string()),
+            session.handle("M300,2:abcd", &mut chip8)
+        );
+        assert_eq!(0xcd, chip8.memory()[0x301]);
+        assert_eq!(
+            Some("E01".to_string()),
+            session.handle("mfff,2", &mut chip8)
+        );
+        assert_eq!(
+            Some("0000".to_string()),
+            session.handle("mffe,2", &mut chip8)
+        );
+        assert_eq!(
+            Some("E01".to_string()),
+            session.handle("mffffffffffffffff,10", &mut chip8)
+        );
+        assert_eq!(
+            Some("E01".to_string()),
+            session.handle("Mffffffffffffffff,1:00", &mut chip8)
+        );
+    }
+    #[test]
+    fn test_gdb_breakpoints_and_watchpoints() {
+        let mut chip8 = chip8();
+        let mut session = Session::new();
+
+        assert_eq!(None, session.execute(&mut chip8, Chip8::tick).1);
+        session.handle("Z0,204,2", &mut chip8);
+        assert_eq!(None, session.handle("c", &mut chip8));
+        assert_eq!(
+            Some("T05swbreak:;".to_string()),
+            run(&mut session, &mut chip8)
+        );
+        assert_eq!(0x204, chip8.pc());
+
+        session.handle("z0,204,2", &mut chip8);
+        session.handle("Z2,300,1", &mut chip8);
+        session.handle("c", &mut chip8);
+        assert_eq!(
+            Some("T05watch:300;".to_string()),
+            run(&mut session, &mut chip8)
+        );
+        assert_eq!(0x206, chip8.pc());
+
+        session.handle("s", &mut chip8);
+        assert_eq!(Some("S05".to_string()), run(&mut session, &mut chip8));
+        assert_eq!(0x200, chip8.pc());
+    }
+    #[test]
+    fn test_gdb_detached_error_halts() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0xff, 0xff]).unwrap();
+        let mut session = Session::new();
+        session.detach();
+
+        assert_eq!((false, None), session.execute(&mut chip8, Chip8::tick));
+        assert_eq!((false, None), session.execute(&mut chip8, Chip8::tick));
+        assert_eq!(0x200, chip8.pc());
+    }
+    #[test]
+    fn test_gdb_target_xml_chunks() {
+        let xml = target_xml();
+        let first = query("qXfer:features:read:target.xml:0,10");
+        assert_eq!(format!("m{}", &xml[..16]), first);
+
+        let rest = query(&format!(
+            "qXfer:features:read:target.xml:10,{:x}",
+            xml.len()
+        ));
+        assert_eq!(format!("l{}", &xml[16..]), rest);
+    }
+}
@@ -0,0 +1,33 @@
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+/// Emulator actions bound to keys outside the keypad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    Pause,
+    Reset,
+    /// Runs one frame while paused.
+    FrameAdvance,
+    /// Runs faster while held.
+    FastForward,
+    SlowMotion,
+    Fullscreen,
+    Mute,
+    Screenshot,
+    ToggleRecording,
+}
+
+/// Maps physical keyboard keys to emulator hotkeys
+pub fn map_to_hotkey(keycode: PhysicalKey) -> Option<Hotkey> {
+    match keycode {
+        PhysicalKey::Code(KeyCode::F1) => Some(Hotkey::Pause),
+        PhysicalKey::Code(KeyCode::F2) => Some(Hotkey::Reset),
+        PhysicalKey::Code(KeyCode::F3) => Some(Hotkey::FrameAdvance),
+        PhysicalKey::Code(KeyCode::F4) => Some(Hotkey::SlowMotion),
+        PhysicalKey::Code(KeyCode::Tab) => Some(Hotkey::FastForward),
+        PhysicalKey::Code(KeyCode::F8) => Some(Hotkey::Mute),
+        PhysicalKey::Code(KeyCode::F9) => Some(Hotkey::Screenshot),
+        PhysicalKey::Code(KeyCode::F10) => Some(Hotkey::ToggleRecording),
+        PhysicalKey::Code(KeyCode::F11) => Some(Hotkey::Fullscreen),
+        _ => None,
+    }
+}
@@ -0,0 +1,131 @@
+//! ROM report printed by `info`.
+
+use crate::rom;
+use libchip8::{
+    analysis::{self, Platform},
+    config as libconfig,
+};
+use std::fmt::Write;
+
+/// Addresses listed per line before the rest are counted.
+const MAX_ADDRESSES: usize = 8;
+
+fn addresses(addresses: &[u16]) -> String {
+    let mut list: Vec<String> = addresses
+        .iter()
+        .take(MAX_ADDRESSES)
+        .map(|address| format!("{:#05X}", address))
+        .collect();
+    if addresses.len() > MAX_ADDRESSES {
+        list.push(format!("and {} more", addresses.len() - MAX_ADDRESSES));
+    }
+
+    list.join(", ")
+}
+
+/// Describes the ROM named `name`: size, hash, opcodes and quirks its reachable code needs.
+pub fn report(name: &str, rom: &[u8]) -> String {
+    let capacity = libconfig::MEMORY_SIZE - libconfig::PROGRAM_START_ADDRESS;
+    let analysis = analysis::analyze(rom);
+    let platform = analysis.platform();
+    let mut out = String::new();
+
+    writeln!(out, "ROM:          {}", name).unwrap();
+    let fits = if rom.len() <= capacity {
+        format!("fits, {} bytes free", capacity - rom.len())
+    } else {
+        format!("does not fit, {} bytes too large", rom.len() - capacity)
+    };
+    writeln!(out, "Size:         {} bytes ({})", rom.len(), fits).unwrap();
+    writeln!(out, "Hash:         {:016x}", rom::hash(rom)).unwrap();
+    writeln!(out, "Platform:     {}", platform).unwrap();
+    writeln!(
+        out,
+        "Reachable:    {} instructions",
+        analysis.reachable.len()
+    )
+    .unwrap();
+
+    writeln!(out, "\nOpcodes:").unwrap();
+    for (pattern, (platform, count)) in &analysis.opcodes {
+        let tag = match platform {
+            Platform::Chip8 => String::new(),
+            platform => format!(" ({})", platform),
+        };
+        writeln!(out, "  {:<10} {:>5}{}", pattern, count, tag).unwrap();
+    }
+
+    if !analysis.undecodable.is_empty() {
+        writeln!(out, "\nUndecodable words in reachable code:").unwrap();
+        for (address, word) in &analysis.undecodable {
+            writeln!(out, "  {:#05X}: {:04X}", address, word).unwrap();
+        }
+    }
+    if !analysis.dynamic_jumps.is_empty() {
+        writeln!(
+            out,
+            "\nJumps through V0, code they lead to may be missing: {}",
+            addresses(&analysis.dynamic_jumps)
+        )
+        .unwrap();
+    }
+    if !analysis.leaves_rom.is_empty() {
+        writeln!(
+            out,
+            "\nInstructions leading outside the ROM: {}",
+            addresses(&analysis.leaves_rom)
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "\nQuirk-sensitive instructions:").unwrap();
+    if analysis.quirks.is_empty() {
+        writeln!(out, "  none, the ROM behaves the same under every profile").unwrap();
+    }
+    for (quirk, found) in &analysis.quirks {
+        writeln!(
+            out,
+            "  {} ({}): {}",
+            quirk.description(),
+            found.len(),
+            addresses(found)
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "\nSuggested profile: {}", platform.profile()).unwrap();
+    if platform != Platform::Chip8 {
+        writeln!(
+            out,
+            "This emulator only runs CHIP-8 with COSMAC VIP quirks, {} opcodes will fail.",
+            platform
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info_report() {
+        // LD V1, 2; SHL V1, V2; HIGH; JP 0x206
+        let rom = [0x61, 0x02, 0x81, 0x2e, 0x00, 0xff, 0x12, 0x06];
+        let report = report("test.ch8", &rom);
+
+        assert!(report.contains("Size:         8 bytes (fits, 3576 bytes free)"));
+        assert!(report.contains(&format!("{:016x}", rom::hash(&rom))));
+        assert!(report.contains("Platform:     SUPER-CHIP"));
+        assert!(report.contains("00FF           1 (SUPER-CHIP)"));
+        assert!(report.contains("8xy6/8xyE with x != y shift Vy or Vx (1): 0x202"));
+        assert!(report.contains("Suggested profile: SUPER-CHIP 1.1"));
+        assert!(!report.contains("Undecodable"));
+
+        let report = super::report("large.ch8", &[0x00; 4000]);
+        assert!(report.contains("does not fit, 416 bytes too large"));
+        assert!(report.contains("Undecodable words in reachable code:\n  0x200: 0000"));
+    }
+}
@@ -1,24 +1,156 @@
+use crate::{config::KeymapConfig, hotkeys};
+use crossterm::event;
+use serde::{
+    Deserialize,
+    de::value::{Error, StrDeserializer},
+};
+use std::collections::HashMap;
 use winit::keyboard::{KeyCode, PhysicalKey};
 
-/// Maps physical keyboard keys to Chip8 keys
-pub fn map_to_chip8(keycode: PhysicalKey) -> Option<u8> {
-    match keycode {
-        PhysicalKey::Code(KeyCode::Digit1) => Some(0x1),
-        PhysicalKey::Code(KeyCode::Digit2) => Some(0x2),
-        PhysicalKey::Code(KeyCode::Digit3) => Some(0x3),
-        PhysicalKey::Code(KeyCode::Digit4) => Some(0xc),
-        PhysicalKey::Code(KeyCode::KeyQ) => Some(0x4),
-        PhysicalKey::Code(KeyCode::KeyW) => Some(0x5),
-        PhysicalKey::Code(KeyCode::KeyE) => Some(0x6),
-        PhysicalKey::Code(KeyCode::KeyR) => Some(0xd),
-        PhysicalKey::Code(KeyCode::KeyA) => Some(0x7),
-        PhysicalKey::Code(KeyCode::KeyS) => Some(0x8),
-        PhysicalKey::Code(KeyCode::KeyD) => Some(0x9),
-        PhysicalKey::Code(KeyCode::KeyF) => Some(0xe),
-        PhysicalKey::Code(KeyCode::KeyZ) => Some(0xa),
-        PhysicalKey::Code(KeyCode::KeyX) => Some(0x0),
-        PhysicalKey::Code(KeyCode::KeyC) => Some(0xb),
-        PhysicalKey::Code(KeyCode::KeyV) => Some(0xf),
-        _ => None,
+/// Physical keyboard keys bound to Chip8 keys.
+#[derive(Clone, Debug)]
+pub struct Keymap(HashMap<KeyCode, u8>);
+
+impl Keymap {
+    /// Resolves winit key names, rejecting unknown names, hotkeys and values above 0xF.
+    pub fn new(config: &KeymapConfig) -> anyhow::Result<Self> {
+        let mut keymap = HashMap::new();
+        for (name, &key) in config {
+            let code = KeyCode::deserialize(StrDeserializer::<Error>::new(name))
+                .map_err(|_| anyhow::anyhow!("unknown key name '{}'", name))?;
+            if hotkeys::map_to_hotkey(PhysicalKey::Code(code)).is_some() {
+                anyhow::bail!("'{}' is reserved for a hotkey", name);
+            }
+            if key > 0xf {
+                anyhow::bail!("'{}' is bound to {:#x}, keypad keys are 0x0-0xf", name, key);
+            }
+            keymap.insert(code, key);
+        }
+
+        Ok(Self(keymap))
+    }
+
+    /// Maps physical keyboard keys to Chip8 keys
+    pub fn map(&self, keycode: PhysicalKey) -> Option<u8> {
+        match keycode {
+            PhysicalKey::Code(code) => self.0.get(&code).copied(),
+            PhysicalKey::Unidentified(_) => None,
+        }
+    }
+
+    /// Maps terminal keys to Chip8 keys through the key names they have on a US layout.
+    pub fn map_terminal(&self, code: event::KeyCode) -> Option<u8> {
+        terminal_key_code(code).and_then(|code| self.0.get(&code).copied())
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&crate::config::default_keymap()).expect("default keymap is valid")
+    }
+}
+
+/// Key typed to get a terminal key on a US layout, terminals only report characters.
+fn terminal_key_code(code: event::KeyCode) -> Option<KeyCode> {
+    let name = match code {
+        event::KeyCode::Char(c) if c.is_ascii_alphabetic() => {
+            format!("Key{}", c.to_ascii_uppercase())
+        }
+        event::KeyCode::Char(c) if c.is_ascii_digit() => format!("Digit{}", c),
+        event::KeyCode::Char(c) => match c {
+            ' ' => "Space",
+            ',' => "Comma",
+            '.' => "Period",
+            '/' => "Slash",
+            ';' => "Semicolon",
+            '\'' => "Quote",
+            '-' => "Minus",
+            '=' => "Equal",
+            '[' => "BracketLeft",
+            ']' => "BracketRight",
+            '\\' => "Backslash",
+            '`' => "Backquote",
+            _ => return None,
+        }
+        .to_string(),
+        event::KeyCode::Left => "ArrowLeft".to_string(),
+        event::KeyCode::Right => "ArrowRight".to_string(),
+        event::KeyCode::Up => "ArrowUp".to_string(),
+        event::KeyCode::Down => "ArrowDown".to_string(),
+        event::KeyCode::Enter => "Enter".to_string(),
+        event::KeyCode::Backspace => "Backspace".to_string(),
+        _ => return None,
+    };
+
+    KeyCode::deserialize(StrDeserializer::<Error>::new(&name)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn keymap(entries: &[(&str, u8)]) -> anyhow::Result<Keymap> {
+        Keymap::new(
+            &entries
+                .iter()
+                .map(|(name, key)| (name.to_string(), *key))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_keyboard_keymap() {
+        // AZERTY: two keys bound to the same keypad key
+        let keymap = keymap(&[("KeyQ", 0x7), ("KeyA", 0x4), ("Numpad4", 0x4)]).unwrap();
+        assert_eq!(Some(0x4), keymap.map(PhysicalKey::Code(KeyCode::KeyA)));
+        assert_eq!(Some(0x4), keymap.map(PhysicalKey::Code(KeyCode::Numpad4)));
+        assert_eq!(Some(0x7), keymap.map(PhysicalKey::Code(KeyCode::KeyQ)));
+        assert_eq!(None, keymap.map(PhysicalKey::Code(KeyCode::KeyW)));
+
+        let default = Keymap::default();
+        assert_eq!(Some(0xc), default.map(PhysicalKey::Code(KeyCode::Digit4)));
+        assert_eq!(Some(0x0), default.map(PhysicalKey::Code(KeyCode::KeyX)));
+    }
+    #[test]
+    fn test_keyboard_keymap_terminal() {
+        let keymap = keymap(&[("KeyA", 0x4), ("ArrowLeft", 0x7), ("Comma", 0x8)]).unwrap();
+        assert_eq!(Some(0x4), keymap.map_terminal(event::KeyCode::Char('A')));
+        assert_eq!(Some(0x7), keymap.map_terminal(event::KeyCode::Left));
+        assert_eq!(Some(0x8), keymap.map_terminal(event::KeyCode::Char(',')));
+        assert_eq!(None, keymap.map_terminal(event::KeyCode::Char('q')));
+
+        let default = Keymap::default();
+        assert_eq!(Some(0xc), default.map_terminal(event::KeyCode::Char('4')));
+        assert_eq!(Some(0xf), default.map_terminal(event::KeyCode::Char('v')));
+    }
+    #[test]
+    fn test_keyboard_keymap_invalid() {
+        assert!(keymap(&[("KeyQQ", 0x4)]).is_err());
+        assert!(keymap(&[("KeyQ", 0x10)]).is_err());
+        assert!(keymap(&[("F1", 0x4)]).is_err());
+    }
+    #[test]
+    fn test_keyboard_keymap_config() {
+        let mut config: Config =
+            toml::from_str(&toml::to_string(&Config::default()).unwrap()).unwrap();
+        assert_eq!(crate::config::default_keymap(), config.keymap);
+
+        config.rom_keymaps = toml::from_str(
+            r#"
+            "BRIX.ch8" = { ArrowLeft = 0x4, ArrowRight = 0x6 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some(&0x6),
+            config
+                .keymap_for(std::path::Path::new("roms/BRIX.ch8"))
+                .get("ArrowRight")
+        );
+        assert_eq!(
+            &config.keymap,
+            config.keymap_for(std::path::Path::new("roms/PONG.ch8"))
+        );
     }
 }
@@ -1,7 +1,20 @@
 pub mod app;
+pub mod audio;
+pub mod bench;
+pub mod capture;
 pub mod cli;
 pub mod commands;
 pub mod config;
+pub mod dap;
+pub mod debug;
+pub mod gamepad;
+pub mod gdb;
+pub mod hotkeys;
+pub mod info;
 pub mod keyboard;
+pub mod movie;
+pub mod phosphor;
 pub mod rom;
 pub mod sound;
+pub mod tui;
+pub mod watch;
@@ -0,0 +1,256 @@
+//! Input movies.
+//!
+//! A movie holds every key change together with the CPU cycle it happened at,
+//! the RNG seed and the configuration, so a session can be replayed exactly.
+
+use crate::{app, config::Config, rom::hash};
+use libchip8::{Chip8, audio::Audio};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Movie file format version.
+pub const VERSION: u32 = 1;
+
+/// Key change applied right before the instruction at `cycle` executes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEvent {
+    pub cycle: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Movie {
+    pub version: u32,
+    #[serde(with = "hex")]
+    pub seed: u64,
+    #[serde(with = "hex")]
+    pub rom_hash: u64,
+    /// Instructions executed during the recording.
+    pub cycles: u64,
+    /// Hash of the save state after the last cycle.
+    #[serde(with = "hex")]
+    pub state_hash: u64,
+    pub config: Config,
+    pub events: Vec<KeyEvent>,
+}
+
+impl Movie {
+    /// Loads movie from toml file.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Movie> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let movie: Movie = toml::from_str(&contents)?;
+
+        if movie.version != VERSION {
+            anyhow::bail!(
+                "Unsupported movie version {} in '{}'",
+                movie.version,
+                path.display()
+            );
+        }
+
+        Ok(movie)
+    }
+
+    /// Saves movie as toml file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        fs::write(path, toml::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    /// Creates the machine the movie was recorded on.
+    pub fn start(&self, rom: &[u8]) -> anyhow::Result<Chip8> {
+        if hash(rom) != self.rom_hash {
+            anyhow::bail!("ROM does not match the one the movie was recorded with");
+        }
+
+        let mut chip8 = Chip8::with_seed(self.seed);
+        chip8.load_rom(rom)?;
+
+        Ok(chip8)
+    }
+}
+
+/// Collects key changes while the emulator runs.
+pub struct Recorder {
+    movie: Movie,
+}
+
+impl Recorder {
+    pub fn new(seed: u64, rom: &[u8], config: Config) -> Self {
+        Recorder {
+            movie: Movie {
+                version: VERSION,
+                seed,
+                rom_hash: hash(rom),
+                cycles: 0,
+                state_hash: 0,
+                config,
+                events: Vec::new(),
+            },
+        }
+    }
+
+    pub fn record(&mut self, cycle: u64, key: u8, pressed: bool) {
+        self.movie.events.push(KeyEvent {
+            cycle,
+            key,
+            pressed,
+        });
+    }
+
+    /// Finishes the movie with the state reached after `cycles` instructions.
+    pub fn finish(mut self, chip8: &Chip8, cycles: u64) -> Movie {
+        self.movie.cycles = cycles;
+        self.movie.state_hash = state_hash(chip8);
+        self.movie
+    }
+}
+
+/// Feeds recorded key changes back into a machine.
+pub struct Playback {
+    movie: Movie,
+    next: usize,
+}
+
+impl Playback {
+    pub fn new(movie: Movie) -> Self {
+        Playback { movie, next: 0 }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// Applies every key change up to and including `cycle`.
+    pub fn apply(&mut self, chip8: &mut Chip8, cycle: u64) {
+        while let Some(event) = self.movie.events.get(self.next) {
+            if event.cycle > cycle {
+                break;
+            }
+            chip8.keyboard.set_key(event.key, event.pressed);
+            self.next += 1;
+        }
+    }
+
+    pub fn is_finished(&self, cycle: u64) -> bool {
+        cycle >= self.movie.cycles
+    }
+
+    /// Checks that the machine ended up in the recorded state.
+    pub fn verify(&self, chip8: &Chip8, cycle: u64) -> anyhow::Result<()> {
+        if cycle != self.movie.cycles {
+            anyhow::bail!(
+                "Replay stopped after {} of {} cycles",
+                cycle,
+                self.movie.cycles
+            );
+        }
+
+        let actual = state_hash(chip8);
+        if actual != self.movie.state_hash {
+            anyhow::bail!(
+                "Final state hash {:016x} does not match recorded {:016x}",
+                actual,
+                self.movie.state_hash
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Replays the whole movie without a window.
+pub fn replay(movie: &Movie, rom: &[u8]) -> anyhow::Result<Chip8> {
+    let mut chip8 = movie.start(rom)?;
+    let mut playback = Playback::new(movie.clone());
+
+    for cycle in 0..movie.cycles {
+        playback.apply(&mut chip8, cycle);
+        app::step(&mut chip8, cycle, &movie.config.timing)
+            .map_err(|e| anyhow::anyhow!("Replay failed at cycle {}: {}", cycle, e))?;
+    }
+
+    Ok(chip8)
+}
+
+/// Hash of the machine's save state.
+///
+/// The audio is left out, how much of it was played depends on the host and not on the movie.
+pub fn state_hash(chip8: &Chip8) -> u64 {
+    let mut chip8 = chip8.clone();
+    chip8.audio = Audio::new();
+    hash(&chip8.save_state())
+}
+
+/// Stores `u64` values as hex strings, toml integers are signed.
+mod hex {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:016x}", value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        u64::from_str_radix(&text, 16).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: LD V1, 0
+    // 0x202: SKNP V1      skip while key 0 is up
+    // 0x204: ADD V0, 1
+    // 0x206: RND V2, 0xFF
+    // 0x208: JP 0x202
+    const ROM: [u8; 10] = [0x61, 0x00, 0xe1, 0xa1, 0x70, 0x01, 0xc2, 0xff, 0x12, 0x02];
+
+    fn record() -> Movie {
+        let config = Config::default();
+        let mut chip8 = Chip8::with_seed(u64::MAX);
+        chip8.load_rom(&ROM).unwrap();
+
+        let mut recorder = Recorder::new(u64::MAX, &ROM, config.clone());
+        for cycle in 0..500 {
+            if cycle == 100 || cycle == 300 {
+                let pressed = cycle == 100;
+                chip8.keyboard.set_key(0, pressed);
+                recorder.record(cycle, 0, pressed);
+            }
+            app::step(&mut chip8, cycle, &config.timing).unwrap();
+        }
+        recorder.finish(&chip8, 500)
+    }
+
+    #[test]
+    fn test_movie_roundtrip() {
+        let movie = record();
+        let movie: Movie = toml::from_str(&toml::to_string(&movie).unwrap()).unwrap();
+        assert_eq!(u64::MAX, movie.seed);
+        assert_eq!(2, movie.events.len());
+
+        let mut chip8 = replay(&movie, &ROM).unwrap();
+        assert_ne!(0, chip8.register(0));
+        Playback::new(movie.clone())
+            .verify(&chip8, movie.cycles)
+            .unwrap();
+
+        // playing the audio leaves the hash alone
+        chip8.fill_buzzer(&mut [false; 100], 600);
+        assert_eq!(movie.state_hash, state_hash(&chip8));
+    }
+    #[test]
+    fn test_movie_detects_divergence() {
+        let mut movie = record();
+        movie.events[1].cycle += 1;
+        let chip8 = replay(&movie, &ROM).unwrap();
+        assert!(Playback::new(movie.clone()).verify(&chip8, 500).is_err());
+
+        assert!(movie.start(&ROM[..8]).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Phosphor persistence, hiding the flicker of sprites erased and redrawn with XOR.
+
+use crate::config::{Color, Persistence};
+use libchip8::config as libconfig;
+
+const PIXELS: usize = libconfig::DISPLAY_WIDTH * libconfig::DISPLAY_HEIGHT;
+
+pub struct Phosphor {
+    mode: Persistence,
+    /// Brightness kept per frame after a pixel turns off.
+    decay: f32,
+    /// Brightness of each pixel from 0 to 1, in `Decay` mode.
+    brightness: Vec<f32>,
+    /// Display at the end of the last two frames, newest first, in `Or` mode.
+    frames: [Vec<bool>; 2],
+}
+
+impl Phosphor {
+    pub fn new(mode: Persistence, decay: f32) -> Self {
+        Self {
+            mode,
+            decay,
+            brightness: vec![0.0; PIXELS],
+            frames: [vec![false; PIXELS], vec![false; PIXELS]],
+        }
+    }
+
+    /// Forgets previous frames.
+    pub fn clear(&mut self) {
+        *self = Self::new(self.mode, self.decay);
+    }
+
+    /// Takes the display at the end of an emulated frame.
+    pub fn update(&mut self, display: &[bool]) {
+        match self.mode {
+            Persistence::Off => (),
+            Persistence::Decay => {
+                for (brightness, &on) in self.brightness.iter_mut().zip(display) {
+                    *brightness = if on { 1.0 } else { *brightness * self.decay };
+                }
+            }
+            Persistence::Or => {
+                self.frames.swap(0, 1);
+                self.frames[0].copy_from_slice(display);
+            }
+        }
+    }
+
+    /// Brightness of pixel `i` from 0 to 1, `display` is the current display.
+    pub fn brightness(&self, i: usize, display: &[bool]) -> f32 {
+        match self.mode {
+            Persistence::Off => display[i] as u8 as f32,
+            Persistence::Decay => self.brightness[i],
+            Persistence::Or => (self.frames[0][i] || self.frames[1][i]) as u8 as f32,
+        }
+    }
+}
+
+/// Color between `off` at brightness 0 and `on` at brightness 1.
+pub fn blend(off: Color, on: Color, brightness: f32) -> [u8; 4] {
+    let (off, on) = (off.rgba(), on.rgba());
+    std::array::from_fn(|c| {
+        (off[c] as f32 + (on[c] as f32 - off[c] as f32) * brightness).round() as u8
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(on: bool) -> Vec<bool> {
+        vec![on; PIXELS]
+    }
+
+    #[test]
+    fn test_phosphor_decay() {
+        let mut phosphor = Phosphor::new(Persistence::Decay, 0.5);
+        phosphor.update(&display(true));
+        assert_eq!(1.0, phosphor.brightness(0, &display(false)));
+
+        phosphor.update(&display(false));
+        assert_eq!(0.5, phosphor.brightness(0, &display(false)));
+        phosphor.update(&display(false));
+        assert_eq!(0.25, phosphor.brightness(0, &display(false)));
+
+        phosphor.clear();
+        assert_eq!(0.0, phosphor.brightness(0, &display(true)));
+    }
+    #[test]
+    fn test_phosphor_or() {
+        let mut phosphor = Phosphor::new(Persistence::Or, 0.5);
+        phosphor.update(&display(true));
+        phosphor.update(&display(false));
+        assert_eq!(1.0, phosphor.brightness(0, &display(false)));
+        phosphor.update(&display(false));
+        assert_eq!(0.0, phosphor.brightness(0, &display(true)));
+
+        let phosphor = Phosphor::new(Persistence::Off, 0.5);
+        assert_eq!(1.0, phosphor.brightness(0, &display(true)));
+    }
+    #[test]
+    fn test_phosphor_blend() {
+        let (off, on) = (Color(0, 0, 0, 255), Color(255, 100, 0, 255));
+        assert_eq!([128, 50, 0, 255], blend(off, on, 0.5));
+        assert_eq!(on.rgba(), blend(off, on, 1.0));
+        assert_eq!(off.rgba(), blend(off, on, 0.0));
+    }
+}
@@ -4,7 +4,7 @@ use std::{fs, path::Path};
 pub fn read_rom_bytes<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<u8>> {
     let path = path.as_ref();
 
-    let rom_bytes: Vec<u8> = fs::read(&path).map_err(|e| {
+    let rom_bytes: Vec<u8> = fs::read(path).map_err(|e| {
         anyhow::anyhow!(
             "Failed to read ROM bytes from path '{}': {}",
             path.display(),
@@ -14,3 +14,10 @@ pub fn read_rom_bytes<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<u8>> {
 
     Ok(rom_bytes)
 }
+
+/// 64-bit FNV-1a, stable across platforms and releases.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
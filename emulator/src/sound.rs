@@ -1,15 +1,162 @@
-use rodio::{
-    MixerDeviceSink, Player,
-    source::{Source, SquareWave},
-};
+//! Buzzer tone synthesis.
 
-pub fn create_beep_sink() -> (Player, MixerDeviceSink) {
-    let handle = rodio::DeviceSinkBuilder::open_default_sink().expect("open default audio stream");
+use crate::config::{AudioConfig, Waveform};
 
-    let player = rodio::Player::connect_new(&handle.mixer());
-    let source = SquareWave::new(1000.0).amplify(0.25).repeat_infinite();
+/// Buzzer tone, fading in and out with the envelope as the buzzer turns on and off.
+pub struct Tone {
+    waveform: Waveform,
+    /// Phase advance per sample, in periods.
+    step: f32,
+    phase: f32,
+    volume: f32,
+    /// Envelope change per sample.
+    attack: f32,
+    release: f32,
+    /// Envelope level from 0 to 1.
+    level: f32,
+    /// xorshift state for the noise waveform.
+    noise: u32,
+    noise_value: f32,
+}
+
+/// Envelope change per sample for a ramp of `ms` milliseconds.
+fn ramp_step(ms: f32, sample_rate: u32) -> f32 {
+    let samples = ms * sample_rate as f32 / 1000.0;
+    if samples < 1.0 { 1.0 } else { 1.0 / samples }
+}
+
+impl Tone {
+    pub fn new(config: &AudioConfig, sample_rate: u32) -> Self {
+        Self {
+            waveform: config.waveform,
+            step: config.frequency / sample_rate as f32,
+            phase: 0.0,
+            volume: config.volume,
+            attack: ramp_step(config.attack_ms, sample_rate),
+            release: ramp_step(config.release_ms, sample_rate),
+            level: 0.0,
+            noise: 0x1234_5678,
+            noise_value: 0.0,
+        }
+    }
+
+    fn wave(&mut self) -> f32 {
+        match self.waveform {
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 4.0 * (self.phase - 0.5).abs() - 1.0,
+            Waveform::Sine => (self.phase * std::f32::consts::TAU).sin(),
+            Waveform::Noise => self.noise_value,
+        }
+    }
+
+    /// Next sample while the buzzer is `on`.
+    pub fn sample(&mut self, on: bool) -> f32 {
+        let (target, step) = if on {
+            (1.0, self.attack)
+        } else {
+            (0.0, self.release)
+        };
+        self.level = if self.level < target {
+            (self.level + step).min(target)
+        } else {
+            (self.level - step).max(target)
+        };
+
+        let sample = self.wave() * self.volume * self.level;
+
+        self.phase += self.step;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            // noise holds a new random value each period, so frequency sets its pitch
+            self.noise ^= self.noise << 13;
+            self.noise ^= self.noise >> 17;
+            self.noise ^= self.noise << 5;
+            self.noise_value = self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0;
+        }
+
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn tone(waveform: Waveform) -> Tone {
+        let mut config = Config::default().audio;
+        config.waveform = waveform;
+        config.frequency = 1000.0;
+        config.volume = 0.5;
+        config.attack_ms = 1.0;
+        config.release_ms = 2.0;
+
+        Tone::new(&config, 8000)
+    }
+
+    fn samples(tone: &mut Tone, on: bool, count: usize) -> Vec<f32> {
+        (0..count).map(|_| tone.sample(on)).collect()
+    }
+
+    #[test]
+    fn test_sound_envelope() {
+        let mut tone = tone(Waveform::Square);
+        assert!(
+            samples(&mut tone, false, 100)
+                .iter()
+                .all(|&sample| sample == 0.0)
+        );
+
+        // 1 ms attack is 8 samples at 8 kHz
+        let attack: Vec<f32> = samples(&mut tone, true, 8)
+            .into_iter()
+            .map(f32::abs)
+            .collect();
+        assert_eq!(0.5 / 8.0, attack[0]);
+        assert_eq!(0.5, attack[7]);
+
+        let release: Vec<f32> = samples(&mut tone, false, 16)
+            .into_iter()
+            .map(f32::abs)
+            .collect();
+        assert!(release.windows(2).all(|pair| pair[1] < pair[0]));
+        assert_eq!(0.0, release[15]);
+    }
+    #[test]
+    fn test_sound_waveforms() {
+        // 8 samples per period at 1 kHz
+        let samples = |waveform, count| {
+            let mut tone = tone(waveform);
+            tone.level = 1.0;
+            tone.attack = 0.0;
+            samples(&mut tone, true, count)
+                .into_iter()
+                .map(|sample| sample * 2.0)
+                .collect::<Vec<f32>>()
+        };
+        let period = |waveform| samples(waveform, 8);
 
-    player.append(source);
+        assert_eq!(
+            vec![1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0],
+            period(Waveform::Square)
+        );
+        assert_eq!(
+            vec![1.0, 0.5, 0.0, -0.5, -1.0, -0.5, 0.0, 0.5],
+            period(Waveform::Triangle)
+        );
+        assert!((period(Waveform::Sine)[2] - 1.0).abs() < 1e-6);
 
-    (player, handle)
+        // one random value per period
+        let noise = samples(Waveform::Noise, 8 * 4);
+        assert!(noise.iter().all(|sample| sample.abs() <= 1.0));
+        let mut values: Vec<f32> = noise.into_iter().filter(|&sample| sample != 0.0).collect();
+        values.dedup();
+        assert!(values.len() >= 2);
+    }
 }
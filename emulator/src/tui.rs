@@ -0,0 +1,428 @@
+//! Terminal frontend.
+//!
+//! Most terminals only report key presses, so a key counts as held until no
+//! press or repeat arrived for the key timeout. Terminals supporting the kitty
+//! keyboard protocol report real releases.
+
+use crate::{
+    app,
+    cli::Graphics,
+    config::{Color, Config},
+    keyboard::Keymap,
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use crossterm::{
+    cursor, event,
+    event::{Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags},
+    execute, queue, style,
+    terminal::{self, ClearType},
+};
+use libchip8::{Chip8, config as libconfig, timers::Timer};
+use std::{
+    env,
+    fmt::Write as _,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+const WIDTH: usize = libconfig::DISPLAY_WIDTH;
+const HEIGHT: usize = libconfig::DISPLAY_HEIGHT;
+
+/// Held keys with their release deadlines.
+pub struct KeyState {
+    deadlines: [Option<Instant>; libconfig::KEY_COUNT as usize],
+    timeout: Duration,
+}
+
+impl KeyState {
+    pub fn new(timeout: Duration) -> Self {
+        KeyState {
+            deadlines: [None; libconfig::KEY_COUNT as usize],
+            timeout,
+        }
+    }
+
+    /// Press or repeat, holds the key for another timeout.
+    pub fn press(&mut self, key: u8, now: Instant) {
+        self.deadlines[key as usize] = Some(now + self.timeout);
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.deadlines[key as usize] = None;
+    }
+
+    /// Copies the held keys into the keyboard, releasing expired ones.
+    pub fn apply(&mut self, chip8: &mut Chip8, now: Instant) {
+        for (key, deadline) in self.deadlines.iter_mut().enumerate() {
+            if deadline.is_some_and(|deadline| deadline <= now) {
+                *deadline = None;
+            }
+            chip8.keyboard.set_key(key as u8, deadline.is_some());
+        }
+    }
+}
+
+/// Picks kitty graphics when the terminal advertises it, half blocks otherwise.
+pub fn detect_graphics() -> Graphics {
+    let term = env::var("TERM").unwrap_or_default();
+    if env::var_os("KITTY_WINDOW_ID").is_some() || term.contains("kitty") {
+        Graphics::Kitty
+    } else {
+        Graphics::HalfBlock
+    }
+}
+
+/// Two pixels per cell, upper one drawn with the foreground color.
+pub fn half_blocks(display: &[bool]) -> Vec<String> {
+    display
+        .chunks(WIDTH * 2)
+        .map(|rows| {
+            let (upper, lower) = rows.split_at(WIDTH);
+            upper
+                .iter()
+                .zip(lower)
+                .map(|pair| match pair {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Eight pixels per cell, 2 wide and 4 tall.
+pub fn braille(display: &[bool]) -> Vec<String> {
+    // dot bits for (column, row) within a cell
+    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+    (0..HEIGHT / 4)
+        .map(|cell_y| {
+            (0..WIDTH / 2)
+                .map(|cell_x| {
+                    let mut bits = 0;
+                    for (dx, column) in DOTS.iter().enumerate() {
+                        for (dy, bit) in column.iter().enumerate() {
+                            if display[(cell_y * 4 + dy) * WIDTH + cell_x * 2 + dx] {
+                                bits |= bit;
+                            }
+                        }
+                    }
+                    char::from_u32(0x2800 + bits).unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Sixel image, every pixel scaled to a `scale` x `scale` square.
+pub fn sixel(display: &[bool], on: Color, off: Color, scale: usize) -> String {
+    let (width, height) = (WIDTH * scale, HEIGHT * scale);
+    let percent = |value: u8| value as u32 * 100 / 255;
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for (idx, color) in [off, on].iter().enumerate() {
+        write!(
+            out,
+            "#{};2;{};{};{}",
+            idx,
+            percent(color.0),
+            percent(color.1),
+            percent(color.2)
+        )
+        .unwrap();
+    }
+
+    for band in 0..height.div_ceil(6) {
+        for (idx, lit) in [false, true].into_iter().enumerate() {
+            write!(out, "#{}", idx).unwrap();
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| {
+                    (0..6).fold(0, |bits, dy| {
+                        let y = band * 6 + dy;
+                        let pixel = y < height && display[(y / scale) * WIDTH + x / scale];
+                        if pixel == lit { bits | 1 << dy } else { bits }
+                    })
+                })
+                .collect();
+
+            // run length encoded, repeats start with `!`
+            for run in sixels.chunk_by(|a, b| a == b) {
+                let c = (b'?' + run[0]) as char;
+                if run.len() > 3 {
+                    write!(out, "!{}{}", run.len(), c).unwrap();
+                } else {
+                    out.extend(std::iter::repeat_n(c, run.len()));
+                }
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+
+    out
+}
+
+/// Kitty graphics protocol image stretched over `columns` x `rows` cells.
+pub fn kitty(display: &[bool], on: Color, off: Color, columns: u16, rows: u16) -> String {
+    let rgb: Vec<u8> = display
+        .iter()
+        .flat_map(|&lit| {
+            let color = if lit { on } else { off };
+            [color.0, color.1, color.2]
+        })
+        .collect();
+    let payload = BASE64.encode(rgb);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(4096).collect();
+
+    let mut out = String::new();
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let more = (idx + 1 < chunks.len()) as u8;
+        if idx == 0 {
+            // same image and placement id, so every frame replaces the previous one
+            write!(
+                out,
+                "\x1b_Ga=T,f=24,s={},v={},c={},r={},i=1,p=1,q=2,C=1,m={};",
+                WIDTH, HEIGHT, columns, rows, more
+            )
+            .unwrap();
+        } else {
+            write!(out, "\x1b_Gm={};", more).unwrap();
+        }
+        out.push_str(std::str::from_utf8(chunk).unwrap());
+        out.push_str("\x1b\\");
+    }
+
+    out
+}
+
+fn term_color(color: Color) -> style::Color {
+    style::Color::Rgb {
+        r: color.0,
+        g: color.1,
+        b: color.2,
+    }
+}
+
+/// Puts the terminal into raw mode and restores it when dropped.
+struct TerminalGuard {
+    enhanced_keyboard: bool,
+}
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let enhanced_keyboard = terminal::supports_keyboard_enhancement().unwrap_or(false);
+
+        let mut stdout = io::stdout();
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        if enhanced_keyboard {
+            execute!(
+                stdout,
+                event::PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        Ok(TerminalGuard { enhanced_keyboard })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.enhanced_keyboard {
+            let _ = execute!(stdout, event::PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(
+            stdout,
+            style::ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Runs the machine in the terminal until Esc or Ctrl-C.
+pub fn run(
+    mut chip8: Chip8,
+    config: &Config,
+    keymap: &Keymap,
+    graphics: Graphics,
+    key_timeout: Duration,
+) -> anyhow::Result<()> {
+    let guard = TerminalGuard::enter()?;
+    let mut stdout = io::stdout();
+    let mut keys = KeyState::new(key_timeout);
+
+    let timing = &config.timing;
+    let frame_duration = timing.timer_tick_duration();
+    let mut next_frame = Instant::now();
+    let mut last_cpu_tick = Instant::now();
+    let mut cycle = 0;
+    let mut last_display: Vec<bool> = Vec::new();
+    let mut beeping = false;
+
+    'frames: loop {
+        while event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+            let Event::Key(key) = event::read()? else {
+                // redraw everything after a resize
+                last_display.clear();
+                continue;
+            };
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.code == KeyCode::Esc || ctrl_c {
+                break 'frames;
+            }
+
+            if let Some(chip8_key) = keymap.map_terminal(key.code) {
+                match key.kind {
+                    KeyEventKind::Release => keys.release(chip8_key),
+                    _ if guard.enhanced_keyboard => keys.press(chip8_key, far_future()),
+                    _ => keys.press(chip8_key, Instant::now()),
+                }
+            }
+        }
+
+        let now = Instant::now();
+        keys.apply(&mut chip8, now);
+        while now.duration_since(last_cpu_tick) >= timing.cpu_tick_duration() {
+            app::step(&mut chip8, cycle, timing)?;
+            cycle += 1;
+            last_cpu_tick += timing.cpu_tick_duration();
+        }
+
+        let sound = chip8.timers.get(Timer::Sound) > 0;
+        if sound && !beeping && config.audio.enabled {
+            queue!(stdout, style::Print('\x07'))?;
+        }
+        beeping = sound;
+
+        if chip8.display.dump() != last_display.as_slice() {
+            if last_display.is_empty() {
+                queue!(stdout, terminal::Clear(ClearType::All))?;
+            }
+            last_display = chip8.display.dump().to_vec();
+            draw(&mut stdout, &last_display, config, graphics)?;
+        }
+        stdout.flush()?;
+
+        next_frame += frame_duration;
+        if next_frame < now {
+            next_frame = now;
+        }
+    }
+
+    Ok(())
+}
+
+/// Far enough that keys stay held until the terminal reports the release.
+fn far_future() -> Instant {
+    Instant::now() + Duration::from_secs(60 * 60 * 24)
+}
+
+fn draw(
+    stdout: &mut io::Stdout,
+    display: &[bool],
+    config: &Config,
+    graphics: Graphics,
+) -> io::Result<()> {
+    let (on, off) = (config.display.on_color, config.display.off_color);
+    queue!(stdout, cursor::MoveTo(0, 0))?;
+
+    let rows = match graphics {
+        Graphics::HalfBlock => half_blocks(display),
+        Graphics::Braille => braille(display),
+        Graphics::Sixel => {
+            let (_, _, pixel_width, _) = terminal_size_pixels();
+            let scale = (pixel_width / WIDTH as u16).clamp(1, 8) as usize;
+            return queue!(stdout, style::Print(sixel(display, on, off, scale)));
+        }
+        Graphics::Kitty => {
+            let (columns, _) = terminal::size()?;
+            let columns = columns.min(WIDTH as u16 * 2);
+            return queue!(
+                stdout,
+                style::Print(kitty(display, on, off, columns, columns / 4))
+            );
+        }
+    };
+
+    queue!(
+        stdout,
+        style::SetForegroundColor(term_color(on)),
+        style::SetBackgroundColor(term_color(off))
+    )?;
+    for (y, row) in rows.iter().enumerate() {
+        queue!(stdout, cursor::MoveTo(0, y as u16), style::Print(row))?;
+    }
+    queue!(stdout, style::ResetColor)
+}
+
+/// Terminal size in cells and pixels, falling back to 8x16 pixel cells.
+fn terminal_size_pixels() -> (u16, u16, u16, u16) {
+    match terminal::window_size() {
+        Ok(size) if size.width > 0 => (size.columns, size.rows, size.width, size.height),
+        _ => {
+            let (columns, rows) = terminal::size().unwrap_or((80, 24));
+            (columns, rows, columns * 8, rows * 16)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(lit: &[(usize, usize)]) -> Vec<bool> {
+        let mut display = vec![false; WIDTH * HEIGHT];
+        for &(x, y) in lit {
+            display[y * WIDTH + x] = true;
+        }
+        display
+    }
+
+    #[test]
+    fn test_tui_half_blocks() {
+        let rows = half_blocks(&display(&[(0, 0), (1, 1), (2, 0), (2, 1)]));
+        assert_eq!(HEIGHT / 2, rows.len());
+        assert!(rows[0].starts_with("▀▄█ "));
+        assert_eq!(WIDTH, rows[0].chars().count());
+    }
+    #[test]
+    fn test_tui_braille() {
+        let rows = braille(&display(&[(0, 0), (1, 3)]));
+        assert_eq!(HEIGHT / 4, rows.len());
+        assert_eq!(Some('\u{2881}'), rows[0].chars().next());
+        assert_eq!(WIDTH / 2, rows[0].chars().count());
+    }
+    #[test]
+    fn test_tui_sixel() {
+        let on = Color(255, 255, 255, 255);
+        let off = Color(0, 0, 0, 255);
+        let image = sixel(&display(&[]), on, off, 1);
+        assert!(image.starts_with("\x1bPq\"1;1;64;32#0;2;0;0;0#1;2;100;100;100"));
+        // every band is fully unlit
+        assert!(image.contains("#0!64~$#1!64?$-"));
+        assert!(image.ends_with("\x1b\\"));
+    }
+    #[test]
+    fn test_tui_key_timeout() {
+        let mut chip8 = Chip8::new();
+        let mut keys = KeyState::new(Duration::from_millis(100));
+        let now = Instant::now();
+
+        keys.press(5, now);
+        keys.apply(&mut chip8, now + Duration::from_millis(50));
+        assert!(chip8.keyboard.is_pressed(5));
+
+        keys.press(5, now + Duration::from_millis(80));
+        keys.apply(&mut chip8, now + Duration::from_millis(150));
+        assert!(chip8.keyboard.is_pressed(5));
+
+        keys.apply(&mut chip8, now + Duration::from_millis(180));
+        assert!(!chip8.keyboard.is_pressed(5));
+    }
+}
@@ -0,0 +1,101 @@
+//! Watching the ROM file for changes.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+/// Quiet time after the last change before it is reported, builds often write in several steps.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Reports changes of a single file.
+pub struct RomWatcher {
+    path: PathBuf,
+    events: Receiver<notify::Result<Event>>,
+    /// Time of the last change not reported yet.
+    pending: Option<Instant>,
+    // events stop when the watcher is dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl RomWatcher {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let path = path
+            .canonicalize()
+            .map_err(|e| anyhow::anyhow!("Failed to watch ROM path '{}': {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(&path).to_path_buf();
+
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        // editors and build tools often replace the file, which ends a watch on the file itself
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            path,
+            events,
+            pending: None,
+            _watcher: watcher,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the file changed and then stayed untouched for a moment.
+    pub fn changed(&mut self) -> bool {
+        for event in self.events.try_iter() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event.paths.contains(&self.path)
+            {
+                self.pending = Some(Instant::now());
+            }
+        }
+
+        match self.pending {
+            Some(changed) if changed.elapsed() >= DEBOUNCE => {
+                self.pending = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, thread};
+
+    #[test]
+    fn test_watch_changed() {
+        let dir = std::env::temp_dir().join(format!("chip8-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.ch8");
+        let other = dir.join("other.ch8");
+        fs::write(&rom, [0x00, 0xe0]).unwrap();
+
+        let mut watcher = RomWatcher::new(&rom).unwrap();
+        assert!(!watcher.changed());
+
+        // other files in the directory are ignored
+        fs::write(&other, [0x00, 0xe0]).unwrap();
+        thread::sleep(DEBOUNCE * 3);
+        assert!(!watcher.changed());
+
+        fs::write(&rom, [0x12, 0x00]).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !watcher.changed() {
+            assert!(Instant::now() < deadline, "change was not reported");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!watcher.changed());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
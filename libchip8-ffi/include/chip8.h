@@ -0,0 +1,137 @@
+/* Generated by cbindgen from libchip8-ffi, do not edit. */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define CHIP8_DISPLAY_WIDTH 64
+
+#define CHIP8_DISPLAY_HEIGHT 32
+
+#define CHIP8_KEY_COUNT 16
+
+#define CHIP8_STATE_SIZE 11320
+
+/**
+ * Result of every fallible call, mirrors `ChipError`, `MemoryError` and `StackError`.
+ */
+typedef enum Chip8Status {
+  CHIP8_STATUS_OK = 0,
+  CHIP8_STATUS_NULL_POINTER,
+  CHIP8_STATUS_BUFFER_TOO_SMALL,
+  CHIP8_STATUS_INVALID_KEY,
+  CHIP8_STATUS_UNKNOWN_INSTRUCTION,
+  CHIP8_STATUS_PROGRAM_TOO_LARGE,
+  CHIP8_STATUS_OUT_OF_BOUNDS,
+  CHIP8_STATUS_SPRITE_OUT_OF_BOUNDS,
+  CHIP8_STATUS_STACK_OVERFLOW,
+  CHIP8_STATUS_STACK_UNDERFLOW,
+  CHIP8_STATUS_INVALID_STATE,
+  CHIP8_STATUS_PANIC,
+} Chip8Status;
+
+/**
+ * Opaque machine handle.
+ */
+typedef struct Chip8Handle Chip8Handle;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a machine, `seed` determines results of `Cxkk`.
+ *
+ * Release it with `chip8_free`.
+ */
+struct Chip8Handle *chip8_new(uint64_t seed);
+
+/**
+ * Releases a machine created with `chip8_new`.
+ *
+ * # Safety
+ * `handle` must come from `chip8_new` and must not be used afterwards.
+ */
+void chip8_free(struct Chip8Handle *handle);
+
+/**
+ * Loads `len` bytes of ROM at the program start address.
+ *
+ * # Safety
+ * `handle` must be valid, `data` must point to `len` readable bytes.
+ */
+enum Chip8Status chip8_load_rom(struct Chip8Handle *handle, const uint8_t *data, size_t len);
+
+/**
+ * Executes `cycles` instructions followed by one timer tick.
+ *
+ * # Safety
+ * `handle` must be valid.
+ */
+enum Chip8Status chip8_run_frame(struct Chip8Handle *handle, uint32_t cycles);
+
+/**
+ * Presses or releases keypad key `0x0` to `0xF`.
+ *
+ * # Safety
+ * `handle` must be valid.
+ */
+enum Chip8Status chip8_set_key(struct Chip8Handle *handle, uint8_t key, bool pressed);
+
+/**
+ * Copies the display into `out`, one byte (0 or 1) per pixel, row by row.
+ *
+ * `len` must be at least `CHIP8_DISPLAY_WIDTH * CHIP8_DISPLAY_HEIGHT`.
+ *
+ * # Safety
+ * `handle` must be valid, `out` must point to `len` writable bytes.
+ */
+enum Chip8Status chip8_framebuffer(const struct Chip8Handle *handle, uint8_t *out, size_t len);
+
+/**
+ * Writes buzzer samples from -1 to 1 for the emulated time not played yet.
+ *
+ * Stores the number of samples written in `written`, which is less than `len`
+ * once the samples catch up with the last timer tick.
+ *
+ * # Safety
+ * `handle` must be valid, `out` must point to `len` writable floats and
+ * `written` must be writable.
+ */
+enum Chip8Status chip8_fill_audio(struct Chip8Handle *handle,
+                                  float *out,
+                                  size_t len,
+                                  uint32_t sample_rate,
+                                  size_t *written);
+
+/**
+ * Writes a save state of `CHIP8_STATE_SIZE` bytes into `out`.
+ *
+ * # Safety
+ * `handle` must be valid, `out` must point to `len` writable bytes.
+ */
+enum Chip8Status chip8_save_state(const struct Chip8Handle *handle, uint8_t *out, size_t len);
+
+/**
+ * Restores a save state written by `chip8_save_state`.
+ *
+ * # Safety
+ * `handle` must be valid, `data` must point to `len` readable bytes.
+ */
+enum Chip8Status chip8_load_state(struct Chip8Handle *handle, const uint8_t *data, size_t len);
+
+/**
+ * Returns a static, human readable description of a status.
+ */
+const char *chip8_status_message(enum Chip8Status status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP8_H */
@@ -0,0 +1,291 @@
+//! C interface to libchip8.
+//!
+//! Every function taking a handle accepts NULL and reports it as
+//! `CHIP8_STATUS_NULL_POINTER`. Unexpected panics in the core are reported as
+//! `CHIP8_STATUS_PANIC` instead of unwinding into the caller, the machine should be freed or
+//! reloaded afterwards. The header lives in `include/chip8.h`.
+
+use std::{
+    ffi::c_char,
+    panic::{self, AssertUnwindSafe},
+    slice,
+};
+
+use libchip8::{Chip8, ChipError, MemoryError, StackError, config, state};
+
+pub const CHIP8_DISPLAY_WIDTH: usize = 64;
+pub const CHIP8_DISPLAY_HEIGHT: usize = 32;
+pub const CHIP8_KEY_COUNT: u8 = 16;
+pub const CHIP8_STATE_SIZE: usize = 11320;
+
+const _: () = assert!(CHIP8_DISPLAY_WIDTH == config::DISPLAY_WIDTH);
+const _: () = assert!(CHIP8_DISPLAY_HEIGHT == config::DISPLAY_HEIGHT);
+const _: () = assert!(CHIP8_KEY_COUNT == config::KEY_COUNT);
+const _: () = assert!(CHIP8_STATE_SIZE == state::STATE_SIZE);
+
+/// Opaque machine handle.
+pub struct Chip8Handle(Chip8);
+
+/// Result of every fallible call, mirrors `ChipError`, `MemoryError` and `StackError`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Status {
+    Ok = 0,
+    NullPointer,
+    BufferTooSmall,
+    InvalidKey,
+    UnknownInstruction,
+    ProgramTooLarge,
+    OutOfBounds,
+    SpriteOutOfBounds,
+    StackOverflow,
+    StackUnderflow,
+    InvalidState,
+    Panic,
+}
+
+impl From<MemoryError> for Chip8Status {
+    fn from(e: MemoryError) -> Self {
+        match e {
+            MemoryError::ProgramTooLarge => Chip8Status::ProgramTooLarge,
+            MemoryError::OutOfBounds(_) => Chip8Status::OutOfBounds,
+            MemoryError::SpriteOutOfBounds(_) => Chip8Status::SpriteOutOfBounds,
+        }
+    }
+}
+
+impl From<StackError> for Chip8Status {
+    fn from(e: StackError) -> Self {
+        match e {
+            StackError::Overflow(_) => Chip8Status::StackOverflow,
+            StackError::Underflow => Chip8Status::StackUnderflow,
+        }
+    }
+}
+
+impl From<ChipError> for Chip8Status {
+    fn from(e: ChipError) -> Self {
+        match e {
+            ChipError::UnknownInstruction(_) => Chip8Status::UnknownInstruction,
+            ChipError::MemoryError(e) => e.into(),
+            ChipError::StackError(e) => e.into(),
+        }
+    }
+}
+
+fn status<E: Into<Chip8Status>>(result: Result<(), E>) -> Chip8Status {
+    match result {
+        Ok(()) => Chip8Status::Ok,
+        Err(e) => e.into(),
+    }
+}
+
+/// Runs the body of an entry point, a panic becomes `Chip8Status::Panic`.
+fn guard(body: impl FnOnce() -> Chip8Status) -> Chip8Status {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(Chip8Status::Panic)
+}
+
+/// Creates a machine, `seed` determines results of `Cxkk`.
+///
+/// Release it with `chip8_free`.
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_new(seed: u64) -> *mut Chip8Handle {
+    Box::into_raw(Box::new(Chip8Handle(Chip8::with_seed(seed))))
+}
+
+/// Releases a machine created with `chip8_new`.
+///
+/// # Safety
+/// `handle` must come from `chip8_new` and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_free(handle: *mut Chip8Handle) {
+    if !handle.is_null() {
+        drop(unsafe { Box::from_raw(handle) });
+    }
+}
+
+/// Loads `len` bytes of ROM at the program start address.
+///
+/// # Safety
+/// `handle` must be valid, `data` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_load_rom(
+    handle: *mut Chip8Handle,
+    data: *const u8,
+    len: usize,
+) -> Chip8Status {
+    guard(|| {
+        let (Some(handle), false) = (unsafe { handle.as_mut() }, data.is_null()) else {
+            return Chip8Status::NullPointer;
+        };
+        let rom = unsafe { slice::from_raw_parts(data, len) };
+
+        status(handle.0.load_rom(rom))
+    })
+}
+
+/// Executes `cycles` instructions followed by one timer tick.
+///
+/// # Safety
+/// `handle` must be valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_run_frame(handle: *mut Chip8Handle, cycles: u32) -> Chip8Status {
+    guard(|| {
+        let Some(handle) = (unsafe { handle.as_mut() }) else {
+            return Chip8Status::NullPointer;
+        };
+
+        status(handle.0.run_frame(cycles))
+    })
+}
+
+/// Presses or releases keypad key `0x0` to `0xF`.
+///
+/// # Safety
+/// `handle` must be valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_set_key(
+    handle: *mut Chip8Handle,
+    key: u8,
+    pressed: bool,
+) -> Chip8Status {
+    guard(|| {
+        let Some(handle) = (unsafe { handle.as_mut() }) else {
+            return Chip8Status::NullPointer;
+        };
+        if key >= CHIP8_KEY_COUNT {
+            return Chip8Status::InvalidKey;
+        }
+        handle.0.keyboard.set_key(key, pressed);
+
+        Chip8Status::Ok
+    })
+}
+
+/// Copies the display into `out`, one byte (0 or 1) per pixel, row by row.
+///
+/// `len` must be at least `CHIP8_DISPLAY_WIDTH * CHIP8_DISPLAY_HEIGHT`.
+///
+/// # Safety
+/// `handle` must be valid, `out` must point to `len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_framebuffer(
+    handle: *const Chip8Handle,
+    out: *mut u8,
+    len: usize,
+) -> Chip8Status {
+    guard(|| {
+        let (Some(handle), false) = (unsafe { handle.as_ref() }, out.is_null()) else {
+            return Chip8Status::NullPointer;
+        };
+        let pixels = handle.0.display.dump();
+        if len < pixels.len() {
+            return Chip8Status::BufferTooSmall;
+        }
+        let out = unsafe { slice::from_raw_parts_mut(out, pixels.len()) };
+        for (byte, &on) in out.iter_mut().zip(pixels) {
+            *byte = on as u8;
+        }
+
+        Chip8Status::Ok
+    })
+}
+
+/// Writes buzzer samples from -1 to 1 for the emulated time not played yet.
+///
+/// Stores the number of samples written in `written`, which is less than `len`
+/// once the samples catch up with the last timer tick.
+///
+/// # Safety
+/// `handle` must be valid, `out` must point to `len` writable floats and
+/// `written` must be writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_fill_audio(
+    handle: *mut Chip8Handle,
+    out: *mut f32,
+    len: usize,
+    sample_rate: u32,
+    written: *mut usize,
+) -> Chip8Status {
+    guard(|| {
+        let (Some(handle), false, Some(written)) =
+            (unsafe { handle.as_mut() }, out.is_null(), unsafe {
+                written.as_mut()
+            })
+        else {
+            return Chip8Status::NullPointer;
+        };
+        let out = unsafe { slice::from_raw_parts_mut(out, len) };
+        *written = handle.0.fill_audio(out, sample_rate);
+
+        Chip8Status::Ok
+    })
+}
+
+/// Writes a save state of `CHIP8_STATE_SIZE` bytes into `out`.
+///
+/// # Safety
+/// `handle` must be valid, `out` must point to `len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_save_state(
+    handle: *const Chip8Handle,
+    out: *mut u8,
+    len: usize,
+) -> Chip8Status {
+    guard(|| {
+        let (Some(handle), false) = (unsafe { handle.as_ref() }, out.is_null()) else {
+            return Chip8Status::NullPointer;
+        };
+        if len < CHIP8_STATE_SIZE {
+            return Chip8Status::BufferTooSmall;
+        }
+        let state = handle.0.save_state();
+        let out = unsafe { slice::from_raw_parts_mut(out, state.len()) };
+        out.copy_from_slice(&state);
+
+        Chip8Status::Ok
+    })
+}
+
+/// Restores a save state written by `chip8_save_state`.
+///
+/// # Safety
+/// `handle` must be valid, `data` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_load_state(
+    handle: *mut Chip8Handle,
+    data: *const u8,
+    len: usize,
+) -> Chip8Status {
+    guard(|| {
+        let (Some(handle), false) = (unsafe { handle.as_mut() }, data.is_null()) else {
+            return Chip8Status::NullPointer;
+        };
+        let state = unsafe { slice::from_raw_parts(data, len) };
+
+        match handle.0.load_state(state) {
+            Ok(()) => Chip8Status::Ok,
+            Err(_) => Chip8Status::InvalidState,
+        }
+    })
+}
+
+/// Returns a static, human readable description of a status.
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_status_message(status: Chip8Status) -> *const c_char {
+    let message = match status {
+        Chip8Status::Ok => c"ok",
+        Chip8Status::NullPointer => c"null pointer",
+        Chip8Status::BufferTooSmall => c"buffer too small",
+        Chip8Status::InvalidKey => c"invalid key",
+        Chip8Status::UnknownInstruction => c"unknown instruction",
+        Chip8Status::ProgramTooLarge => c"program too large",
+        Chip8Status::OutOfBounds => c"out of bounds access",
+        Chip8Status::SpriteOutOfBounds => c"out of bound sprite access",
+        Chip8Status::StackOverflow => c"stack overflow",
+        Chip8Status::StackUnderflow => c"stack underflow",
+        Chip8Status::InvalidState => c"invalid save state",
+        Chip8Status::Panic => c"emulator core panicked",
+    };
+    message.as_ptr()
+}
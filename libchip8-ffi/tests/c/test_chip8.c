@@ -0,0 +1,94 @@
+/* Exercises the C interface, exits with non-zero status on failure. */
+#include <stdio.h>
+#include <string.h>
+
+#include "chip8.h"
+
+#define CHECK(cond)                                                 \
+  do {                                                              \
+    if (!(cond)) {                                                  \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,        \
+              __LINE__, #cond);                                     \
+      return 1;                                                     \
+    }                                                               \
+  } while (0)
+
+/*
+ * 0x200: LD V0, 0x0A
+ * 0x202: LD F, V0
+ * 0x204: SKNP V1        V1 = 0, draw only while key 0 is held
+ * 0x206: DRW V1, V1, 5
+ * 0x208: JP 0x208
+ */
+static const uint8_t ROM[] = {0x60, 0x0a, 0xf0, 0x29, 0xe1, 0xa1,
+                              0xd1, 0x15, 0x12, 0x08};
+
+static int lit_pixels(const Chip8Handle *chip8) {
+  uint8_t pixels[CHIP8_DISPLAY_WIDTH * CHIP8_DISPLAY_HEIGHT];
+  int lit = 0;
+
+  if (chip8_framebuffer(chip8, pixels, sizeof pixels) != CHIP8_STATUS_OK) {
+    return -1;
+  }
+  for (size_t i = 0; i < sizeof pixels; i++) {
+    lit += pixels[i];
+  }
+  return lit;
+}
+
+int main(void) {
+  static uint8_t state[CHIP8_STATE_SIZE];
+  static float samples[1024];
+  size_t written = 0;
+  static uint8_t too_large[4096];
+  uint8_t small[8];
+  Chip8Handle *chip8 = chip8_new(1);
+  Chip8Handle *other = chip8_new(2);
+
+  CHECK(chip8 != NULL && other != NULL);
+  CHECK(chip8_load_rom(NULL, ROM, sizeof ROM) == CHIP8_STATUS_NULL_POINTER);
+  CHECK(chip8_load_rom(chip8, too_large, sizeof too_large) ==
+        CHIP8_STATUS_PROGRAM_TOO_LARGE);
+  CHECK(chip8_load_rom(chip8, ROM, sizeof ROM) == CHIP8_STATUS_OK);
+
+  CHECK(chip8_set_key(chip8, 16, true) == CHIP8_STATUS_INVALID_KEY);
+  CHECK(chip8_set_key(chip8, 0, true) == CHIP8_STATUS_OK);
+  CHECK(chip8_run_frame(chip8, 10) == CHIP8_STATUS_OK);
+  /* font sprite "A" has 14 lit pixels */
+  CHECK(lit_pixels(chip8) == 14);
+  CHECK(chip8_framebuffer(chip8, small, sizeof small) ==
+        CHIP8_STATUS_BUFFER_TOO_SMALL);
+
+  /* one silent timer tick is 800 samples at 48 kHz */
+  CHECK(chip8_fill_audio(chip8, samples, 1024, 48000, &written) ==
+        CHIP8_STATUS_OK);
+  CHECK(written == 800 && samples[0] == 0.0f);
+  CHECK(chip8_fill_audio(chip8, samples, 1024, 48000, NULL) ==
+        CHIP8_STATUS_NULL_POINTER);
+
+  CHECK(chip8_save_state(chip8, state, sizeof state) == CHIP8_STATUS_OK);
+  CHECK(chip8_load_state(other, state, sizeof state) == CHIP8_STATUS_OK);
+  CHECK(lit_pixels(other) == 14);
+  CHECK(chip8_load_state(other, state, 10) == CHIP8_STATUS_INVALID_STATE);
+
+  chip8_free(chip8);
+  chip8_free(other);
+
+  /* 0xFFFF is not an instruction */
+  chip8 = chip8_new(0);
+  CHECK(chip8_load_rom(chip8, (const uint8_t[]){0xff, 0xff}, 2) ==
+        CHIP8_STATUS_OK);
+  CHECK(chip8_run_frame(chip8, 1) == CHIP8_STATUS_UNKNOWN_INSTRUCTION);
+  CHECK(strcmp(chip8_status_message(CHIP8_STATUS_UNKNOWN_INSTRUCTION),
+               "unknown instruction") == 0);
+  chip8_free(chip8);
+
+  /* RET with an empty stack */
+  chip8 = chip8_new(0);
+  CHECK(chip8_load_rom(chip8, (const uint8_t[]){0x00, 0xee}, 2) ==
+        CHIP8_STATUS_OK);
+  CHECK(chip8_run_frame(chip8, 1) == CHIP8_STATUS_STACK_UNDERFLOW);
+  chip8_free(chip8);
+
+  return 0;
+}
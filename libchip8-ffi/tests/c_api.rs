@@ -0,0 +1,47 @@
+#![cfg(unix)]
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Directory holding the build artifacts, e.g. `target/debug`.
+fn profile_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    // target/<profile>/deps/c_api-<hash>
+    exe.parent().unwrap().parent().unwrap().to_path_buf()
+}
+
+/// Builds the static library, compiles `tests/c/test_chip8.c` against it and runs it.
+#[test]
+fn test_c_program() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let profile_dir = profile_dir();
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+
+    // `cargo test` only builds the rlib, not the static library
+    let mut build = Command::new(cargo);
+    build.args(["build", "-p", "libchip8-ffi"]);
+    if profile_dir.ends_with("release") {
+        build.arg("--release");
+    }
+    let status = build.status().expect("run cargo build");
+    assert!(status.success(), "building libchip8-ffi failed");
+
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_chip8");
+    let status = Command::new(cc)
+        .arg(crate_dir.join("tests/c/test_chip8.c"))
+        .arg("-I")
+        .arg(crate_dir.join("include"))
+        .arg(profile_dir.join("libchip8.a"))
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&program)
+        .status()
+        .expect("run C compiler");
+    assert!(status.success(), "compiling test_chip8.c failed");
+
+    let status = Command::new(&program).status().expect("run test_chip8");
+    assert!(status.success(), "test_chip8 failed");
+}
@@ -0,0 +1,25 @@
+use std::{env, fs, path::Path};
+
+/// Fails when `include/chip8.h` is out of date.
+/// Run with `CHIP8_UPDATE_HEADER=1` to regenerate it.
+#[test]
+fn test_header_is_up_to_date() {
+    let crate_dir = env!("CARGO_MANIFEST_DIR");
+    let header_path = Path::new(crate_dir).join("include/chip8.h");
+
+    let mut generated = Vec::new();
+    cbindgen::generate(crate_dir)
+        .expect("generate header")
+        .write(&mut generated);
+    let generated = String::from_utf8(generated).unwrap();
+
+    if env::var_os("CHIP8_UPDATE_HEADER").is_some() {
+        fs::write(&header_path, &generated).unwrap();
+    }
+    let committed = fs::read_to_string(&header_path).unwrap_or_default();
+
+    assert!(
+        committed == generated,
+        "include/chip8.h is out of date, rerun the tests with CHIP8_UPDATE_HEADER=1"
+    );
+}
@@ -0,0 +1,227 @@
+//! Python bindings for libchip8, built with maturin (see `pyproject.toml`).
+
+use libchip8::{Chip8, config, timers::Timer};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyMemoryView},
+};
+
+create_exception!(chip8, Chip8Error, PyException);
+
+fn chip_error(e: impl std::fmt::Display) -> PyErr {
+    Chip8Error::new_err(e.to_string())
+}
+
+fn check_key(key: u8) -> PyResult<u8> {
+    if key >= config::KEY_COUNT {
+        return Err(PyValueError::new_err(format!(
+            "key out of range: {} (0x0 to 0xf)",
+            key
+        )));
+    }
+    Ok(key)
+}
+
+fn check_register(index: u8) -> PyResult<u8> {
+    if index as usize >= config::REGISTER_COUNT {
+        return Err(PyValueError::new_err(format!(
+            "register out of range: {} (0x0 to 0xf)",
+            index
+        )));
+    }
+    Ok(index)
+}
+
+/// Chip-8 machine.
+#[pyclass(name = "Chip8", module = "chip8")]
+struct PyChip8 {
+    inner: Chip8,
+}
+
+#[pymethods]
+impl PyChip8 {
+    /// Creates a machine, `seed` makes `Cxkk` results reproducible.
+    #[new]
+    #[pyo3(signature = (seed=None))]
+    fn new(seed: Option<u64>) -> Self {
+        let inner = match seed {
+            Some(seed) => Chip8::with_seed(seed),
+            None => Chip8::new(),
+        };
+        PyChip8 { inner }
+    }
+
+    /// Loads ROM bytes at the program start address.
+    fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        self.inner.load_rom(rom).map_err(chip_error)
+    }
+
+    /// Executes next instruction.
+    fn step(&mut self) -> PyResult<()> {
+        self.inner.tick().map_err(chip_error)
+    }
+
+    /// Executes `cycles` instructions followed by a timer tick.
+    #[pyo3(signature = (cycles=12))]
+    fn run_frame(&mut self, cycles: u32) -> PyResult<()> {
+        self.inner.run_frame(cycles).map_err(chip_error)
+    }
+
+    fn press(&mut self, key: u8) -> PyResult<()> {
+        self.inner.keyboard.set_key(check_key(key)?, true);
+        Ok(())
+    }
+
+    fn release(&mut self, key: u8) -> PyResult<()> {
+        self.inner.keyboard.set_key(check_key(key)?, false);
+        Ok(())
+    }
+
+    /// Display as a read-only `memoryview` of shape (32, 64), 1 = pixel on.
+    ///
+    /// `numpy.asarray(chip8.framebuffer())` gives a `uint8` array.
+    fn framebuffer<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let pixels: Vec<u8> = self
+            .inner
+            .display
+            .dump()
+            .iter()
+            .map(|&on| on as u8)
+            .collect();
+        let bytes = PyBytes::new(py, &pixels);
+
+        PyMemoryView::from(bytes.as_any())?.call_method1(
+            "cast",
+            ("B", (config::DISPLAY_HEIGHT, config::DISPLAY_WIDTH)),
+        )
+    }
+
+    /// Up to `count` buzzer samples from -1 to 1 for the emulated time not played yet,
+    /// as a read-only `memoryview` of `float32`.
+    fn fill_audio<'py>(
+        &mut self,
+        py: Python<'py>,
+        count: usize,
+        sample_rate: u32,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let mut samples = vec![0.0f32; count];
+        let written = self.inner.fill_audio(&mut samples, sample_rate);
+        let bytes: Vec<u8> = samples[..written]
+            .iter()
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect();
+        let bytes = PyBytes::new(py, &bytes);
+
+        PyMemoryView::from(bytes.as_any())?.call_method1("cast", ("f",))
+    }
+
+    /// V0 to VF as bytes.
+    #[getter]
+    fn registers<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let registers: Vec<u8> = (0..config::REGISTER_COUNT as u8)
+            .map(|idx| self.inner.register(idx))
+            .collect();
+        PyBytes::new(py, &registers)
+    }
+
+    fn get_register(&self, index: u8) -> PyResult<u8> {
+        Ok(self.inner.register(check_register(index)?))
+    }
+
+    fn set_register(&mut self, index: u8, value: u8) -> PyResult<()> {
+        self.inner.set_register(check_register(index)?, value);
+        Ok(())
+    }
+
+    #[getter]
+    fn get_i(&self) -> u16 {
+        self.inner.i()
+    }
+
+    #[setter]
+    fn set_i(&mut self, value: u16) {
+        self.inner.set_i(value);
+    }
+
+    #[getter]
+    fn get_pc(&self) -> u16 {
+        self.inner.pc()
+    }
+
+    #[setter]
+    fn set_pc(&mut self, address: u16) {
+        self.inner.set_pc(address);
+    }
+
+    #[getter]
+    fn get_delay_timer(&self) -> u8 {
+        self.inner.timers.get(Timer::Delay)
+    }
+
+    #[setter]
+    fn set_delay_timer(&mut self, value: u8) {
+        self.inner.timers.set(Timer::Delay, value);
+    }
+
+    #[getter]
+    fn get_sound_timer(&self) -> u8 {
+        self.inner.timers.get(Timer::Sound)
+    }
+
+    #[setter]
+    fn set_sound_timer(&mut self, value: u8) {
+        self.inner.timers.set(Timer::Sound, value);
+    }
+
+    /// Whole 4 KiB memory as bytes.
+    #[getter]
+    fn memory<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.inner.memory())
+    }
+
+    fn read_memory<'py>(
+        &self,
+        py: Python<'py>,
+        address: usize,
+        length: usize,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self
+            .inner
+            .read_memory(address, length)
+            .map_err(chip_error)?;
+        Ok(PyBytes::new(py, bytes))
+    }
+
+    fn write_memory(&mut self, address: usize, data: &[u8]) -> PyResult<()> {
+        self.inner.write_memory(address, data).map_err(chip_error)
+    }
+
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.inner.save_state())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.inner
+            .load_state(state)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Independent copy of the machine.
+    fn copy(&self) -> Self {
+        PyChip8 {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "chip8")]
+fn chip8_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyChip8>()?;
+    m.add("Chip8Error", m.py().get_type::<Chip8Error>())?;
+    m.add("DISPLAY_WIDTH", config::DISPLAY_WIDTH)?;
+    m.add("DISPLAY_HEIGHT", config::DISPLAY_HEIGHT)?;
+    Ok(())
+}
@@ -0,0 +1,109 @@
+import numpy as np
+import pytest
+
+import chip8
+
+# 0x200: LD V0, 0x0A
+# 0x202: LD F, V0
+# 0x204: SKNP V1        V1 = 0, draw only while key 0 is held
+# 0x206: DRW V1, V1, 5
+# 0x208: JP 0x208
+ROM = bytes([0x60, 0x0A, 0xF0, 0x29, 0xE1, 0xA1, 0xD1, 0x15, 0x12, 0x08])
+
+
+@pytest.fixture
+def machine():
+    m = chip8.Chip8(seed=1)
+    m.load_rom(ROM)
+    return m
+
+
+def test_step_updates_registers(machine):
+    machine.step()
+    assert machine.pc == 0x202
+    assert machine.get_register(0) == 0x0A
+    assert machine.registers[0] == 0x0A
+
+
+def test_framebuffer_is_numpy_compatible(machine):
+    machine.press(0)
+    machine.run_frame(10)
+    pixels = np.asarray(machine.framebuffer())
+    assert pixels.shape == (chip8.DISPLAY_HEIGHT, chip8.DISPLAY_WIDTH)
+    assert pixels.dtype == np.uint8
+    # font sprite "A"
+    assert pixels[:5, :4].tolist() == [
+        [1, 1, 1, 1],
+        [1, 0, 0, 1],
+        [1, 1, 1, 1],
+        [1, 0, 0, 1],
+        [1, 0, 0, 1],
+    ]
+
+
+def test_release_skips_draw(machine):
+    machine.press(0)
+    machine.release(0)
+    machine.run_frame(10)
+    assert not np.asarray(machine.framebuffer()).any()
+
+
+def test_memory_access(machine):
+    assert machine.read_memory(0x200, 2) == ROM[:2]
+    machine.write_memory(0x300, b"\x01\x02")
+    assert machine.memory[0x300:0x302] == b"\x01\x02"
+    assert len(machine.memory) == 4096
+    with pytest.raises(chip8.Chip8Error):
+        machine.read_memory(2**64 - 1, 16)
+
+
+def test_registers_and_timers_are_writable(machine):
+    machine.set_register(0xF, 7)
+    machine.i = 0x300
+    machine.delay_timer = 5
+    machine.run_frame(0)
+    assert machine.get_register(0xF) == 7
+    assert machine.i == 0x300
+    assert machine.delay_timer == 4
+
+
+def test_fill_audio(machine):
+    machine.sound_timer = 2
+    machine.run_frame(10)
+    samples = np.asarray(machine.fill_audio(1000, 48000))
+    # one timer tick at 48 kHz
+    assert samples.shape == (800,)
+    assert samples.dtype == np.float32
+    assert set(samples.tolist()) == {-1.0, 1.0}
+    assert len(machine.fill_audio(1000, 48000)) == 0
+
+
+def test_save_and_load_state(machine):
+    machine.press(0)
+    machine.run_frame(10)
+    state = machine.save_state()
+
+    other = chip8.Chip8()
+    other.load_state(state)
+    assert other.save_state() == state
+    assert bytes(other.framebuffer()) == bytes(machine.framebuffer())
+
+
+def test_copy_is_independent(machine):
+    fork = machine.copy()
+    fork.step()
+    assert machine.pc == 0x200
+    assert fork.pc == 0x202
+
+
+def test_errors():
+    m = chip8.Chip8()
+    with pytest.raises(chip8.Chip8Error):
+        m.load_rom(bytes(4096))
+    m.load_rom(b"\xff\xff")
+    with pytest.raises(chip8.Chip8Error, match="unknown instruction"):
+        m.step()
+    with pytest.raises(ValueError):
+        m.press(16)
+    with pytest.raises(ValueError):
+        m.load_state(b"garbage")
@@ -0,0 +1,319 @@
+//! Static ROM analysis: reachable code, opcode usage and quirk-sensitive instructions.
+//!
+//! Code is found by following every path from the program start without executing anything,
+//! so jumps through `Bnnn` and self-modifying code may hide some of it.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::{config, instruction::Instruction};
+
+/// Machine a ROM was written for, in order of added opcodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    /// Quirk profile usually expected by ROMs of the platform.
+    pub fn profile(self) -> &'static str {
+        match self {
+            Platform::Chip8 => {
+                "COSMAC VIP: VF reset, shifts read Vy, Fx55/Fx65 increment I, Bnnn adds V0, \
+                 draws wait for vblank and clip"
+            }
+            Platform::SuperChip => {
+                "SUPER-CHIP 1.1: shifts read Vx, Fx55/Fx65 leave I, Bxnn adds Vx, \
+                 no VF reset or vblank wait, draws clip"
+            }
+            Platform::XoChip => {
+                "XO-CHIP: shifts read Vy, Fx55/Fx65 increment I, Bnnn adds V0, \
+                 no VF reset or vblank wait, draws wrap"
+            }
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        })
+    }
+}
+
+/// Behaviour that differs between platforms, see `Platform::profile`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quirk {
+    /// 8xy1, 8xy2 and 8xy3 reset VF.
+    VfReset,
+    /// 8xy6 and 8xyE shift Vy or Vx, only matters when x and y differ.
+    Shift,
+    /// Fx55 and Fx65 increment I.
+    Memory,
+    /// Bnnn adds V0 or Vx.
+    Jump,
+    /// Dxyn waits for vblank and clips or wraps at the edges.
+    Draw,
+}
+
+impl Quirk {
+    pub fn description(self) -> &'static str {
+        match self {
+            Quirk::VfReset => "8xy1/8xy2/8xy3 reset VF",
+            Quirk::Shift => "8xy6/8xyE with x != y shift Vy or Vx",
+            Quirk::Memory => "Fx55/Fx65 increment I or leave it",
+            Quirk::Jump => "Bnnn adds V0 or Vx",
+            Quirk::Draw => "Dxyn waits for vblank, clips or wraps",
+        }
+    }
+}
+
+/// Opcode pattern of a word, e.g. `8xy6`, and the platform that added it.
+pub fn opcode(word: u16) -> Option<(&'static str, Platform)> {
+    use Platform::*;
+
+    let pattern = match Instruction::decode(word) {
+        // draws of 16x16 sprites
+        Some(Instruction::Draw(_, _, 0)) => return Some(("Dxy0", SuperChip)),
+        Some(instruction) => return Some((chip8_pattern(instruction), Chip8)),
+        None => word,
+    };
+
+    let extension = match (pattern & 0xf000, pattern & 0x00ff) {
+        (0x0000, _) => match pattern {
+            0x00fb => ("00FB", SuperChip),
+            0x00fc => ("00FC", SuperChip),
+            0x00fd => ("00FD", SuperChip),
+            0x00fe => ("00FE", SuperChip),
+            0x00ff => ("00FF", SuperChip),
+            0x00c0..=0x00cf => ("00Cn", SuperChip),
+            0x00d0..=0x00df => ("00Dn", XoChip),
+            _ => return None,
+        },
+        (0x5000, byte) if byte & 0x0f == 0x2 => ("5xy2", XoChip),
+        (0x5000, byte) if byte & 0x0f == 0x3 => ("5xy3", XoChip),
+        (0xf000, 0x30) => ("Fx30", SuperChip),
+        (0xf000, 0x75) => ("Fx75", SuperChip),
+        (0xf000, 0x85) => ("Fx85", SuperChip),
+        (0xf000, 0x01) => ("Fn01", XoChip),
+        (0xf000, 0x3a) => ("Fx3A", XoChip),
+        _ if pattern == 0xf000 => ("F000 nnnn", XoChip),
+        _ if pattern == 0xf002 => ("F002", XoChip),
+        _ => return None,
+    };
+
+    Some(extension)
+}
+
+fn chip8_pattern(instruction: Instruction) -> &'static str {
+    match instruction {
+        Instruction::ClearDisplay => "00E0",
+        Instruction::Return => "00EE",
+        Instruction::Jump(_) => "1nnn",
+        Instruction::Call(_) => "2nnn",
+        Instruction::SkipEqual(..) => "3xkk",
+        Instruction::SkipNotEqual(..) => "4xkk",
+        Instruction::SkipEqualRegisters(..) => "5xy0",
+        Instruction::LoadValue(..) => "6xkk",
+        Instruction::AddValue(..) => "7xkk",
+        Instruction::LoadRegister(..) => "8xy0",
+        Instruction::Or(..) => "8xy1",
+        Instruction::And(..) => "8xy2",
+        Instruction::Xor(..) => "8xy3",
+        Instruction::AddRegister(..) => "8xy4",
+        Instruction::SubtractRegister(..) => "8xy5",
+        Instruction::ShiftRight(..) => "8xy6",
+        Instruction::SubtractNegative(..) => "8xy7",
+        Instruction::ShiftLeft(..) => "8xyE",
+        Instruction::SkipNotEqualRegisters(..) => "9xy0",
+        Instruction::SetI(_) => "Annn",
+        Instruction::JumpV0(_) => "Bnnn",
+        Instruction::Random(..) => "Cxkk",
+        Instruction::Draw(..) => "Dxyn",
+        Instruction::SkipKeyPressed(_) => "Ex9E",
+        Instruction::SkipKeyNotPressed(_) => "ExA1",
+        Instruction::LoadDelayTimer(_) => "Fx07",
+        Instruction::WaitForKey(_) => "Fx0A",
+        Instruction::SetDelayTimer(_) => "Fx15",
+        Instruction::SetSoundTimer(_) => "Fx18",
+        Instruction::AddI(_) => "Fx1E",
+        Instruction::SetSpriteLocation(_) => "Fx29",
+        Instruction::LoadBcd(_) => "Fx33",
+        Instruction::StoreRegisters(_) => "Fx55",
+        Instruction::LoadRegisters(_) => "Fx65",
+    }
+}
+
+fn quirk(instruction: Instruction) -> Option<Quirk> {
+    match instruction {
+        Instruction::Or(..) | Instruction::And(..) | Instruction::Xor(..) => Some(Quirk::VfReset),
+        Instruction::ShiftRight(x, y) | Instruction::ShiftLeft(x, y) if x != y => {
+            Some(Quirk::Shift)
+        }
+        Instruction::StoreRegisters(_) | Instruction::LoadRegisters(_) => Some(Quirk::Memory),
+        Instruction::JumpV0(_) => Some(Quirk::Jump),
+        Instruction::Draw(..) => Some(Quirk::Draw),
+        _ => None,
+    }
+}
+
+/// What a ROM's reachable code uses.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    /// Addresses of reachable instructions.
+    pub reachable: BTreeSet<u16>,
+    /// Reachable instructions per opcode pattern.
+    pub opcodes: BTreeMap<&'static str, (Platform, usize)>,
+    /// Reachable words no platform defines, with their address.
+    pub undecodable: Vec<(u16, u16)>,
+    /// Addresses of quirk-sensitive instructions.
+    pub quirks: BTreeMap<Quirk, Vec<u16>>,
+    /// `Bnnn` jumps, code only they lead to is missed.
+    pub dynamic_jumps: Vec<u16>,
+    /// Addresses of instructions leading outside the ROM.
+    pub leaves_rom: Vec<u16>,
+}
+
+impl Analysis {
+    /// Newest platform whose opcodes the ROM uses.
+    pub fn platform(&self) -> Platform {
+        self.opcodes
+            .values()
+            .map(|(platform, _)| *platform)
+            .max()
+            .unwrap_or(Platform::Chip8)
+    }
+}
+
+/// Follows every path from the program start through `rom`.
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let start = config::PROGRAM_START_ADDRESS as u16;
+    let end = config::PROGRAM_START_ADDRESS + rom.len();
+    let word_at = |address: u16| {
+        let offset = address as usize - config::PROGRAM_START_ADDRESS;
+        u16::from_be_bytes([rom[offset], rom[offset + 1]])
+    };
+    let in_rom = |address: u16| address >= start && (address as usize) + 1 < end;
+
+    let mut analysis = Analysis::default();
+    let mut pending = vec![start];
+    while let Some(address) = pending.pop() {
+        if !in_rom(address) || !analysis.reachable.insert(address) {
+            continue;
+        }
+        let word = word_at(address);
+
+        let Some((pattern, platform)) = opcode(word) else {
+            analysis.undecodable.push((address, word));
+            continue;
+        };
+        analysis.opcodes.entry(pattern).or_insert((platform, 0)).1 += 1;
+
+        let next = address.wrapping_add(2);
+        let mut targets = Vec::new();
+        match Instruction::decode(word) {
+            Some(Instruction::Return) => (),
+            Some(Instruction::Jump(target)) => targets.push(target),
+            Some(Instruction::Call(target)) => targets.extend([target, next]),
+            Some(Instruction::JumpV0(target)) => {
+                analysis.dynamic_jumps.push(address);
+                targets.push(target);
+            }
+            Some(instruction) if instruction.is_skip() => {
+                // skipping the XO-CHIP long load skips both of its words
+                let skipped = if in_rom(next) && word_at(next) == 0xf000 {
+                    4
+                } else {
+                    2
+                };
+                targets.extend([next, next.wrapping_add(skipped)]);
+            }
+            Some(_) => targets.push(next),
+            // 00FD exits
+            None if word == 0x00fd => (),
+            None if word == 0xf000 => targets.push(address.wrapping_add(4)),
+            None => targets.push(next),
+        }
+        if let Some(quirk) = Instruction::decode(word).and_then(quirk) {
+            analysis.quirks.entry(quirk).or_default().push(address);
+        }
+
+        if targets.iter().any(|&target| !in_rom(target)) {
+            analysis.leaves_rom.push(address);
+        }
+        pending.extend(targets);
+    }
+
+    analysis.undecodable.sort_unstable();
+    analysis.dynamic_jumps.sort_unstable();
+    analysis.leaves_rom.sort_unstable();
+    for addresses in analysis.quirks.values_mut() {
+        addresses.sort_unstable();
+    }
+
+    analysis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analysis_opcode() {
+        assert_eq!(Some(("8xy6", Platform::Chip8)), opcode(0x8126));
+        assert_eq!(Some(("Dxy0", Platform::SuperChip)), opcode(0xd120));
+        assert_eq!(Some(("00Cn", Platform::SuperChip)), opcode(0x00c4));
+        assert_eq!(Some(("F000 nnnn", Platform::XoChip)), opcode(0xf000));
+        assert_eq!(Some(("Fn01", Platform::XoChip)), opcode(0xf201));
+        assert_eq!(None, opcode(0x0123));
+        assert_eq!(None, opcode(0xe1ff));
+    }
+    #[test]
+    fn test_analysis_reachable_code() {
+        let rom = [
+            0x30, 0x01, // 0x200: SE V0, 1
+            0x12, 0x08, // 0x202: JP 0x208
+            0x22, 0x0c, // 0x204: CALL 0x20C
+            0x12, 0x04, // 0x206: JP 0x206
+            0x81, 0x26, // 0x208: SHR V1, V2
+            0x12, 0x08, // 0x20A: JP 0x208
+            0x00, 0xff, // 0x20C: HIGH
+            0x00, 0xee, // 0x20E: RET
+            0x01, 0x23, // 0x210: data, unreachable
+        ];
+        let analysis = analyze(&rom);
+
+        assert_eq!(8, analysis.reachable.len());
+        assert!(!analysis.reachable.contains(&0x210));
+        assert!(analysis.undecodable.is_empty());
+        assert_eq!(Some(&(Platform::Chip8, 3)), analysis.opcodes.get("1nnn"));
+        assert_eq!(Platform::SuperChip, analysis.platform());
+        assert_eq!(Some(&vec![0x208]), analysis.quirks.get(&Quirk::Shift));
+    }
+    #[test]
+    fn test_analysis_undecodable() {
+        let rom = [
+            0xb2, 0x04, // 0x200: JP V0, 0x204
+            0x01, 0x23, // 0x202: data, unreachable
+            0x01, 0x23, // 0x204: undecodable
+            0x13, 0x00, // 0x206: JP 0x300, unreachable
+        ];
+        let analysis = analyze(&rom);
+
+        assert_eq!(vec![(0x204, 0x0123)], analysis.undecodable);
+        assert_eq!(vec![0x200], analysis.dynamic_jumps);
+        assert_eq!(Some(&vec![0x200]), analysis.quirks.get(&Quirk::Jump));
+        assert!(analysis.leaves_rom.is_empty());
+
+        let analysis = analyze(&[0x13, 0x00]);
+        assert_eq!(vec![0x200], analysis.leaves_rom);
+        assert_eq!(Platform::Chip8, analysis.platform());
+    }
+}
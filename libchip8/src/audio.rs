@@ -0,0 +1,343 @@
+//! Buzzer audio in emulated time.
+//!
+//! Timer ticks are the clock, each lasting 1/60 s. The instructions executed between two ticks
+//! are spread evenly over it, so a beep starts where its `Fx18` ran instead of wherever the host
+//! happened to poll the sound timer.
+
+use std::collections::VecDeque;
+
+use crate::state::Reader;
+
+/// Timer ticks per second.
+pub const TIMER_HZ: u32 = 60;
+/// Completed ticks waiting to be rendered, older ones are dropped when nobody reads them.
+const MAX_QUEUED_TICKS: usize = 60;
+/// Buzzer changes kept per tick, later ones replace the last so the state after it stays right.
+const MAX_TICK_CHANGES: usize = 8;
+
+#[derive(Clone, Default)]
+struct Tick {
+    /// Buzzer state when the tick started.
+    start: bool,
+    /// Instruction index and new state of every change during the tick.
+    changes: Vec<(u64, bool)>,
+    instructions: u64,
+}
+
+impl Tick {
+    /// Buzzer state `position` samples into the tick, which lasts `len` samples.
+    fn state_at(&self, position: u64, len: u64) -> bool {
+        let index = position * self.instructions / len;
+        self.changes
+            .iter()
+            .take_while(|(at, _)| *at <= index)
+            .last()
+            .map_or(self.start, |(_, on)| *on)
+    }
+
+    /// Bytes of a tick in a save state.
+    const STATE_SIZE: usize = 1 + 8 + 1 + MAX_TICK_CHANGES * 9;
+
+    fn save(&self, out: &mut Vec<u8>) {
+        out.push(self.start as u8);
+        out.extend_from_slice(&self.instructions.to_be_bytes());
+        out.push(self.changes.len() as u8);
+        for idx in 0..MAX_TICK_CHANGES {
+            let (at, on) = self.changes.get(idx).copied().unwrap_or_default();
+            out.extend_from_slice(&at.to_be_bytes());
+            out.push(on as u8);
+        }
+    }
+
+    fn load(reader: &mut Reader) -> Option<Self> {
+        let start = reader.u8() != 0;
+        let instructions = u64::from_be_bytes(reader.array());
+        let len = reader.u8() as usize;
+        let mut changes: Vec<(u64, bool)> = (0..MAX_TICK_CHANGES)
+            .map(|_| (u64::from_be_bytes(reader.array()), reader.u8() != 0))
+            .collect();
+        if len > MAX_TICK_CHANGES {
+            return None;
+        }
+        changes.truncate(len);
+
+        Some(Tick {
+            start,
+            changes,
+            instructions,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Audio {
+    /// Pitch of the square wave from `fill_audio` in Hz.
+    pub frequency: f32,
+    current: Tick,
+    on: bool,
+    ticks_seen: u64,
+    queue: VecDeque<Tick>,
+    /// Position in the oldest queued tick, which lasts `sample_rate` units, `TIMER_HZ` per sample.
+    position: u64,
+    /// Sample rate `position` was counted at.
+    sample_rate: u32,
+    /// Square wave phase in periods.
+    phase: f64,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        Self {
+            frequency: 1000.0,
+            current: Tick::default(),
+            on: false,
+            ticks_seen: 0,
+            queue: VecDeque::new(),
+            position: 0,
+            sample_rate: 0,
+            phase: 0.0,
+        }
+    }
+
+    /// Completes the ticks up to `ticks`, `on` is the buzzer state after them.
+    pub(crate) fn sync(&mut self, ticks: u64, on: bool) {
+        // ticks which would be dropped from the queue right away are skipped
+        let unseen = ticks.saturating_sub(self.ticks_seen);
+        self.ticks_seen += unseen.saturating_sub(MAX_QUEUED_TICKS as u64 + 1);
+        while self.ticks_seen < ticks {
+            self.ticks_seen += 1;
+            let next = Tick {
+                start: on,
+                ..Tick::default()
+            };
+            if self.queue.len() == MAX_QUEUED_TICKS {
+                self.queue.pop_front();
+                self.position = 0;
+            }
+            self.queue
+                .push_back(std::mem::replace(&mut self.current, next));
+            self.on = on;
+        }
+        // the host may set the sound timer between instructions
+        self.record(on);
+    }
+
+    fn record(&mut self, on: bool) {
+        if on != self.on {
+            let change = (self.current.instructions, on);
+            if self.current.changes.len() == MAX_TICK_CHANGES {
+                self.current.changes[MAX_TICK_CHANGES - 1] = change;
+            } else {
+                self.current.changes.push(change);
+            }
+            self.on = on;
+        }
+    }
+
+    /// Records the buzzer state after an instruction.
+    pub(crate) fn instruction(&mut self, on: bool) {
+        self.record(on);
+        self.current.instructions += 1;
+    }
+
+    /// Counts instructions which left the buzzer alone.
+    pub(crate) fn instructions(&mut self, count: u64) {
+        self.current.instructions += count;
+    }
+
+    /// Calls `write` with the buzzer state of each sample while completed ticks last.
+    fn render(
+        &mut self,
+        len: usize,
+        sample_rate: u32,
+        mut write: impl FnMut(usize, bool),
+    ) -> usize {
+        if sample_rate == 0 {
+            return 0;
+        }
+
+        // counting in integers keeps ticks exactly `sample_rate / TIMER_HZ` samples long
+        if sample_rate != self.sample_rate {
+            if self.sample_rate > 0 {
+                self.position = self.position * sample_rate as u64 / self.sample_rate as u64;
+            }
+            self.sample_rate = sample_rate;
+        }
+
+        let tick_len = sample_rate as u64;
+        for i in 0..len {
+            let Some(tick) = self.queue.front() else {
+                return i;
+            };
+            write(i, tick.state_at(self.position, tick_len));
+
+            self.position += TIMER_HZ as u64;
+            if self.position >= tick_len {
+                self.position -= tick_len;
+                self.queue.pop_front();
+            }
+        }
+
+        len
+    }
+
+    /// Writes the buzzer state of each sample, see `Chip8::fill_audio`.
+    pub fn fill_buzzer(&mut self, out: &mut [bool], sample_rate: u32) -> usize {
+        self.render(out.len(), sample_rate, |i, on| out[i] = on)
+    }
+
+    /// Writes a square wave from -1 to 1 while the buzzer is on and 0 while it is off.
+    pub fn fill_audio(&mut self, out: &mut [f32], sample_rate: u32) -> usize {
+        let step = self.frequency as f64 / sample_rate as f64;
+        let mut phase = self.phase;
+
+        let written = self.render(out.len(), sample_rate, |i, on| {
+            out[i] = match (on, phase < 0.5) {
+                (false, _) => 0.0,
+                (true, true) => 1.0,
+                (true, false) => -1.0,
+            };
+            phase = (phase + step).fract();
+        });
+        self.phase = phase;
+
+        written
+    }
+
+    /// Bytes of the audio in a save state.
+    pub(crate) const STATE_SIZE: usize =
+        4 + 1 + 8 + 8 + 4 + 8 + 1 + (MAX_QUEUED_TICKS + 1) * Tick::STATE_SIZE;
+
+    /// Appends the buzzer history and playback position to a save state.
+    pub(crate) fn save(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.frequency.to_be_bytes());
+        out.push(self.on as u8);
+        out.extend_from_slice(&self.ticks_seen.to_be_bytes());
+        out.extend_from_slice(&self.position.to_be_bytes());
+        out.extend_from_slice(&self.sample_rate.to_be_bytes());
+        out.extend_from_slice(&self.phase.to_be_bytes());
+        self.current.save(out);
+        out.push(self.queue.len() as u8);
+        for idx in 0..MAX_QUEUED_TICKS {
+            self.queue.get(idx).cloned().unwrap_or_default().save(out);
+        }
+    }
+
+    /// Reads audio written by `save`, `None` if it could not have been.
+    pub(crate) fn load(reader: &mut Reader) -> Option<Self> {
+        let frequency = f32::from_be_bytes(reader.array());
+        let on = reader.u8() != 0;
+        let ticks_seen = u64::from_be_bytes(reader.array());
+        let position = u64::from_be_bytes(reader.array());
+        let sample_rate = u32::from_be_bytes(reader.array());
+        let phase = f64::from_be_bytes(reader.array());
+        let current = Tick::load(reader)?;
+        let len = reader.u8() as usize;
+        let mut queue = (0..MAX_QUEUED_TICKS)
+            .map(|_| Tick::load(reader))
+            .collect::<Option<VecDeque<Tick>>>()?;
+
+        let valid = frequency.is_finite()
+            && position < sample_rate.max(1) as u64
+            && (0.0..1.0).contains(&phase)
+            && len <= MAX_QUEUED_TICKS;
+        if !valid {
+            return None;
+        }
+        queue.truncate(len);
+
+        Some(Self {
+            frequency,
+            current,
+            on,
+            ticks_seen,
+            queue,
+            position,
+            sample_rate,
+            phase,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Chip8, timers::Timer};
+
+    // 0x200: LD V0, 5
+    // 0x202: LD V1, 0 (x4)
+    // 0x20A: LD ST, V0
+    // 0x20C: JP 0x20C
+    const ROM: [u8; 14] = [
+        0x60, 0x05, 0x61, 0x00, 0x61, 0x00, 0x61, 0x00, 0x61, 0x00, 0xf0, 0x18, 0x12, 0x0c,
+    ];
+
+    fn chip8() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&ROM).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn test_audio_buzzer_timing() {
+        let mut chip8 = chip8();
+        for _ in 0..6 {
+            chip8.run_frame(10).unwrap();
+        }
+
+        // 10 samples per tick, one per instruction
+        let mut buzzer = [false; 100];
+        assert_eq!(60, chip8.fill_buzzer(&mut buzzer, 600));
+        // on after the sixth instruction, off after five timer ticks
+        assert!(buzzer[..5].iter().all(|on| !on));
+        assert!(buzzer[5..50].iter().all(|on| *on));
+        assert!(buzzer[50..60].iter().all(|on| !on));
+
+        // nothing left until emulation continues
+        assert_eq!(0, chip8.fill_buzzer(&mut buzzer, 600));
+    }
+    #[test]
+    fn test_audio_square_wave() {
+        let mut chip8 = chip8();
+        chip8.audio.frequency = 150.0;
+        for _ in 0..2 {
+            chip8.run_frame(10).unwrap();
+        }
+
+        // 4 samples per period
+        let mut samples = [0.0; 20];
+        assert_eq!(20, chip8.fill_audio(&mut samples, 600));
+        assert_eq!([0.0; 5], samples[..5]);
+        // the wave keeps its phase while silent
+        assert_eq!([1.0, -1.0, -1.0, 1.0, 1.0], samples[5..10]);
+        assert!(samples[10..].iter().all(|sample| sample.abs() == 1.0));
+    }
+    #[test]
+    fn test_audio_queue_limit() {
+        let mut chip8 = chip8();
+        for _ in 0..200 {
+            chip8.run_frame(10).unwrap();
+        }
+
+        let mut buzzer = [true; 1000];
+        assert_eq!(600, chip8.fill_buzzer(&mut buzzer, 600));
+        assert_eq!(0, chip8.fill_audio(&mut [0.0; 10], 0));
+    }
+    #[test]
+    fn test_audio_host_sets_timer() {
+        let mut chip8 = chip8();
+        chip8.timers.set(Timer::Sound, 1);
+        chip8.run_frame(4).unwrap();
+        chip8.run_frame(0).unwrap();
+
+        let mut buzzer = [false; 20];
+        assert_eq!(20, chip8.fill_buzzer(&mut buzzer, 600));
+        assert!(buzzer[..10].iter().all(|on| *on));
+        assert!(buzzer[10..].iter().all(|on| !on));
+    }
+}
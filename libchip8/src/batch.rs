@@ -0,0 +1,262 @@
+//! Runs many machines in parallel, e.g. forks of one `Chip8` exploring
+//! different inputs.
+
+use std::thread;
+
+use crate::{Chip8, ChipError, config, recompiler::Recompiler};
+
+/// Key change applied right before the given frame runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// How a job executes instructions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Fetches and decodes every instruction with `Chip8::tick`.
+    #[default]
+    Interpreter,
+    /// Runs cached basic blocks, see `Recompiler`.
+    Recompiler,
+}
+
+/// One machine together with its inputs.
+#[derive(Clone)]
+pub struct BatchJob {
+    pub chip8: Chip8,
+    pub inputs: Vec<InputEvent>,
+    pub backend: Backend,
+}
+
+/// Final state of a job.
+pub struct BatchResult {
+    pub chip8: Chip8,
+    /// Frames completed before the run finished or failed.
+    pub frames: u32,
+    pub error: Option<ChipError>,
+}
+
+impl BatchResult {
+    pub fn framebuffer(&self) -> &[bool] {
+        self.chip8.display.dump()
+    }
+
+    pub fn registers(&self) -> [u8; config::REGISTER_COUNT] {
+        std::array::from_fn(|idx| self.chip8.cpu.v.get(idx as u8))
+    }
+}
+
+impl BatchJob {
+    pub fn new(chip8: Chip8, inputs: Vec<InputEvent>) -> Self {
+        BatchJob {
+            chip8,
+            inputs,
+            backend: Backend::default(),
+        }
+    }
+
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Runs the job on the current thread.
+    pub fn run(mut self, frames: u32, cycles_per_frame: u32) -> BatchResult {
+        self.inputs.sort_by_key(|input| input.frame);
+        let mut inputs = self.inputs.iter().peekable();
+        let mut recompiler = (self.backend == Backend::Recompiler).then(Recompiler::new);
+
+        for frame in 0..frames {
+            while let Some(input) = inputs.next_if(|input| input.frame <= frame) {
+                self.chip8.keyboard.set_key(input.key, input.pressed);
+            }
+
+            let result = match &mut recompiler {
+                Some(recompiler) => recompiler.run_frame(&mut self.chip8, cycles_per_frame),
+                None => self.chip8.run_frame(cycles_per_frame),
+            };
+            if let Err(e) = result {
+                return BatchResult {
+                    chip8: self.chip8,
+                    frames: frame,
+                    error: Some(e),
+                };
+            }
+        }
+
+        BatchResult {
+            chip8: self.chip8,
+            frames,
+            error: None,
+        }
+    }
+}
+
+/// Runs every job for `frames` frames across all available cores.
+///
+/// Results are returned in the same order as the jobs.
+pub fn run(jobs: Vec<BatchJob>, frames: u32, cycles_per_frame: u32) -> Vec<BatchResult> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    run_with_threads(jobs, frames, cycles_per_frame, threads)
+}
+
+/// Same as `run`, with an explicit number of worker threads.
+pub fn run_with_threads(
+    jobs: Vec<BatchJob>,
+    frames: u32,
+    cycles_per_frame: u32,
+    threads: usize,
+) -> Vec<BatchResult> {
+    if jobs.is_empty() {
+        return Vec::new();
+    }
+
+    let chunk_size = jobs.len().div_ceil(threads.max(1));
+    let mut chunks: Vec<Vec<BatchJob>> = Vec::new();
+    let mut jobs = jobs.into_iter().peekable();
+    while jobs.peek().is_some() {
+        chunks.push(jobs.by_ref().take(chunk_size).collect());
+    }
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .into_iter()
+                        .map(|job| job.run(frames, cycles_per_frame))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("batch worker panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StackError;
+
+    // 0x200: LD V1, 0
+    // 0x202: SKNP V1      skip while key 0 is up
+    // 0x204: ADD V0, 1
+    // 0x206: JP 0x202
+    const ROM: [u8; 8] = [0x61, 0x00, 0xe1, 0xa1, 0x70, 0x01, 0x12, 0x02];
+
+    fn assert_clone_send<T: Clone + Send>() {}
+
+    #[test]
+    fn test_chip8_is_clone_and_send() {
+        assert_clone_send::<Chip8>();
+    }
+    #[test]
+    fn test_batch_forks_diverge() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&ROM).unwrap();
+        chip8.run_frame(3).unwrap();
+
+        let idle = BatchJob::new(chip8.clone(), vec![]);
+        let pressed = BatchJob::new(
+            chip8,
+            vec![InputEvent {
+                frame: 1,
+                key: 0,
+                pressed: true,
+            }],
+        );
+        let results = run_with_threads(vec![idle, pressed], 2, 6, 2);
+
+        assert_eq!(0, results[0].registers()[0]);
+        assert_eq!(2, results[1].registers()[0]);
+        assert!(results.iter().all(|r| r.error.is_none() && r.frames == 2));
+    }
+    #[test]
+    fn test_batch_keeps_order() {
+        let jobs = (0..10u8)
+            .map(|value| {
+                let mut chip8 = Chip8::new();
+                // LD V0, value; JP 0x202
+                chip8.load_rom(&[0x60, value, 0x12, 0x02]).unwrap();
+                BatchJob::new(chip8, vec![])
+            })
+            .collect();
+        let results = run_with_threads(jobs, 1, 4, 3);
+
+        let values: Vec<u8> = results.iter().map(|r| r.registers()[0]).collect();
+        assert_eq!((0..10).collect::<Vec<u8>>(), values);
+    }
+    #[test]
+    fn test_batch_backends_agree() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&ROM).unwrap();
+        let inputs = vec![
+            InputEvent {
+                frame: 2,
+                key: 0,
+                pressed: true,
+            },
+            InputEvent {
+                frame: 5,
+                key: 0,
+                pressed: false,
+            },
+        ];
+        let jobs = [Backend::Interpreter, Backend::Recompiler]
+            .map(|backend| BatchJob::new(chip8.clone(), inputs.clone()).with_backend(backend));
+        let results = run_with_threads(jobs.to_vec(), 8, 7, 2);
+
+        assert_eq!(results[0].registers(), results[1].registers());
+        assert_eq!(results[0].chip8.cpu.pc.get(), results[1].chip8.cpu.pc.get());
+        assert!(results[0].registers()[0] > 0);
+    }
+    #[test]
+    fn test_batch_failing_job_keeps_others() {
+        let jobs = [[0x60, 0x07, 0x12, 0x02], [0x00, 0xee, 0x12, 0x02]]
+            .iter()
+            .cycle()
+            .take(6)
+            .map(|rom| {
+                // LD V0, 7; JP 0x202 or RET with an empty stack
+                let mut chip8 = Chip8::new();
+                chip8.load_rom(rom).unwrap();
+                BatchJob::new(chip8, vec![])
+            })
+            .collect();
+        let results = run_with_threads(jobs, 3, 4, 2);
+
+        for (idx, result) in results.iter().enumerate() {
+            if idx % 2 == 0 {
+                assert!(result.error.is_none());
+                assert_eq!(3, result.frames);
+                assert_eq!(7, result.registers()[0]);
+            } else {
+                assert!(matches!(
+                    result.error,
+                    Some(ChipError::StackError(StackError::Underflow))
+                ));
+                assert_eq!(0, result.frames);
+            }
+        }
+    }
+    #[test]
+    fn test_batch_reports_errors() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0xff, 0xff]).unwrap();
+        let results = run(vec![BatchJob::new(chip8, vec![])], 5, 10);
+
+        assert_eq!(0, results[0].frames);
+        assert!(matches!(
+            results[0].error,
+            Some(ChipError::UnknownInstruction(0xffff))
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{Chip8, ChipError, config, instruction::Instruction, listing::Listing};
+
+/// How often a skip instruction skipped (taken) or fell through (not taken).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BranchHits {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Collects executed addresses and skip outcomes while driving a `Chip8`.
+pub struct Coverage {
+    instruction_hits: Vec<u64>,
+    branches: BTreeMap<u16, BranchHits>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage {
+            instruction_hits: vec![0; config::MEMORY_SIZE],
+            branches: BTreeMap::new(),
+        }
+    }
+
+    /// Executes next instruction and records it.
+    pub fn tick(&mut self, chip8: &mut Chip8) -> Result<(), ChipError> {
+        let pc = chip8.cpu.pc.get();
+        let instruction = chip8.fetch().map_err(ChipError::MemoryError)?;
+        chip8.execute(instruction)?;
+
+        self.instruction_hits[pc as usize] += 1;
+
+        if Instruction::decode(instruction).is_some_and(|i| i.is_skip()) {
+            let hits = self.branches.entry(pc).or_default();
+            if chip8.cpu.pc.get() == pc.wrapping_add(4) {
+                hits.taken += 1;
+            } else {
+                hits.not_taken += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns how many times an instruction starting at the address was executed.
+    pub fn hits(&self, address: u16) -> u64 {
+        self.instruction_hits
+            .get(address as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns true if the byte at the address was part of an executed instruction.
+    pub fn is_byte_executed(&self, address: u16) -> bool {
+        self.hits(address) > 0 || (address > 0 && self.hits(address - 1) > 0)
+    }
+
+    /// Returns recorded skip outcomes for the instruction at the address.
+    pub fn branch(&self, address: u16) -> Option<BranchHits> {
+        self.branches.get(&address).copied()
+    }
+
+    /// Renders an lcov tracefile for the ROM.
+    ///
+    /// With a listing, lines refer to listing lines of every listed address.
+    /// Without one, every ROM word gets its own line starting at 1 for 0x200.
+    pub fn lcov(&self, rom: &[u8], source_name: &str, listing: Option<&Listing>) -> String {
+        let lines: Vec<(u16, usize)> = match listing {
+            Some(listing) => listing.iter().collect(),
+            None => (0..rom.len().div_ceil(2))
+                .map(|word| ((config::PROGRAM_START_ADDRESS + word * 2) as u16, word + 1))
+                .collect(),
+        };
+
+        let mut out = String::new();
+        let mut lines_hit = 0;
+        let mut branches_found = 0;
+        let mut branches_hit = 0;
+
+        writeln!(out, "TN:").unwrap();
+        writeln!(out, "SF:{}", source_name).unwrap();
+        for &(address, line) in &lines {
+            let hits = self.hits(address);
+            if hits > 0 {
+                lines_hit += 1;
+            }
+            writeln!(out, "DA:{},{}", line, hits).unwrap();
+        }
+        for &(address, line) in &lines {
+            let is_skip = word_at(rom, address)
+                .and_then(Instruction::decode)
+                .is_some_and(|i| i.is_skip());
+            if !is_skip {
+                continue;
+            }
+
+            branches_found += 2;
+            match self.branch(address) {
+                Some(branch) => {
+                    branches_hit += (branch.taken > 0) as usize + (branch.not_taken > 0) as usize;
+                    writeln!(out, "BRDA:{},0,0,{}", line, branch.taken).unwrap();
+                    writeln!(out, "BRDA:{},0,1,{}", line, branch.not_taken).unwrap();
+                }
+                None => {
+                    writeln!(out, "BRDA:{},0,0,-", line).unwrap();
+                    writeln!(out, "BRDA:{},0,1,-", line).unwrap();
+                }
+            }
+        }
+        writeln!(out, "BRF:{}", branches_found).unwrap();
+        writeln!(out, "BRH:{}", branches_hit).unwrap();
+        writeln!(out, "LF:{}", lines.len()).unwrap();
+        writeln!(out, "LH:{}", lines_hit).unwrap();
+        writeln!(out, "end_of_record").unwrap();
+
+        out
+    }
+
+    /// Renders the ROM as disassembly annotated with hit counts.
+    ///
+    /// Executed instructions show their hit count (and skip outcomes),
+    /// bytes never executed are marked with `-----`.
+    pub fn annotated_disassembly(&self, rom: &[u8], listing: Option<&Listing>) -> String {
+        let mut out = String::new();
+        let start = config::PROGRAM_START_ADDRESS;
+        let mut offset = 0;
+
+        while offset < rom.len() {
+            let address = (start + offset) as u16;
+            let line = listing
+                .and_then(|l| l.line_for(address))
+                .map(|l| format!("  ; line {}", l))
+                .unwrap_or_default();
+            let hits = self.hits(address);
+
+            if hits > 0 {
+                let word = word_at(rom, address).unwrap_or(rom[offset] as u16);
+                let text = Instruction::decode(word)
+                    .map(|i| i.to_string())
+                    .unwrap_or_else(|| "???".to_string());
+                let branch = self
+                    .branch(address)
+                    .map(|b| format!("  [taken {}, not taken {}]", b.taken, b.not_taken))
+                    .unwrap_or_default();
+                let row = format!(
+                    "{:04X}  {:04X}  {:>10}  {:<18}{}{}",
+                    address, word, hits, text, branch, line
+                );
+                writeln!(out, "{}", row.trim_end()).unwrap();
+                offset += 2;
+            } else if offset + 1 < rom.len() && self.hits(address + 1) == 0 {
+                let word = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+                writeln!(
+                    out,
+                    "{:04X}  {:04X}  {:>10}{}",
+                    address, word, "-----", line
+                )
+                .unwrap();
+                offset += 2;
+            } else {
+                writeln!(
+                    out,
+                    "{:04X}  {:02X}    {:>10}{}",
+                    address, rom[offset], "-----", line
+                )
+                .unwrap();
+                offset += 1;
+            }
+        }
+
+        out
+    }
+}
+
+fn word_at(rom: &[u8], address: u16) -> Option<u16> {
+    let offset = (address as usize).checked_sub(config::PROGRAM_START_ADDRESS)?;
+    let bytes = rom.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: LD V0, 1
+    // 0x202: SE V0, 1
+    // 0x204: CLS        (skipped)
+    // 0x206: JP 0x206
+    const ROM: [u8; 8] = [0x60, 0x01, 0x30, 0x01, 0x00, 0xe0, 0x12, 0x06];
+
+    fn run(cycles: usize) -> Coverage {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&ROM).unwrap();
+        let mut coverage = Coverage::new();
+        for _ in 0..cycles {
+            coverage.tick(&mut chip8).unwrap();
+        }
+        coverage
+    }
+
+    #[test]
+    fn test_coverage_hits() {
+        let coverage = run(5);
+        assert_eq!(1, coverage.hits(0x200));
+        assert_eq!(0, coverage.hits(0x204));
+        assert_eq!(3, coverage.hits(0x206));
+        assert!(coverage.is_byte_executed(0x207));
+        assert!(!coverage.is_byte_executed(0x205));
+    }
+    #[test]
+    fn test_coverage_branches() {
+        let coverage = run(3);
+        assert_eq!(
+            Some(BranchHits {
+                taken: 1,
+                not_taken: 0
+            }),
+            coverage.branch(0x202)
+        );
+        assert_eq!(None, coverage.branch(0x200));
+    }
+    #[test]
+    fn test_coverage_lcov() {
+        let report = run(3).lcov(&ROM, "rom.ch8", None);
+        assert!(report.contains("DA:1,1\nDA:2,1\nDA:3,0\nDA:4,1\n"));
+        assert!(report.contains("BRDA:2,0,0,1\nBRDA:2,0,1,0\n"));
+        assert!(report.contains("LH:3\n"));
+    }
+    #[test]
+    fn test_coverage_lcov_with_listing() {
+        let listing = Listing::parse("start:\n0200: 6001\n0202: 3001\n0204: 00E0\n0206: 1206\n");
+        let report = run(3).lcov(&ROM, "rom.lst", Some(&listing));
+        assert!(report.contains("SF:rom.lst\n"));
+        assert!(report.contains("DA:2,1\nDA:3,1\nDA:4,0\nDA:5,1\n"));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::config;
+#[derive(Clone)]
 pub struct Cpu {
     pub v: GeneralRegisters,
     pub i: IRegister,
@@ -14,6 +15,7 @@ impl Cpu {
     }
 }
 
+#[derive(Clone)]
 pub struct GeneralRegisters([u8; config::REGISTER_COUNT]);
 impl GeneralRegisters {
     fn bounds_check(&self, index: u8) -> usize {
@@ -36,6 +38,7 @@ impl GeneralRegisters {
     }
 }
 
+#[derive(Clone)]
 pub struct IRegister(u16);
 impl IRegister {
     pub fn set(&mut self, value: u16) {
@@ -47,6 +50,7 @@ impl IRegister {
     }
 }
 
+#[derive(Clone)]
 pub struct ProgramCounter(u16);
 impl ProgramCounter {
     pub fn set(&mut self, address: u16) {
@@ -0,0 +1,222 @@
+//! Breakpoints, watchpoints and stepping, shared by the debugger frontends.
+
+use std::{collections::BTreeSet, ops::Range};
+
+use crate::{Chip8, ChipError, instruction::Instruction};
+
+/// Kind of memory access a watchpoint reacts to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Write,
+    Read,
+    /// Read or write.
+    Access,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub address: u16,
+    pub len: u16,
+    pub kind: WatchKind,
+}
+
+/// Why execution stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// About to execute the instruction at this address.
+    Breakpoint(u16),
+    /// Instruction that just executed accessed a watched address.
+    Watchpoint { kind: WatchKind, address: u16 },
+}
+
+#[derive(Clone, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    resuming: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Returns whether the breakpoint existed.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    /// Returns whether the watchpoint existed.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != watchpoint);
+        len != self.watchpoints.len()
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    /// Lets the next step execute even if a breakpoint is set at the current address.
+    pub fn resume(&mut self) {
+        self.resuming = true;
+    }
+
+    /// Executes one instruction, see `step_with`.
+    pub fn step(&mut self, chip8: &mut Chip8) -> Result<Option<Stop>, ChipError> {
+        self.step_with(chip8, Chip8::tick)
+    }
+
+    /// Executes one instruction with `exec` unless a breakpoint stops it first.
+    ///
+    /// `exec` lets the caller run its own timing around the instruction.
+    pub fn step_with<F>(&mut self, chip8: &mut Chip8, exec: F) -> Result<Option<Stop>, ChipError>
+    where
+        F: FnOnce(&mut Chip8) -> Result<(), ChipError>,
+    {
+        let pc = chip8.cpu.pc.get();
+        if !std::mem::take(&mut self.resuming) && self.breakpoints.contains(&pc) {
+            return Ok(Some(Stop::Breakpoint(pc)));
+        }
+
+        let access = memory_access(chip8);
+        exec(chip8)?;
+
+        let Some((kind, range)) = access else {
+            return Ok(None);
+        };
+        let hit = self.watchpoints.iter().find_map(|watch| {
+            let matches = watch.kind == WatchKind::Access || watch.kind == kind;
+            let watched = watch.address as usize..watch.address as usize + watch.len as usize;
+            let address = range.start.max(watched.start);
+            (matches && address < range.end.min(watched.end)).then_some(Stop::Watchpoint {
+                kind: watch.kind,
+                address: address as u16,
+            })
+        });
+
+        Ok(hit)
+    }
+
+    /// Runs until a breakpoint or watchpoint stops execution, or `cycles` instructions ran.
+    pub fn run(&mut self, chip8: &mut Chip8, cycles: u64) -> Result<Option<Stop>, ChipError> {
+        for _ in 0..cycles {
+            if let Some(stop) = self.step(chip8)? {
+                return Ok(Some(stop));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Memory the next instruction reads or writes as data.
+fn memory_access(chip8: &Chip8) -> Option<(WatchKind, Range<usize>)> {
+    let instruction = Instruction::decode(chip8.fetch().ok()?)?;
+    let i = chip8.cpu.i.get() as usize;
+
+    match instruction {
+        Instruction::Draw(_, _, n) => Some((WatchKind::Read, i..i + n as usize)),
+        Instruction::LoadRegisters(x) => Some((WatchKind::Read, i..i + x as usize + 1)),
+        Instruction::StoreRegisters(x) => Some((WatchKind::Write, i..i + x as usize + 1)),
+        Instruction::LoadBcd(_) => Some((WatchKind::Write, i..i + 3)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: LD I, 0x300
+    // 0x202: LD [I], V1
+    // 0x204: LD I, 0x300
+    // 0x206: LD V1, [I]
+    // 0x208: JP 0x200
+    const ROM: [u8; 10] = [0xa3, 0x00, 0xf1, 0x55, 0xa3, 0x00, 0xf1, 0x65, 0x12, 0x00];
+
+    fn chip8() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&ROM).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn test_debugger_breakpoint() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x204);
+
+        assert_eq!(
+            Some(Stop::Breakpoint(0x204)),
+            debugger.run(&mut chip8, 100).unwrap()
+        );
+        assert_eq!(0x204, chip8.cpu.pc.get());
+
+        // stays on the breakpoint until resumed
+        assert_eq!(
+            Some(Stop::Breakpoint(0x204)),
+            debugger.step(&mut chip8).unwrap()
+        );
+        debugger.resume();
+        assert_eq!(None, debugger.step(&mut chip8).unwrap());
+        assert_eq!(0x206, chip8.cpu.pc.get());
+
+        assert!(debugger.remove_breakpoint(0x204));
+        assert_eq!(None, debugger.run(&mut chip8, 100).unwrap());
+    }
+    #[test]
+    fn test_debugger_watchpoints() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new();
+        let read = Watchpoint {
+            address: 0x301,
+            len: 1,
+            kind: WatchKind::Read,
+        };
+        debugger.add_watchpoint(read);
+
+        assert_eq!(
+            Some(Stop::Watchpoint {
+                kind: WatchKind::Read,
+                address: 0x301
+            }),
+            debugger.run(&mut chip8, 100).unwrap()
+        );
+        assert_eq!(0x208, chip8.cpu.pc.get());
+
+        debugger.remove_watchpoint(read);
+        debugger.add_watchpoint(Watchpoint {
+            address: 0x2ff,
+            len: 2,
+            kind: WatchKind::Access,
+        });
+        assert_eq!(
+            Some(Stop::Watchpoint {
+                kind: WatchKind::Access,
+                address: 0x300
+            }),
+            debugger.run(&mut chip8, 100).unwrap()
+        );
+        assert_eq!(0x204, chip8.cpu.pc.get());
+    }
+}
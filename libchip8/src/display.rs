@@ -3,6 +3,7 @@
 /////////////////////////////////////////////
 use crate::config;
 
+#[derive(Clone)]
 pub struct Display([bool; config::DISPLAY_WIDTH * config::DISPLAY_HEIGHT]);
 impl Display {
     pub fn new() -> Self {
@@ -13,6 +14,11 @@ impl Display {
         &self.0
     }
 
+    /// Replaces all pixels, `pixels` must cover the whole display.
+    pub fn restore(&mut self, pixels: &[bool]) {
+        self.0.copy_from_slice(pixels);
+    }
+
     pub fn clear(&mut self) {
         self.0.fill(false);
     }
@@ -20,12 +26,12 @@ impl Display {
     pub fn display_sprite(&mut self, pos_x: usize, pos_y: usize, data: &[u8]) -> bool {
         let mut collision = false;
 
-        for row in 0..data.len() {
+        for (row, byte) in data.iter().enumerate() {
             for col in 0..8 {
                 let screen_x = pos_x + col;
                 let screen_y = pos_y + row;
 
-                let new_pixel = ((data[row] >> (7 - col)) & 1) == 1;
+                let new_pixel = ((byte >> (7 - col)) & 1) == 1;
                 let current_pixel = self.get_pixel(screen_x, screen_y);
 
                 if current_pixel && new_pixel {
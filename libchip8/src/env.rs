@@ -0,0 +1,355 @@
+//! Gym-style environment for training agents on Chip-8 games.
+//!
+//! Everything, including the game's `Cxkk` results and sticky actions, is
+//! derived from the seed passed to `Environment::reset`, so an episode can be
+//! replayed exactly by resetting with the same seed and taking the same actions.
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use thiserror::Error;
+
+use crate::{Chip8, ChipError, config, memory::MemoryError};
+
+/// How a multi-byte value is stored in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Big-endian binary number.
+    Binary,
+    /// One decimal digit per byte, most significant first (as written by `Fx33`).
+    Bcd,
+}
+
+/// Game value (score, lives, ...) located in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryValue {
+    pub address: u16,
+    /// Bytes, values too large for a `u64` read as `u64::MAX`.
+    pub len: u8,
+    pub encoding: Encoding,
+}
+
+impl MemoryValue {
+    pub fn new(address: u16, len: u8, encoding: Encoding) -> Self {
+        MemoryValue {
+            address,
+            len,
+            encoding,
+        }
+    }
+
+    /// Reads the value, bytes outside memory read as 0.
+    pub fn read(&self, chip8: &Chip8) -> u64 {
+        (0..self.len as usize).fold(0, |value, offset| {
+            let byte = chip8
+                .memory
+                .get(self.address as usize + offset)
+                .unwrap_or(0) as u64;
+            match self.encoding {
+                Encoding::Binary => value.saturating_mul(0x100).saturating_add(byte),
+                Encoding::Bcd => value.saturating_mul(10).saturating_add(byte % 10),
+            }
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct EnvConfig {
+    pub rom: Vec<u8>,
+    /// Instructions executed per 60 Hz frame.
+    pub cycles_per_frame: u32,
+    /// Frames emulated per `step`, the action is held for all of them.
+    pub frame_skip: u32,
+    /// Chance that a frame repeats the previous action instead of the new one.
+    pub sticky_action_probability: f64,
+    /// Reward is the change of this value between steps.
+    pub score: Option<MemoryValue>,
+    /// Episode ends once this value drops to 0.
+    pub lives: Option<MemoryValue>,
+    /// Episode ends after this many frames.
+    pub max_frames: Option<u64>,
+}
+
+impl EnvConfig {
+    pub fn new(rom: Vec<u8>) -> Self {
+        EnvConfig {
+            rom,
+            cycles_per_frame: 12,
+            frame_skip: 4,
+            sticky_action_probability: 0.25,
+            score: None,
+            lives: None,
+            max_frames: None,
+        }
+    }
+}
+
+/// Result of a single `step`.
+pub struct Step {
+    /// Display pixels, row by row.
+    pub observation: Vec<bool>,
+    pub reward: f64,
+    pub done: bool,
+    /// Why the game stopped during this step, if it crashed.
+    pub error: Option<ChipError>,
+}
+
+#[derive(Debug, Error)]
+pub enum EnvError {
+    #[error(transparent)]
+    MemoryError(MemoryError),
+
+    #[error("sticky action probability is not a number")]
+    InvalidStickyActionProbability,
+}
+
+pub struct Environment {
+    config: EnvConfig,
+    chip8: Chip8,
+    rng: ChaCha8Rng,
+    action: u16,
+    score: u64,
+    lives: u64,
+    frame: u64,
+    done: bool,
+}
+
+impl Environment {
+    /// Creates environment, fails if the ROM does not fit into memory or the sticky action
+    /// probability is NaN.
+    pub fn new(config: EnvConfig) -> Result<Self, EnvError> {
+        if config.sticky_action_probability.is_nan() {
+            return Err(EnvError::InvalidStickyActionProbability);
+        }
+
+        let mut env = Environment {
+            config,
+            chip8: Chip8::with_seed(0),
+            rng: ChaCha8Rng::seed_from_u64(0),
+            action: 0,
+            score: 0,
+            lives: 0,
+            frame: 0,
+            done: false,
+        };
+        env.chip8
+            .load_rom(&env.config.rom)
+            .map_err(EnvError::MemoryError)?;
+
+        Ok(env)
+    }
+
+    /// Starts a new episode and returns the first observation.
+    pub fn reset(&mut self, seed: u64) -> Vec<bool> {
+        self.chip8 = Chip8::with_seed(seed);
+        self.chip8
+            .load_rom(&self.config.rom)
+            .expect("ROM size checked in Environment::new");
+
+        // separate stream so sticky actions do not shift the game's random numbers
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        self.rng.set_stream(1);
+
+        self.action = 0;
+        self.frame = 0;
+        self.done = false;
+        self.score = self.read_score();
+        self.lives = self.read_lives();
+
+        self.observation()
+    }
+
+    /// Holds the keys in `action` (bit n set = key n pressed) for `frame_skip` frames.
+    pub fn step(&mut self, action: u16) -> Step {
+        if self.done {
+            return Step {
+                observation: self.observation(),
+                reward: 0.0,
+                done: true,
+                error: None,
+            };
+        }
+
+        let mut error = None;
+        for _ in 0..self.config.frame_skip.max(1) {
+            if !self
+                .rng
+                .random_bool(self.config.sticky_action_probability.clamp(0.0, 1.0))
+            {
+                self.action = action;
+            }
+            for key in 0..config::KEY_COUNT {
+                self.chip8
+                    .keyboard
+                    .set_key(key, self.action & (1 << key) != 0);
+            }
+
+            self.frame += 1;
+            if let Err(e) = self.chip8.run_frame(self.config.cycles_per_frame) {
+                error = Some(e);
+                self.done = true;
+                break;
+            }
+        }
+
+        let score = self.read_score();
+        let reward = score as f64 - self.score as f64;
+        self.score = score;
+
+        let lives = self.read_lives();
+        if self.config.lives.is_some() && self.lives > 0 && lives == 0 {
+            self.done = true;
+        }
+        self.lives = lives;
+
+        if self.config.max_frames.is_some_and(|max| self.frame >= max) {
+            self.done = true;
+        }
+
+        Step {
+            observation: self.observation(),
+            reward,
+            done: self.done,
+            error,
+        }
+    }
+
+    /// Frames emulated since the last reset.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    fn observation(&self) -> Vec<bool> {
+        self.chip8.display.dump().to_vec()
+    }
+
+    fn read_score(&self) -> u64 {
+        self.config.score.map_or(0, |v| v.read(&self.chip8))
+    }
+
+    fn read_lives(&self) -> u64 {
+        self.config.lives.map_or(0, |v| v.read(&self.chip8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StackError;
+
+    // 0x200: LD V0, 3       lives
+    // 0x202: LD I, 0x300
+    // 0x204: SKNP V1        V1 = 0, key 0 scores
+    // 0x206: ADD V2, 1      score
+    // 0x208: RND V3, 0xFF
+    // 0x20A: SE V2, 5
+    // 0x20C: JP 0x210
+    // 0x20E: LD V0, 0
+    // 0x210: LD I, 0x300
+    // 0x212: LD [I], V3     0x300 lives, 0x302 score
+    // 0x214: JP 0x204
+    const ROM: [u8; 22] = [
+        0x60, 0x03, 0xa3, 0x00, 0xe1, 0xa1, 0x72, 0x01, 0xc3, 0xff, 0x32, 0x05, 0x12, 0x10, 0x60,
+        0x00, 0xa3, 0x00, 0xf3, 0x55, 0x12, 0x04,
+    ];
+
+    fn env(sticky: f64) -> Environment {
+        let mut config = EnvConfig::new(ROM.to_vec());
+        config.cycles_per_frame = 8;
+        config.frame_skip = 1;
+        config.sticky_action_probability = sticky;
+        config.lives = Some(MemoryValue::new(0x300, 1, Encoding::Binary));
+        config.score = Some(MemoryValue::new(0x302, 1, Encoding::Binary));
+        Environment::new(config).unwrap()
+    }
+
+    #[test]
+    fn test_memory_value_encodings() {
+        let mut chip8 = Chip8::new();
+        chip8.write_memory(0x300, &[0x01, 0x02]).unwrap();
+        assert_eq!(
+            0x0102,
+            MemoryValue::new(0x300, 2, Encoding::Binary).read(&chip8)
+        );
+        assert_eq!(12, MemoryValue::new(0x300, 2, Encoding::Bcd).read(&chip8));
+
+        // too long for a u64
+        assert_eq!(
+            u64::MAX,
+            MemoryValue::new(0x300, 21, Encoding::Bcd).read(&chip8)
+        );
+        assert_eq!(
+            u64::MAX,
+            MemoryValue::new(0x300, 9, Encoding::Binary).read(&chip8)
+        );
+        assert_eq!(
+            12_000_000_000_000_000_000,
+            MemoryValue::new(0x300, 20, Encoding::Bcd).read(&chip8)
+        );
+    }
+    #[test]
+    fn test_env_reward_and_done() {
+        let mut env = env(0.0);
+        env.reset(1);
+
+        let step = env.step(0);
+        assert_eq!(0.0, step.reward);
+        assert!(!step.done);
+
+        let mut total = 0.0;
+        let mut done = false;
+        for _ in 0..10 {
+            let step = env.step(0b1);
+            total += step.reward;
+            if step.done {
+                done = true;
+                break;
+            }
+        }
+        assert!(done);
+        assert_eq!(5.0, total);
+    }
+    #[test]
+    fn test_env_is_deterministic() {
+        let run = |seed| {
+            let mut env = env(0.5);
+            env.reset(seed);
+            (0..20)
+                .map(|i| {
+                    env.step(i % 2);
+                    env.chip8().register(3)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+    #[test]
+    fn test_env_reports_crash() {
+        let mut config = EnvConfig::new(vec![0x00, 0xee]);
+        config.frame_skip = 3;
+        let mut env = Environment::new(config).unwrap();
+        env.reset(1);
+
+        let step = env.step(0);
+        assert!(step.done);
+        assert!(matches!(
+            step.error,
+            Some(ChipError::StackError(StackError::Underflow))
+        ));
+        assert_eq!(1, env.frame());
+        assert!(env.step(0).error.is_none());
+    }
+    #[test]
+    fn test_env_rejects_nan_sticky_probability() {
+        let mut config = EnvConfig::new(ROM.to_vec());
+        config.sticky_action_probability = f64::NAN;
+        assert!(matches!(
+            Environment::new(config),
+            Err(EnvError::InvalidStickyActionProbability)
+        ));
+    }
+}
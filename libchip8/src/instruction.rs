@@ -0,0 +1,235 @@
+use std::fmt;
+
+use crate::parser;
+
+/// Decoded Chip-8 instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0 - CLS
+    ClearDisplay,
+    /// 00EE - RET
+    Return,
+    /// 1nnn - JP addr
+    Jump(u16),
+    /// 2nnn - CALL addr
+    Call(u16),
+    /// 3xkk - SE Vx, byte
+    SkipEqual(u8, u8),
+    /// 4xkk - SNE Vx, byte
+    SkipNotEqual(u8, u8),
+    /// 5xy0 - SE Vx, Vy
+    SkipEqualRegisters(u8, u8),
+    /// 6xkk - LD Vx, byte
+    LoadValue(u8, u8),
+    /// 7xkk - ADD Vx, byte
+    AddValue(u8, u8),
+    /// 8xy0 - LD Vx, Vy
+    LoadRegister(u8, u8),
+    /// 8xy1 - OR Vx, Vy
+    Or(u8, u8),
+    /// 8xy2 - AND Vx, Vy
+    And(u8, u8),
+    /// 8xy3 - XOR Vx, Vy
+    Xor(u8, u8),
+    /// 8xy4 - ADD Vx, Vy
+    AddRegister(u8, u8),
+    /// 8xy5 - SUB Vx, Vy
+    SubtractRegister(u8, u8),
+    /// 8xy6 - SHR Vx {, Vy}
+    ShiftRight(u8, u8),
+    /// 8xy7 - SUBN Vx, Vy
+    SubtractNegative(u8, u8),
+    /// 8xyE - SHL Vx {, Vy}
+    ShiftLeft(u8, u8),
+    /// 9xy0 - SNE Vx, Vy
+    SkipNotEqualRegisters(u8, u8),
+    /// Annn - LD I, addr
+    SetI(u16),
+    /// Bnnn - JP V0, addr
+    JumpV0(u16),
+    /// Cxkk - RND Vx, byte
+    Random(u8, u8),
+    /// Dxyn - DRW Vx, Vy, nibble
+    Draw(u8, u8, u8),
+    /// Ex9E - SKP Vx
+    SkipKeyPressed(u8),
+    /// ExA1 - SKNP Vx
+    SkipKeyNotPressed(u8),
+    /// Fx07 - LD Vx, DT
+    LoadDelayTimer(u8),
+    /// Fx0A - LD Vx, K
+    WaitForKey(u8),
+    /// Fx15 - LD DT, Vx
+    SetDelayTimer(u8),
+    /// Fx18 - LD ST, Vx
+    SetSoundTimer(u8),
+    /// Fx1E - ADD I, Vx
+    AddI(u8),
+    /// Fx29 - LD F, Vx
+    SetSpriteLocation(u8),
+    /// Fx33 - LD B, Vx
+    LoadBcd(u8),
+    /// Fx55 - LD [I], Vx
+    StoreRegisters(u8),
+    /// Fx65 - LD Vx, [I]
+    LoadRegisters(u8),
+}
+
+impl Instruction {
+    /// Decodes raw instruction, returns `None` for unknown opcodes.
+    pub fn decode(instruction: u16) -> Option<Self> {
+        let x = parser::x(instruction);
+        let y = parser::y(instruction);
+        let kk = parser::kk(instruction);
+        let nnn = parser::nnn(instruction);
+
+        let decoded = match instruction & 0xf000 {
+            0x0000 => match instruction {
+                0x00e0 => Self::ClearDisplay,
+                0x00ee => Self::Return,
+                _ => return None,
+            },
+            0x1000 => Self::Jump(nnn),
+            0x2000 => Self::Call(nnn),
+            0x3000 => Self::SkipEqual(x, kk),
+            0x4000 => Self::SkipNotEqual(x, kk),
+            0x5000 => match parser::n(instruction) {
+                0x0 => Self::SkipEqualRegisters(x, y),
+                _ => return None,
+            },
+            0x6000 => Self::LoadValue(x, kk),
+            0x7000 => Self::AddValue(x, kk),
+            0x8000 => match parser::n(instruction) {
+                0x0 => Self::LoadRegister(x, y),
+                0x1 => Self::Or(x, y),
+                0x2 => Self::And(x, y),
+                0x3 => Self::Xor(x, y),
+                0x4 => Self::AddRegister(x, y),
+                0x5 => Self::SubtractRegister(x, y),
+                0x6 => Self::ShiftRight(x, y),
+                0x7 => Self::SubtractNegative(x, y),
+                0xe => Self::ShiftLeft(x, y),
+                _ => return None,
+            },
+            0x9000 => match parser::n(instruction) {
+                0x0 => Self::SkipNotEqualRegisters(x, y),
+                _ => return None,
+            },
+            0xa000 => Self::SetI(nnn),
+            0xb000 => Self::JumpV0(nnn),
+            0xc000 => Self::Random(x, kk),
+            0xd000 => Self::Draw(x, y, parser::n(instruction)),
+            0xe000 => match kk {
+                0x9e => Self::SkipKeyPressed(x),
+                0xa1 => Self::SkipKeyNotPressed(x),
+                _ => return None,
+            },
+            0xf000 => match kk {
+                0x07 => Self::LoadDelayTimer(x),
+                0x0a => Self::WaitForKey(x),
+                0x15 => Self::SetDelayTimer(x),
+                0x18 => Self::SetSoundTimer(x),
+                0x1e => Self::AddI(x),
+                0x29 => Self::SetSpriteLocation(x),
+                0x33 => Self::LoadBcd(x),
+                0x55 => Self::StoreRegisters(x),
+                0x65 => Self::LoadRegisters(x),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(decoded)
+    }
+
+    /// Returns true for conditional skip instructions.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Self::SkipEqual(..)
+                | Self::SkipNotEqual(..)
+                | Self::SkipEqualRegisters(..)
+                | Self::SkipNotEqualRegisters(..)
+                | Self::SkipKeyPressed(_)
+                | Self::SkipKeyNotPressed(_)
+        )
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::ClearDisplay => write!(f, "CLS"),
+            Self::Return => write!(f, "RET"),
+            Self::Jump(addr) => write!(f, "JP {addr:#05X}"),
+            Self::Call(addr) => write!(f, "CALL {addr:#05X}"),
+            Self::SkipEqual(x, byte) => write!(f, "SE V{x:X}, {byte:#04X}"),
+            Self::SkipNotEqual(x, byte) => write!(f, "SNE V{x:X}, {byte:#04X}"),
+            Self::SkipEqualRegisters(x, y) => write!(f, "SE V{x:X}, V{y:X}"),
+            Self::LoadValue(x, byte) => write!(f, "LD V{x:X}, {byte:#04X}"),
+            Self::AddValue(x, byte) => write!(f, "ADD V{x:X}, {byte:#04X}"),
+            Self::LoadRegister(x, y) => write!(f, "LD V{x:X}, V{y:X}"),
+            Self::Or(x, y) => write!(f, "OR V{x:X}, V{y:X}"),
+            Self::And(x, y) => write!(f, "AND V{x:X}, V{y:X}"),
+            Self::Xor(x, y) => write!(f, "XOR V{x:X}, V{y:X}"),
+            Self::AddRegister(x, y) => write!(f, "ADD V{x:X}, V{y:X}"),
+            Self::SubtractRegister(x, y) => write!(f, "SUB V{x:X}, V{y:X}"),
+            Self::ShiftRight(x, y) => write!(f, "SHR V{x:X}, V{y:X}"),
+            Self::SubtractNegative(x, y) => write!(f, "SUBN V{x:X}, V{y:X}"),
+            Self::ShiftLeft(x, y) => write!(f, "SHL V{x:X}, V{y:X}"),
+            Self::SkipNotEqualRegisters(x, y) => write!(f, "SNE V{x:X}, V{y:X}"),
+            Self::SetI(addr) => write!(f, "LD I, {addr:#05X}"),
+            Self::JumpV0(addr) => write!(f, "JP V0, {addr:#05X}"),
+            Self::Random(x, byte) => write!(f, "RND V{x:X}, {byte:#04X}"),
+            Self::Draw(x, y, n) => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+            Self::SkipKeyPressed(x) => write!(f, "SKP V{x:X}"),
+            Self::SkipKeyNotPressed(x) => write!(f, "SKNP V{x:X}"),
+            Self::LoadDelayTimer(x) => write!(f, "LD V{x:X}, DT"),
+            Self::WaitForKey(x) => write!(f, "LD V{x:X}, K"),
+            Self::SetDelayTimer(x) => write!(f, "LD DT, V{x:X}"),
+            Self::SetSoundTimer(x) => write!(f, "LD ST, V{x:X}"),
+            Self::AddI(x) => write!(f, "ADD I, V{x:X}"),
+            Self::SetSpriteLocation(x) => write!(f, "LD F, V{x:X}"),
+            Self::LoadBcd(x) => write!(f, "LD B, V{x:X}"),
+            Self::StoreRegisters(x) => write!(f, "LD [I], V{x:X}"),
+            Self::LoadRegisters(x) => write!(f, "LD V{x:X}, [I]"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_known() {
+        assert_eq!(Some(Instruction::ClearDisplay), Instruction::decode(0x00e0));
+        assert_eq!(
+            Some(Instruction::Draw(1, 2, 3)),
+            Instruction::decode(0xd123)
+        );
+        assert_eq!(
+            Some(Instruction::ShiftLeft(0xa, 0xb)),
+            Instruction::decode(0x8abe)
+        );
+    }
+    #[test]
+    fn test_decode_unknown() {
+        assert_eq!(None, Instruction::decode(0x0123));
+        assert_eq!(None, Instruction::decode(0x5121));
+        assert_eq!(None, Instruction::decode(0xe1ff));
+    }
+    #[test]
+    fn test_is_skip() {
+        assert!(Instruction::decode(0x3a01).unwrap().is_skip());
+        assert!(Instruction::decode(0xe19e).unwrap().is_skip());
+        assert!(!Instruction::decode(0x1200).unwrap().is_skip());
+    }
+    #[test]
+    fn test_disassembly() {
+        assert_eq!("JP 0x228", Instruction::Jump(0x228).to_string());
+        assert_eq!("LD VA, 0x02", Instruction::LoadValue(0xa, 2).to_string());
+        assert_eq!("DRW V0, V1, 5", Instruction::Draw(0, 1, 5).to_string());
+        assert_eq!("LD [I], VF", Instruction::StoreRegisters(0xf).to_string());
+    }
+}
@@ -1,11 +1,16 @@
 use crate::config;
 
+#[derive(Clone)]
 pub struct Keyboard([bool; config::KEY_COUNT as usize]);
 impl Keyboard {
     pub fn new() -> Self {
         Keyboard([false; config::KEY_COUNT as usize])
     }
 
+    pub fn dump(&self) -> &[bool] {
+        &self.0
+    }
+
     pub fn is_pressed(&mut self, key: u8) -> bool {
         if key as usize >= self.0.len() {
             return false;
@@ -14,12 +19,7 @@ impl Keyboard {
     }
 
     pub fn get_pressed_key(&mut self) -> Option<u8> {
-        for idx in 0..config::KEY_COUNT {
-            if self.0[idx as usize] {
-                return Some(idx);
-            }
-        }
-        None
+        (0..config::KEY_COUNT).find(|&idx| self.0[idx as usize])
     }
 
     pub fn set_key(&mut self, key: u8, is_pressed: bool) {
@@ -1,44 +1,84 @@
-use rand::random;
+use rand::{Rng, SeedableRng, random};
+use rand_chacha::ChaCha8Rng;
+use thiserror::Error;
 
+pub mod analysis;
+pub mod audio;
+pub mod batch;
 pub mod config;
+pub mod coverage;
 mod cpu;
+pub mod debugger;
 mod display;
+pub mod env;
+pub mod instruction;
 mod keyboard;
+pub mod listing;
 mod memory;
 mod parser;
+pub mod recompiler;
 mod stack;
+pub mod state;
 pub mod timers;
 
+use audio::Audio;
 use cpu::Cpu;
 use display::Display;
+use instruction::Instruction;
 use keyboard::Keyboard;
 use memory::Memory;
 use stack::Stack;
 use timers::Timers;
 
-use crate::memory::MemoryError;
+pub use crate::memory::MemoryError;
+pub use crate::stack::StackError;
 
+/// Complete machine state.
+///
+/// All state lives in fixed-size arrays, so cloning is a cheap copy of a
+/// few KiB and can be used to fork a machine mid-run. Only the audio
+/// keeps a short queue of buzzer changes on top of that.
+#[derive(Clone)]
 pub struct Chip8 {
-    cpu: Cpu,
+    pub(crate) cpu: Cpu,
     stack: Stack,
-    memory: Memory,
+    pub(crate) memory: Memory,
     pub display: Display,
     pub keyboard: Keyboard,
     pub timers: Timers,
+    pub audio: Audio,
+    rng: ChaCha8Rng,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum ChipError {
+    #[error("unknown instruction: {0:04X}")]
     UnknownInstruction(u16),
+
+    #[error(transparent)]
     MemoryError(MemoryError),
+
+    #[error(transparent)]
+    StackError(StackError),
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chip8 {
     //////////////////////////////////////
-    ///         PUBLIC INTERFACE       ///
+    //          PUBLIC INTERFACE        //
     //////////////////////////////////////
 
     pub fn new() -> Self {
+        Self::with_seed(random())
+    }
+
+    /// Creates machine whose `Cxkk` results are determined by the seed.
+    pub fn with_seed(seed: u64) -> Self {
         Self {
             cpu: Cpu::new(),
             stack: Stack::new(),
@@ -46,28 +86,126 @@ impl Chip8 {
             display: Display::new(),
             keyboard: Keyboard::new(),
             timers: Timers::new(),
+            audio: Audio::new(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
         }
     }
 
+    /// Restarts the random number sequence from the seed.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
     /// Loads bytes into memory.
     pub fn load_rom(&mut self, bytes: &[u8]) -> Result<(), MemoryError> {
         self.memory.load_rom(bytes)
     }
 
+    /// Return addresses of the active subroutine calls, oldest first.
+    pub fn call_stack(&self) -> &[u16] {
+        self.stack.frames()
+    }
+
+    /// Value of `Vx`, panics past `VF`.
+    pub fn register(&self, index: u8) -> u8 {
+        self.cpu.v.get(index)
+    }
+
+    /// Sets `Vx`, panics past `VF`.
+    pub fn set_register(&mut self, index: u8, value: u8) {
+        self.cpu.v.set(index, value);
+    }
+
+    /// Index register.
+    pub fn i(&self) -> u16 {
+        self.cpu.i.get()
+    }
+
+    pub fn set_i(&mut self, value: u16) {
+        self.cpu.i.set(value);
+    }
+
+    /// Address of the next instruction.
+    pub fn pc(&self) -> u16 {
+        self.cpu.pc.get()
+    }
+
+    pub fn set_pc(&mut self, address: u16) {
+        self.cpu.pc.set(address);
+    }
+
+    /// Whole memory from address 0.
+    pub fn memory(&self) -> &[u8] {
+        self.memory.dump()
+    }
+
+    /// Reads `len` bytes from `address`.
+    pub fn read_memory(&self, address: usize, len: usize) -> Result<&[u8], MemoryError> {
+        self.memory.get_slice(address, len)
+    }
+
+    /// Writes `bytes` from `address`, nothing is written unless they all fit.
+    pub fn write_memory(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        self.memory.get_slice(address, bytes.len())?;
+        for (offset, &byte) in bytes.iter().enumerate() {
+            self.memory.set(address + offset, byte)?;
+        }
+
+        Ok(())
+    }
+
     /// Executes next instruction.
     pub fn tick(&mut self) -> Result<(), ChipError> {
-        let instruction = self.fetch().map_err(|e| ChipError::MemoryError(e))?;
+        let instruction = self.fetch().map_err(ChipError::MemoryError)?;
         self.execute(instruction)
     }
 
+    /// Executes one 60 Hz frame: `cycles` instructions followed by a timer tick.
+    pub fn run_frame(&mut self, cycles: u32) -> Result<(), ChipError> {
+        for _ in 0..cycles {
+            self.tick()?;
+        }
+        self.timers.tick();
+
+        Ok(())
+    }
+
+    /// Writes samples of the buzzer square wave for the emulated time not played yet.
+    ///
+    /// Returns how many samples were written, fewer than `out.len()` once the
+    /// samples catch up with the last timer tick.
+    pub fn fill_audio(&mut self, out: &mut [f32], sample_rate: u32) -> usize {
+        self.sync_audio();
+        self.audio.fill_audio(out, sample_rate)
+    }
+
+    /// Same as `fill_audio` but writes whether the buzzer is on, for hosts making their own tone.
+    pub fn fill_buzzer(&mut self, out: &mut [bool], sample_rate: u32) -> usize {
+        self.sync_audio();
+        self.audio.fill_buzzer(out, sample_rate)
+    }
+
+    pub(crate) fn buzzer_on(&self) -> bool {
+        self.timers.get(timers::Timer::Sound) > 0
+    }
+
+    /// Hands timer ticks since the last instruction to the audio.
+    pub(crate) fn sync_audio(&mut self) {
+        self.audio.sync(self.timers.ticks(), self.buzzer_on());
+    }
+
     //////////////////////////////////////
-    ///    FETCH / DECODE / EXECUTE    ///
+    //     FETCH / DECODE / EXECUTE     //
     //////////////////////////////////////
 
     fn fetch(&self) -> Result<u16, MemoryError> {
+        self.fetch_at(self.cpu.pc.get() as usize)
+    }
+
+    fn fetch_at(&self, address: usize) -> Result<u16, MemoryError> {
         let instruction_memory: [u8; 2] = self
             .memory
-            .get_slice(self.cpu.pc.get() as usize, 2)?
+            .get_slice(address, 2)?
             .try_into()
             .map_err(|_| MemoryError::OutOfBounds(0))?;
 
@@ -75,84 +213,74 @@ impl Chip8 {
     }
 
     fn execute(&mut self, instruction: u16) -> Result<(), ChipError> {
-        match instruction & 0xf000 {
-            0x0000 => match instruction {
-                0x00e0 => self.op_clear_display(),
-                0x00ee => self.op_return_from_subroutine(),
-                _ => return Err(ChipError::UnknownInstruction(instruction)),
-            },
-            0x1000 => self.op_jump(parser::nnn(instruction)),
-            0x2000 => self.op_call(parser::nnn(instruction)),
-            0x3000 => self.op_skip_equal(parser::x(instruction), parser::kk(instruction)),
-            0x4000 => self.op_skip_not_equal(parser::x(instruction), parser::kk(instruction)),
-            0x5000 => match instruction & 0x000f {
-                0x0 => self.op_skip_equal_registers(parser::x(instruction), parser::y(instruction)),
-                _ => return Err(ChipError::UnknownInstruction(instruction)),
-            },
-            0x6000 => self.op_load_value(parser::x(instruction), parser::kk(instruction)),
-            0x7000 => self.op_add_value(parser::x(instruction), parser::kk(instruction)),
-            0x8000 => match instruction & 0x000f {
-                0x0 => self.op_load_register(parser::x(instruction), parser::y(instruction)),
-                0x1 => self.op_bitwise_or(parser::x(instruction), parser::y(instruction)),
-                0x2 => self.op_bitwise_and(parser::x(instruction), parser::y(instruction)),
-                0x3 => self.op_bitwise_xor(parser::x(instruction), parser::y(instruction)),
-                0x4 => self.op_add_register(parser::x(instruction), parser::y(instruction)),
-                0x5 => self.op_subtract_register(parser::x(instruction), parser::y(instruction)),
-                0x6 => self.op_shift_right(parser::x(instruction), parser::y(instruction)),
-                0x7 => self.op_subtract_negative(parser::x(instruction), parser::y(instruction)),
-                0xe => self.op_shift_left(parser::x(instruction), parser::y(instruction)),
-                _ => return Err(ChipError::UnknownInstruction(instruction)),
-            },
-            0x9000 => match instruction & 0x000f {
-                0x0 => {
-                    self.op_skip_not_equal_registers(parser::x(instruction), parser::y(instruction))
-                }
-                _ => return Err(ChipError::UnknownInstruction(instruction)),
-            },
-            0xa000 => self.op_set_i_register(parser::nnn(instruction)),
-            0xb000 => self.op_jump_to_v0_plus_addr(parser::nnn(instruction)),
-            0xc000 => self.op_random(parser::x(instruction), parser::kk(instruction)),
-            0xd000 => self
-                .op_display_sprite(
-                    parser::x(instruction),
-                    parser::y(instruction),
-                    parser::n(instruction),
-                )
-                .map_err(|e| ChipError::MemoryError(e))?,
-            0xe000 => match instruction & 0x00ff {
-                0x9e => self.op_skip_key_pressed(parser::x(instruction)),
-                0xa1 => self.op_skip_key_not_pressed(parser::x(instruction)),
-                _ => return Err(ChipError::UnknownInstruction(instruction)),
-            },
-            0xf000 => match instruction & 0x00ff {
-                0x07 => self.op_load_delay_timer(parser::x(instruction)),
-                0x0a => self.op_wait_for_key_press(parser::x(instruction)),
-                0x15 => self.op_set_delay_timer(parser::x(instruction)),
-                0x18 => self.op_set_sound_timer(parser::x(instruction)),
-                0x1e => self.op_add_i(parser::x(instruction)),
-                0x29 => self
-                    .op_set_sprite_location(parser::x(instruction))
-                    .map_err(|e| ChipError::MemoryError(e))?,
-                0x33 => self
-                    .op_load_bcd(parser::x(instruction))
-                    .map_err(|e| ChipError::MemoryError(e))?,
-
-                0x55 => self
-                    .op_store_registers(parser::x(instruction))
-                    .map_err(|e| ChipError::MemoryError(e))?,
-                0x65 => self
-                    .op_load_registers(parser::x(instruction))
-                    .map_err(|e| ChipError::MemoryError(e))?,
-                _ => return Err(ChipError::UnknownInstruction(instruction)),
-            },
-            _ => return Err(ChipError::UnknownInstruction(instruction)),
+        let decoded =
+            Instruction::decode(instruction).ok_or(ChipError::UnknownInstruction(instruction))?;
+        self.execute_decoded(decoded)
+    }
+
+    /// Executes an instruction and records the buzzer state after it.
+    fn execute_decoded(&mut self, decoded: Instruction) -> Result<(), ChipError> {
+        self.sync_audio();
+        self.execute_op(decoded)?;
+        self.audio.instruction(self.buzzer_on());
+
+        Ok(())
+    }
+
+    /// Executes an instruction without telling the audio, the caller has to count it.
+    pub(crate) fn execute_op(&mut self, decoded: Instruction) -> Result<(), ChipError> {
+        match decoded {
+            Instruction::ClearDisplay => self.op_clear_display(),
+            Instruction::Return => self
+                .op_return_from_subroutine()
+                .map_err(ChipError::StackError)?,
+            Instruction::Jump(addr) => self.op_jump(addr),
+            Instruction::Call(addr) => self.op_call(addr).map_err(ChipError::StackError)?,
+            Instruction::SkipEqual(x, byte) => self.op_skip_equal(x, byte),
+            Instruction::SkipNotEqual(x, byte) => self.op_skip_not_equal(x, byte),
+            Instruction::SkipEqualRegisters(x, y) => self.op_skip_equal_registers(x, y),
+            Instruction::LoadValue(x, byte) => self.op_load_value(x, byte),
+            Instruction::AddValue(x, byte) => self.op_add_value(x, byte),
+            Instruction::LoadRegister(x, y) => self.op_load_register(x, y),
+            Instruction::Or(x, y) => self.op_bitwise_or(x, y),
+            Instruction::And(x, y) => self.op_bitwise_and(x, y),
+            Instruction::Xor(x, y) => self.op_bitwise_xor(x, y),
+            Instruction::AddRegister(x, y) => self.op_add_register(x, y),
+            Instruction::SubtractRegister(x, y) => self.op_subtract_register(x, y),
+            Instruction::ShiftRight(x, y) => self.op_shift_right(x, y),
+            Instruction::SubtractNegative(x, y) => self.op_subtract_negative(x, y),
+            Instruction::ShiftLeft(x, y) => self.op_shift_left(x, y),
+            Instruction::SkipNotEqualRegisters(x, y) => self.op_skip_not_equal_registers(x, y),
+            Instruction::SetI(addr) => self.op_set_i_register(addr),
+            Instruction::JumpV0(addr) => self.op_jump_to_v0_plus_addr(addr),
+            Instruction::Random(x, byte) => self.op_random(x, byte),
+            Instruction::Draw(x, y, n) => self
+                .op_display_sprite(x, y, n)
+                .map_err(ChipError::MemoryError)?,
+            Instruction::SkipKeyPressed(x) => self.op_skip_key_pressed(x),
+            Instruction::SkipKeyNotPressed(x) => self.op_skip_key_not_pressed(x),
+            Instruction::LoadDelayTimer(x) => self.op_load_delay_timer(x),
+            Instruction::WaitForKey(x) => self.op_wait_for_key_press(x),
+            Instruction::SetDelayTimer(x) => self.op_set_delay_timer(x),
+            Instruction::SetSoundTimer(x) => self.op_set_sound_timer(x),
+            Instruction::AddI(x) => self.op_add_i(x),
+            Instruction::SetSpriteLocation(x) => self
+                .op_set_sprite_location(x)
+                .map_err(ChipError::MemoryError)?,
+            Instruction::LoadBcd(x) => self.op_load_bcd(x).map_err(ChipError::MemoryError)?,
+            Instruction::StoreRegisters(x) => {
+                self.op_store_registers(x).map_err(ChipError::MemoryError)?
+            }
+            Instruction::LoadRegisters(x) => {
+                self.op_load_registers(x).map_err(ChipError::MemoryError)?
+            }
         };
 
         Ok(())
     }
 
     //////////////////////////////////////
-    ///           OPERATIONS           ///
+    //            OPERATIONS            //
     //////////////////////////////////////
 
     // 00E0 - CLS
@@ -162,8 +290,10 @@ impl Chip8 {
     }
 
     // 00EE - RET
-    fn op_return_from_subroutine(&mut self) {
-        self.cpu.pc.set(self.stack.pop());
+    fn op_return_from_subroutine(&mut self) -> Result<(), StackError> {
+        self.cpu.pc.set(self.stack.pop()?);
+
+        Ok(())
     }
 
     // 1nnn - JP addr
@@ -172,10 +302,12 @@ impl Chip8 {
     }
 
     // 2nnn - CALL addr
-    fn op_call(&mut self, address: u16) {
-        self.cpu.pc.advance();
-        self.stack.push(self.cpu.pc.get());
+    fn op_call(&mut self, address: u16) -> Result<(), StackError> {
+        // return address, pc stays on the CALL if the stack is full
+        self.stack.push(self.cpu.pc.get().wrapping_add(2))?;
         self.cpu.pc.set(address);
+
+        Ok(())
     }
 
     // 3xkk - SE Vx, byte
@@ -306,7 +438,7 @@ impl Chip8 {
 
     // Cxkk - RND Vx, byte
     fn op_random(&mut self, x: u8, byte: u8) {
-        self.cpu.v.set(x, random::<u8>() & byte);
+        self.cpu.v.set(x, self.rng.random::<u8>() & byte);
         self.cpu.pc.advance();
     }
 
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+/// Address to source line mapping read from an assembler listing.
+///
+/// Every line starting with a hexadecimal address (`0200`, `200:` or `0x200`)
+/// is treated as the line that produced the code at that address, e.g.
+/// `0200: 6A02  LD VA, 2`. Other lines are ignored.
+pub struct Listing {
+    lines: BTreeMap<u16, usize>,
+}
+
+impl Listing {
+    /// Parses listing text, line numbers start at 1.
+    pub fn parse(text: &str) -> Self {
+        let mut lines = BTreeMap::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            if let Some(address) = parse_address(line) {
+                lines.entry(address).or_insert(idx + 1);
+            }
+        }
+
+        Listing { lines }
+    }
+
+    /// Returns listing line which produced the given address.
+    pub fn line_for(&self, address: u16) -> Option<usize> {
+        self.lines.get(&address).copied()
+    }
+
+    /// Returns first address produced by the given listing line.
+    pub fn address_for(&self, line: usize) -> Option<u16> {
+        self.lines
+            .iter()
+            .find(|(_, l)| **l == line)
+            .map(|(address, _)| *address)
+    }
+
+    /// Iterates over `(address, line)` pairs in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, usize)> + '_ {
+        self.lines.iter().map(|(address, line)| (*address, *line))
+    }
+}
+
+fn parse_address(line: &str) -> Option<u16> {
+    let token = line.split_whitespace().next()?;
+    let (token, has_colon) = match token.strip_suffix(':') {
+        Some(token) => (token, true),
+        None => (token, false),
+    };
+    let (digits, has_prefix) = match token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+    {
+        Some(digits) => (digits, true),
+        None => (token, false),
+    };
+
+    // mnemonics such as `ADD` are valid hex too, so bare tokens must look like addresses
+    let looks_like_address = has_prefix
+        || (digits.bytes().any(|b| b.is_ascii_digit()) && (has_colon || digits.len() == 4));
+    if !looks_like_address || !(3..=4).contains(&digits.len()) {
+        return None;
+    }
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_parse() {
+        let listing =
+            Listing::parse("; pong\n0200: 6A02  LD VA, 2\n0x202 6B0C\n\n  0204 00E0 CLS\n");
+        assert_eq!(Some(2), listing.line_for(0x200));
+        assert_eq!(Some(3), listing.line_for(0x202));
+        assert_eq!(Some(5), listing.line_for(0x204));
+        assert_eq!(None, listing.line_for(0x206));
+    }
+    #[test]
+    fn test_listing_ignores_non_addresses() {
+        let listing = Listing::parse("loop:\nCLS\nADD V1, 2\nFADE 1\n");
+        assert_eq!(0, listing.iter().count());
+    }
+    #[test]
+    fn test_listing_address_for() {
+        let listing = Listing::parse("0200: 00E0\n0202: 1200\n");
+        assert_eq!(Some(0x202), listing.address_for(2));
+        assert_eq!(None, listing.address_for(3));
+    }
+}
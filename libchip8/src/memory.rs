@@ -1,8 +1,15 @@
 use crate::{config, memory::MemoryError::ProgramTooLarge};
 use thiserror::Error;
 
+/// Granularity of write tracking, in bytes.
+pub const PAGE_SIZE: usize = 64;
+const PAGE_COUNT: usize = config::MEMORY_SIZE / PAGE_SIZE;
+
+#[derive(Clone)]
 pub struct Memory {
     cells: [u8; config::MEMORY_SIZE],
+    // bumped on every write into the page, lets caches detect stale data
+    page_generations: [u32; PAGE_COUNT],
 }
 
 #[derive(Debug, Error)]
@@ -21,6 +28,7 @@ impl Memory {
     pub fn new() -> Self {
         let mut memory = Memory {
             cells: [0; config::MEMORY_SIZE],
+            page_generations: [0; PAGE_COUNT],
         };
 
         for (i, &byte) in config::FONTSET.iter().enumerate() {
@@ -36,6 +44,9 @@ impl Memory {
             return Err(ProgramTooLarge);
         }
         self.cells[config::PROGRAM_START_ADDRESS..end].copy_from_slice(bytes);
+        for page in config::PROGRAM_START_ADDRESS / PAGE_SIZE..end.div_ceil(PAGE_SIZE) {
+            self.page_generations[page] = self.page_generations[page].wrapping_add(1);
+        }
         Ok(())
     }
 
@@ -44,6 +55,8 @@ impl Memory {
             return Err(MemoryError::OutOfBounds(address));
         }
         self.cells[address] = value;
+        let page = address / PAGE_SIZE;
+        self.page_generations[page] = self.page_generations[page].wrapping_add(1);
 
         Ok(())
     }
@@ -56,10 +69,30 @@ impl Memory {
     }
 
     pub fn get_slice(&self, from_address: usize, size: usize) -> Result<&[u8], MemoryError> {
-        if from_address + size >= config::MEMORY_SIZE {
-            return Err(MemoryError::OutOfBounds(from_address + size - 1));
+        let end = from_address
+            .checked_add(size)
+            .filter(|&end| end <= config::MEMORY_SIZE)
+            .ok_or(MemoryError::OutOfBounds(
+                from_address.saturating_add(size).saturating_sub(1),
+            ))?;
+        Ok(&self.cells[from_address..end])
+    }
+
+    pub fn dump(&self) -> &[u8] {
+        &self.cells
+    }
+
+    /// Replaces whole memory, `cells` must cover all of it.
+    pub fn restore(&mut self, cells: &[u8]) {
+        self.cells.copy_from_slice(cells);
+        for generation in self.page_generations.iter_mut() {
+            *generation = generation.wrapping_add(1);
         }
-        Ok(&self.cells[from_address..from_address + size])
+    }
+
+    /// Returns write generation of the page containing the address.
+    pub fn page_generation(&self, address: usize) -> u32 {
+        self.page_generations[(address % config::MEMORY_SIZE) / PAGE_SIZE]
     }
 
     pub fn get_sprite_address(&self, digit: u8) -> Result<usize, MemoryError> {
@@ -69,3 +102,25 @@ impl Memory {
         Ok(config::FONTSET_START_ADDRESS + digit as usize * 5)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_get_slice_bounds() {
+        let mut memory = Memory::new();
+        memory.set(config::MEMORY_SIZE - 1, 0xab).unwrap();
+
+        // ends exactly at the last byte
+        let slice = memory.get_slice(config::MEMORY_SIZE - 2, 2).unwrap();
+        assert_eq!(&[0x00, 0xab], slice);
+        assert_eq!(
+            config::MEMORY_SIZE,
+            memory.get_slice(0, config::MEMORY_SIZE).unwrap().len()
+        );
+
+        assert!(memory.get_slice(config::MEMORY_SIZE - 1, 2).is_err());
+        assert!(memory.get_slice(usize::MAX, 2).is_err());
+    }
+}
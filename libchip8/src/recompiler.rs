@@ -0,0 +1,349 @@
+//! Basic-block execution backend.
+//!
+//! Straight-line code is decoded once into blocks of `Instruction`s which end
+//! at the first jump, call, return, skip, draw or key wait. Blocks are cached
+//! by start address and thrown away when a memory page they were decoded from
+//! is written to, so self-modifying ROMs behave exactly as under `Chip8::tick`.
+//!
+//! Timers only tick between runs, so inside a block nothing but `Fx18` can
+//! switch the buzzer. The audio is synced once per block and only told about
+//! instructions one by one around sound timer writes.
+
+use crate::{Chip8, ChipError, config, instruction::Instruction, memory::PAGE_SIZE};
+
+/// Upper bound on instructions in a single block.
+const MAX_BLOCK_LEN: usize = 64;
+
+struct Block {
+    instructions: Vec<Instruction>,
+    // write generations of the pages the block was decoded from
+    pages: Vec<(usize, u32)>,
+}
+
+impl Block {
+    fn is_stale(&self, chip8: &Chip8) -> bool {
+        self.pages
+            .iter()
+            .any(|&(address, generation)| chip8.memory.page_generation(address) != generation)
+    }
+}
+
+pub struct Recompiler {
+    blocks: Vec<Option<Block>>,
+}
+
+impl Default for Recompiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recompiler {
+    pub fn new() -> Self {
+        Recompiler {
+            blocks: (0..config::MEMORY_SIZE).map(|_| None).collect(),
+        }
+    }
+
+    /// Drops every cached block.
+    pub fn invalidate_all(&mut self) {
+        self.blocks.iter_mut().for_each(|block| *block = None);
+    }
+
+    /// Executes up to `cycles` instructions, returns how many were executed.
+    ///
+    /// Timers are not ticked, same as with `Chip8::tick`.
+    pub fn run(&mut self, chip8: &mut Chip8, cycles: u64) -> Result<u64, ChipError> {
+        let mut executed = 0;
+
+        while executed < cycles {
+            let pc = chip8.cpu.pc.get() as usize;
+            let block = match self.blocks.get(pc) {
+                Some(Some(block)) if !block.is_stale(chip8) => block,
+                _ => {
+                    let block = translate(chip8)?;
+                    // unreachable pc values past memory end fail in `translate`
+                    self.blocks[pc] = Some(block);
+                    self.blocks[pc].as_ref().unwrap()
+                }
+            };
+
+            let (ran, result) = run_block(chip8, block, cycles - executed);
+            executed += ran;
+            result?;
+        }
+
+        Ok(executed)
+    }
+
+    /// Same as `Chip8::run_frame`: `cycles` instructions followed by a timer tick.
+    pub fn run_frame(&mut self, chip8: &mut Chip8, cycles: u32) -> Result<(), ChipError> {
+        self.run(chip8, cycles as u64)?;
+        chip8.timers.tick();
+
+        Ok(())
+    }
+}
+
+/// Executes up to `limit` instructions of `block`, returns how many ran.
+fn run_block(chip8: &mut Chip8, block: &Block, limit: u64) -> (u64, Result<(), ChipError>) {
+    chip8.sync_audio();
+    // instructions already handed to the audio
+    let (mut executed, mut counted) = (0, 0);
+    let mut result = Ok(());
+
+    for &instruction in block.instructions.iter().take(limit as usize) {
+        if let Err(e) = chip8.execute_op(instruction) {
+            result = Err(e);
+            break;
+        }
+        executed += 1;
+
+        if matches!(instruction, Instruction::SetSoundTimer(_)) {
+            chip8.audio.instructions(executed - 1 - counted);
+            chip8.audio.instruction(chip8.buzzer_on());
+            counted = executed;
+        }
+
+        let writes_memory = matches!(
+            instruction,
+            Instruction::LoadBcd(_) | Instruction::StoreRegisters(_)
+        );
+        if writes_memory && block.is_stale(chip8) {
+            break;
+        }
+    }
+    chip8.audio.instructions(executed - counted);
+
+    (executed, result)
+}
+
+/// Decodes the block starting at the current program counter.
+fn translate(chip8: &Chip8) -> Result<Block, ChipError> {
+    let start = chip8.cpu.pc.get() as usize;
+    let mut instructions = Vec::new();
+    let mut address = start;
+
+    while instructions.len() < MAX_BLOCK_LEN {
+        let word = match chip8.fetch_at(address) {
+            Ok(word) => word,
+            Err(e) if instructions.is_empty() => return Err(ChipError::MemoryError(e)),
+            Err(_) => break,
+        };
+        let instruction = match Instruction::decode(word) {
+            Some(instruction) => instruction,
+            None if instructions.is_empty() => return Err(ChipError::UnknownInstruction(word)),
+            None => break,
+        };
+
+        instructions.push(instruction);
+        address += 2;
+        if ends_block(instruction) {
+            break;
+        }
+    }
+
+    let pages = (start / PAGE_SIZE..=(address + 1) / PAGE_SIZE)
+        .map(|page| page * PAGE_SIZE)
+        .filter(|&page| page < config::MEMORY_SIZE)
+        .map(|page| (page, chip8.memory.page_generation(page)))
+        .collect();
+
+    Ok(Block {
+        instructions,
+        pages,
+    })
+}
+
+/// Returns true for instructions after which the next pc is not `pc + 2`,
+/// or which touch state the caller may want to observe between instructions.
+fn ends_block(instruction: Instruction) -> bool {
+    instruction.is_skip()
+        || matches!(
+            instruction,
+            Instruction::Jump(_)
+                | Instruction::JumpV0(_)
+                | Instruction::Call(_)
+                | Instruction::Return
+                | Instruction::Draw(..)
+                | Instruction::WaitForKey(_)
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    /// Random instruction which cannot panic.
+    fn random_instruction(rng: &mut StdRng) -> u16 {
+        let x = rng.random_range(0..16u16) << 8;
+        let y = rng.random_range(0..16u16) << 4;
+        let kk = rng.random_range(0..=0xffu16);
+        let addr = 0x200 + rng.random_range(0..0x80u16) * 2;
+        // stores land past the program, rewriting code with arbitrary
+        // register values could produce calls and random draws
+        let data = 0x300 + rng.random_range(0..0x80u16);
+
+        match rng.random_range(0..23) {
+            0 => 0x00e0,
+            1 => 0x1000 | addr,
+            2 => 0x3000 | x | kk,
+            3 => 0x4000 | x | kk,
+            4 => 0x5000 | x | y,
+            5 => 0x6000 | x | kk,
+            6 => 0x7000 | x | kk,
+            7 => 0x8000 | x | y | [0, 1, 2, 3, 4, 5, 6, 7, 0xe][rng.random_range(0..9)],
+            8 => 0x9000 | x | y,
+            9 => 0xa000 | data,
+            10 => 0xb000 | (addr & 0x2ff),
+            11 => 0xd000 | x | y | rng.random_range(0..16u16),
+            12 => 0xe09e | x,
+            13 => 0xe0a1 | x,
+            14 => 0xf007 | x,
+            15 => 0xf00a | x,
+            16 => 0xf015 | x,
+            17 => 0xf01e | x,
+            18 => 0xf029 | (x & 0x0f00),
+            19 => 0xf033 | x,
+            20 => 0xf055 | x,
+            21 => 0xc000 | x | kk,
+            _ => 0xf065 | x,
+        }
+    }
+
+    fn random_rom(rng: &mut StdRng) -> Vec<u8> {
+        (0..0x80)
+            .flat_map(|_| random_instruction(rng).to_be_bytes())
+            .collect()
+    }
+
+    fn assert_same_state(expected: &Chip8, actual: &Chip8) {
+        assert_eq!(expected.cpu.pc.get(), actual.cpu.pc.get());
+        assert_eq!(expected.cpu.i.get(), actual.cpu.i.get());
+        for idx in 0..config::REGISTER_COUNT as u8 {
+            assert_eq!(expected.cpu.v.get(idx), actual.cpu.v.get(idx));
+        }
+        for address in 0..config::MEMORY_SIZE {
+            assert_eq!(
+                expected.memory.get(address).ok(),
+                actual.memory.get(address).ok()
+            );
+        }
+        assert_eq!(expected.display.dump(), actual.display.dump());
+    }
+
+    #[test]
+    fn test_recompiler_matches_interpreter() {
+        let mut rng = StdRng::seed_from_u64(0xc8);
+
+        for _ in 0..200 {
+            let rom = random_rom(&mut rng);
+            let seed = rng.random();
+            let mut interpreter = Chip8::with_seed(seed);
+            let mut recompiled = Chip8::with_seed(seed);
+            interpreter.load_rom(&rom).unwrap();
+            recompiled.load_rom(&rom).unwrap();
+            let key = rng.random_range(0..16);
+            interpreter.keyboard.set_key(key, true);
+            recompiled.keyboard.set_key(key, true);
+
+            let mut recompiler = Recompiler::new();
+            for _ in 0..20 {
+                let cycles = rng.random_range(1..50);
+
+                let mut expected = Ok(());
+                for _ in 0..cycles {
+                    expected = interpreter.tick();
+                    if expected.is_err() {
+                        break;
+                    }
+                }
+                let actual = recompiler.run(&mut recompiled, cycles);
+
+                assert_eq!(
+                    expected.as_ref().err().map(|e| e.to_string()),
+                    actual.as_ref().err().map(|e| e.to_string())
+                );
+                assert_same_state(&interpreter, &recompiled);
+                if expected.is_err() {
+                    break;
+                }
+
+                interpreter.timers.tick();
+                recompiled.timers.tick();
+            }
+        }
+    }
+    #[test]
+    fn test_recompiler_audio_matches_interpreter() {
+        // 0x200: LD V0, 5
+        // 0x202: LD V1, 0 (x4)
+        // 0x20A: LD ST, V0
+        // 0x20C: JP 0x20C
+        let rom = [
+            0x60, 0x05, 0x61, 0x00, 0x61, 0x00, 0x61, 0x00, 0x61, 0x00, 0xf0, 0x18, 0x12, 0x0c,
+        ];
+        let mut interpreter = Chip8::new();
+        let mut recompiled = Chip8::new();
+        interpreter.load_rom(&rom).unwrap();
+        recompiled.load_rom(&rom).unwrap();
+
+        let mut recompiler = Recompiler::new();
+        for _ in 0..6 {
+            interpreter.run_frame(10).unwrap();
+            recompiler.run(&mut recompiled, 10).unwrap();
+            recompiled.timers.tick();
+        }
+
+        let (mut expected, mut actual) = ([false; 60], [false; 60]);
+        assert_eq!(60, interpreter.fill_buzzer(&mut expected, 600));
+        assert_eq!(60, recompiled.fill_buzzer(&mut actual, 600));
+        assert_eq!(expected, actual);
+        // the beep starts at the sixth instruction, not at the first tick
+        assert!(!actual[4] && actual[5]);
+    }
+    #[test]
+    fn test_recompiler_self_modifying_code() {
+        // 0x200: LD V0, 0x12
+        // 0x202: LD V1, 0x08
+        // 0x204: LD I, 0x208
+        // 0x206: LD [I], V1    rewrites the CLS below with JP 0x208
+        // 0x208: CLS
+        // 0x20A: JP 0x20A
+        let rom = [
+            0x60, 0x12, 0x61, 0x08, 0xa2, 0x08, 0xf1, 0x55, 0x00, 0xe0, 0x12, 0x0a,
+        ];
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&rom).unwrap();
+        let mut recompiler = Recompiler::new();
+
+        assert_eq!(10, recompiler.run(&mut chip8, 10).unwrap());
+        assert_eq!(0x208, chip8.cpu.pc.get());
+        assert_eq!(0x20a, chip8.cpu.i.get());
+    }
+    #[test]
+    fn test_recompiler_invalidates_on_rom_load() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0x60, 0x01, 0x12, 0x00]).unwrap();
+        let mut recompiler = Recompiler::new();
+        recompiler.run(&mut chip8, 2).unwrap();
+        assert_eq!(1, chip8.cpu.v.get(0));
+
+        chip8.load_rom(&[0x60, 0x02, 0x12, 0x00]).unwrap();
+        recompiler.run(&mut chip8, 2).unwrap();
+        assert_eq!(2, chip8.cpu.v.get(0));
+    }
+    #[test]
+    fn test_recompiler_unknown_instruction() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0x60, 0x01, 0xff, 0xff]).unwrap();
+        let mut recompiler = Recompiler::new();
+
+        assert!(matches!(
+            recompiler.run(&mut chip8, 2),
+            Err(ChipError::UnknownInstruction(0xffff))
+        ));
+        assert_eq!(0x202, chip8.cpu.pc.get());
+    }
+}
@@ -1,10 +1,21 @@
 use crate::config;
+use thiserror::Error;
 
+#[derive(Clone)]
 pub struct Stack {
     data: [u16; config::STACK_SIZE],
     sp: usize, // stack pointer
 }
 
+#[derive(Debug, Error)]
+pub enum StackError {
+    #[error("stack overflow: tried to push {0:04X} with a full stack")]
+    Overflow(u16),
+
+    #[error("stack underflow: return with an empty stack")]
+    Underflow,
+}
+
 impl Stack {
     pub fn new() -> Self {
         Stack {
@@ -13,23 +24,41 @@ impl Stack {
         }
     }
 
-    pub fn push(&mut self, value: u16) {
+    pub fn push(&mut self, value: u16) -> Result<(), StackError> {
         if self.sp >= config::STACK_SIZE {
-            panic!(
-                "Stack overflow: tried to push {:04X} with SP at {}",
-                value, self.sp
-            );
+            return Err(StackError::Overflow(value));
         }
         self.data[self.sp] = value;
         self.sp += 1;
+
+        Ok(())
+    }
+
+    /// Returns pushed return addresses, oldest first.
+    pub fn frames(&self) -> &[u16] {
+        &self.data[..self.sp]
+    }
+
+    /// Replaces stack contents with the given return addresses.
+    pub fn restore(&mut self, frames: &[u16]) {
+        if frames.len() > config::STACK_SIZE {
+            panic!(
+                "Stack overflow: tried to restore {} frames into a stack of {}",
+                frames.len(),
+                config::STACK_SIZE
+            );
+        }
+        self.data = [0; config::STACK_SIZE];
+        self.data[..frames.len()].copy_from_slice(frames);
+        self.sp = frames.len();
     }
 
-    pub fn pop(&mut self) -> u16 {
+    pub fn pop(&mut self) -> Result<u16, StackError> {
         if self.sp == 0 {
-            panic!("Stack underflow: attempted pop with SP at 0");
+            return Err(StackError::Underflow);
         }
         self.sp -= 1;
-        self.data[self.sp]
+        Ok(self.data[self.sp])
     }
 }
 
@@ -40,31 +69,31 @@ mod tests {
     #[test]
     fn test_stack_push() {
         let mut s = Stack::new();
-        s.push(5);
+        s.push(5).unwrap();
         assert_eq!(1, s.sp);
         assert_eq!(5, s.data[0]);
     }
     #[test]
     fn test_stack_pop() {
         let mut s = Stack::new();
-        s.push(1);
-        s.push(2);
-        let value = s.pop();
+        s.push(1).unwrap();
+        s.push(2).unwrap();
+        let value = s.pop().unwrap();
         assert_eq!(1, s.sp);
         assert_eq!(2, value);
     }
     #[test]
-    #[should_panic]
     fn test_stack_push_overflow() {
         let mut s = Stack::new();
         s.sp = config::STACK_SIZE;
-        s.push(1);
+        assert!(matches!(s.push(1), Err(StackError::Overflow(1))));
+        assert_eq!(config::STACK_SIZE, s.sp);
     }
     #[test]
-    #[should_panic]
     fn test_stack_pop_underflow() {
         let mut s = Stack::new();
         s.sp = 0;
-        s.pop();
+        assert!(matches!(s.pop(), Err(StackError::Underflow)));
+        assert_eq!(0, s.sp);
     }
 }
@@ -0,0 +1,256 @@
+//! Save states.
+//!
+//! A state is a fixed-size blob holding everything needed to resume
+//! the machine, including the position in the random number sequence
+//! and the buzzer audio not played yet.
+
+use rand_chacha::ChaCha8Rng;
+use rand_chacha::rand_core::SeedableRng;
+use thiserror::Error;
+
+use crate::{Chip8, audio::Audio, config, timers::Timer};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 2;
+const DISPLAY_SIZE: usize = config::DISPLAY_WIDTH * config::DISPLAY_HEIGHT;
+
+/// Size of every save state in bytes.
+pub const STATE_SIZE: usize = MAGIC.len()
+    + 1 // version
+    + config::REGISTER_COUNT
+    + 2 // I
+    + 2 // PC
+    + 1 // SP
+    + config::STACK_SIZE * 2
+    + config::MEMORY_SIZE
+    + DISPLAY_SIZE
+    + config::KEY_COUNT as usize
+    + 2 // timers
+    + 8 // timer ticks
+    + 32 // RNG seed
+    + 8 // RNG stream
+    + 16 // RNG word position
+    + Audio::STATE_SIZE;
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("not a save state")]
+    InvalidHeader,
+
+    #[error("unsupported save state version: {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("save state has wrong size: {0} (expected {STATE_SIZE})")]
+    InvalidLength(usize),
+
+    #[error("save state stack pointer out of range: {0}")]
+    InvalidStackPointer(u8),
+
+    #[error("save state has invalid audio")]
+    InvalidAudio,
+}
+
+impl Chip8 {
+    /// Serializes the whole machine.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(STATE_SIZE);
+
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend((0..config::REGISTER_COUNT as u8).map(|idx| self.cpu.v.get(idx)));
+        out.extend_from_slice(&self.cpu.i.get().to_be_bytes());
+        out.extend_from_slice(&self.cpu.pc.get().to_be_bytes());
+
+        let frames = self.stack.frames();
+        out.push(frames.len() as u8);
+        for idx in 0..config::STACK_SIZE {
+            let frame = frames.get(idx).copied().unwrap_or(0);
+            out.extend_from_slice(&frame.to_be_bytes());
+        }
+
+        out.extend_from_slice(self.memory.dump());
+        out.extend(self.display.dump().iter().map(|&on| on as u8));
+        out.extend(self.keyboard.dump().iter().map(|&pressed| pressed as u8));
+        out.push(self.timers.get(Timer::Delay));
+        out.push(self.timers.get(Timer::Sound));
+        out.extend_from_slice(&self.timers.ticks().to_be_bytes());
+
+        out.extend_from_slice(&self.rng.get_seed());
+        out.extend_from_slice(&self.rng.get_stream().to_be_bytes());
+        out.extend_from_slice(&self.rng.get_word_pos().to_be_bytes());
+        self.audio.save(&mut out);
+
+        out
+    }
+
+    /// Restores machine saved with `save_state`, leaves it untouched on error.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(StateError::InvalidHeader);
+        }
+        if bytes[MAGIC.len()] != VERSION {
+            return Err(StateError::UnsupportedVersion(bytes[MAGIC.len()]));
+        }
+        if bytes.len() != STATE_SIZE {
+            return Err(StateError::InvalidLength(bytes.len()));
+        }
+
+        let mut reader = Reader {
+            bytes,
+            offset: MAGIC.len() + 1,
+        };
+        let registers = reader.take(config::REGISTER_COUNT);
+        let i = reader.u16();
+        let pc = reader.u16();
+        let sp = reader.u8();
+        if sp as usize > config::STACK_SIZE {
+            return Err(StateError::InvalidStackPointer(sp));
+        }
+        let frames: Vec<u16> = (0..config::STACK_SIZE).map(|_| reader.u16()).collect();
+        let memory = reader.take(config::MEMORY_SIZE);
+        let display: Vec<bool> = reader.take(DISPLAY_SIZE).iter().map(|&b| b != 0).collect();
+        let keys = reader.take(config::KEY_COUNT as usize);
+        let delay = reader.u8();
+        let sound = reader.u8();
+        let ticks = u64::from_be_bytes(reader.array());
+        let seed: [u8; 32] = reader.array();
+        let stream = u64::from_be_bytes(reader.array());
+        let word_pos = u128::from_be_bytes(reader.array());
+        let audio = Audio::load(&mut reader).ok_or(StateError::InvalidAudio)?;
+
+        for (idx, &value) in registers.iter().enumerate() {
+            self.cpu.v.set(idx as u8, value);
+        }
+        self.cpu.i.set(i);
+        self.cpu.pc.set(pc);
+        self.stack.restore(&frames[..sp as usize]);
+        self.memory.restore(memory);
+        self.display.restore(&display);
+        for (key, &pressed) in keys.iter().enumerate() {
+            self.keyboard.set_key(key as u8, pressed != 0);
+        }
+        self.timers.set(Timer::Delay, delay);
+        self.timers.set(Timer::Sound, sound);
+        self.timers.set_ticks(ticks);
+        self.audio = audio;
+
+        self.rng = ChaCha8Rng::from_seed(seed);
+        self.rng.set_stream(stream);
+        self.rng.set_word_pos(word_pos);
+
+        Ok(())
+    }
+}
+
+/// Reads fields in order, the length is checked up front.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> &'a [u8] {
+        let slice = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        slice
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> [u8; N] {
+        self.take(N).try_into().unwrap()
+    }
+
+    pub(crate) fn u8(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_be_bytes(self.array())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: CALL 0x206
+    // 0x202: JP 0x202
+    // 0x204: (unused)
+    // 0x206: RND V0, 0x0F
+    // 0x208: LD F, V0
+    // 0x20A: DRW V1, V1, 5
+    // 0x20C: JP 0x206
+    const ROM: [u8; 14] = [
+        0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0xc0, 0x0f, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x06,
+    ];
+
+    #[test]
+    fn test_state_roundtrip() {
+        let mut chip8 = Chip8::with_seed(3);
+        chip8.load_rom(&ROM).unwrap();
+        chip8.keyboard.set_key(5, true);
+        chip8.timers.set(Timer::Sound, 9);
+        chip8.run_frame(10).unwrap();
+
+        let state = chip8.save_state();
+        assert_eq!(STATE_SIZE, state.len());
+
+        let mut restored = Chip8::with_seed(99);
+        restored.load_state(&state).unwrap();
+        assert_eq!(state, restored.save_state());
+
+        chip8.run_frame(20).unwrap();
+        restored.run_frame(20).unwrap();
+        assert_eq!(chip8.save_state(), restored.save_state());
+    }
+    #[test]
+    fn test_state_keeps_unplayed_audio() {
+        let mut chip8 = Chip8::with_seed(3);
+        chip8.load_rom(&ROM).unwrap();
+        chip8.timers.set(Timer::Sound, 2);
+        for _ in 0..3 {
+            chip8.run_frame(10).unwrap();
+        }
+        // half of the first tick is played before saving
+        let mut played = [false; 5];
+        assert_eq!(5, chip8.fill_buzzer(&mut played, 600));
+
+        let mut restored = Chip8::new();
+        restored.load_state(&chip8.save_state()).unwrap();
+        assert_eq!(3, restored.timers.ticks());
+
+        let (mut expected, mut actual) = ([false; 30], [false; 30]);
+        assert_eq!(25, chip8.fill_buzzer(&mut expected, 600));
+        assert_eq!(25, restored.fill_buzzer(&mut actual, 600));
+        assert_eq!(expected, actual);
+        assert!(actual[..15].iter().all(|on| *on) && !actual[15]);
+    }
+    #[test]
+    fn test_state_rejects_garbage() {
+        let mut chip8 = Chip8::new();
+        assert!(matches!(
+            chip8.load_state(b"nope"),
+            Err(StateError::InvalidHeader)
+        ));
+
+        let mut state = chip8.save_state();
+        state[4] = 7;
+        assert!(matches!(
+            chip8.load_state(&state),
+            Err(StateError::UnsupportedVersion(7))
+        ));
+
+        let state = chip8.save_state();
+        assert!(matches!(
+            chip8.load_state(&state[..100]),
+            Err(StateError::InvalidLength(100))
+        ));
+
+        // change count of the current tick
+        let mut state = chip8.save_state();
+        state[STATE_SIZE - Audio::STATE_SIZE + 42] = 200;
+        assert!(matches!(
+            chip8.load_state(&state),
+            Err(StateError::InvalidAudio)
+        ));
+    }
+}
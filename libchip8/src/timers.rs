@@ -1,16 +1,29 @@
+#[derive(Clone, Copy)]
 pub enum Timer {
     Delay,
     Sound,
 }
 
+#[derive(Clone)]
 pub struct Timers {
     delay: u8,
     sound: u8,
+    ticks: u64,
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Timers {
     pub fn new() -> Self {
-        Self { delay: 0, sound: 0 }
+        Self {
+            delay: 0,
+            sound: 0,
+            ticks: 0,
+        }
     }
 
     pub fn get(&self, t: Timer) -> u8 {
@@ -27,7 +40,17 @@ impl Timers {
         };
     }
 
+    /// Ticks since the machine started.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub(crate) fn set_ticks(&mut self, ticks: u64) {
+        self.ticks = ticks;
+    }
+
     pub fn tick(&mut self) {
+        self.ticks += 1;
         if self.sound > 0 {
             self.sound -= 1;
         }